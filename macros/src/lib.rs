@@ -0,0 +1,132 @@
+//! Procedural macros for `wasm-cache`.
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, parse_quote, spanned::Spanned, Error, Fields, Generics, Ident, ItemStruct,
+    Member,
+};
+
+/// Make a newtype usable as a cache key, delegating to its inner type.
+///
+/// Used as `#[cache_key(transparent)]` on a struct with a single field. This implements `Clone`,
+/// `Debug`, `PartialEq`, `Eq`, `PartialOrd` and `Ord` by delegating to the inner field, so that
+/// together with an `Invalidatable` implementation the struct is a `CacheKey`. Keys keep the type
+/// of the newtype, so two newtypes wrapping the same inner type are never equal, and it is
+/// included in the `Debug` output. Type parameters are required to implement the same traits.
+#[proc_macro_attribute]
+pub fn cache_key(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mode = parse_macro_input!(attr as Ident);
+    let item = parse_macro_input!(item as ItemStruct);
+    let result = if mode == "transparent" {
+        transparent(&item)
+    } else {
+        Err(Error::new(mode.span(), "expected `transparent`"))
+    };
+    match result {
+        Ok(impls) => quote!(#item #impls).into(),
+        Err(error) => {
+            let error = error.to_compile_error();
+            quote!(#item #error).into()
+        }
+    }
+}
+
+/// Generate the delegating implementations for a newtype.
+fn transparent(item: &ItemStruct) -> Result<TokenStream2, Error> {
+    let field = match &item.fields {
+        Fields::Named(fields) if fields.named.len() == 1 => {
+            Member::Named(fields.named[0].ident.clone().unwrap())
+        }
+        Fields::Unnamed(fields) if fields.unnamed.len() == 1 => Member::Unnamed(0.into()),
+        fields => {
+            return Err(Error::new(
+                fields.span(),
+                "`cache_key(transparent)` requires a struct with exactly one field",
+            ))
+        }
+    };
+    let name = &item.ident;
+    let type_generics = item.generics.split_for_impl().1;
+    // the partial ordering delegates to the total one
+    let [clone, debug, partial_eq, eq, ord] = [
+        quote!(::std::clone::Clone),
+        quote!(::std::fmt::Debug),
+        quote!(::std::cmp::PartialEq),
+        quote!(::std::cmp::Eq),
+        quote!(::std::cmp::Ord),
+    ]
+    .map(|bound| bounded(&item.generics, &bound));
+    let construct = match &field {
+        Member::Named(field) => quote!(Self { #field: ::std::clone::Clone::clone(&self.#field) }),
+        Member::Unnamed(_) => quote!(Self(::std::clone::Clone::clone(&self.0))),
+    };
+    let debug_fmt = match &field {
+        Member::Named(field) => {
+            let (name, field_name) = (name.to_string(), field.to_string());
+            quote!(f.debug_struct(#name).field(#field_name, &self.#field).finish())
+        }
+        Member::Unnamed(_) => {
+            let name = name.to_string();
+            quote!(f.debug_tuple(#name).field(&self.0).finish())
+        }
+    };
+    let impl_for = |generics: &Generics, tr: TokenStream2| {
+        let (impl_generics, _, where_clause) = generics.split_for_impl();
+        quote!(impl #impl_generics #tr for #name #type_generics #where_clause)
+    };
+    let clone_impl = impl_for(&clone, quote!(::std::clone::Clone));
+    let debug_impl = impl_for(&debug, quote!(::std::fmt::Debug));
+    let partial_eq_impl = impl_for(&partial_eq, quote!(::std::cmp::PartialEq));
+    let eq_impl = impl_for(&eq, quote!(::std::cmp::Eq));
+    let partial_ord_impl = impl_for(&ord, quote!(::std::cmp::PartialOrd));
+    let ord_impl = impl_for(&ord, quote!(::std::cmp::Ord));
+
+    Ok(quote! {
+        #clone_impl {
+            fn clone(&self) -> Self {
+                #construct
+            }
+        }
+
+        #debug_impl {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                #debug_fmt
+            }
+        }
+
+        #partial_eq_impl {
+            fn eq(&self, other: &Self) -> bool {
+                ::std::cmp::PartialEq::eq(&self.#field, &other.#field)
+            }
+        }
+
+        #eq_impl {}
+
+        #partial_ord_impl {
+            fn partial_cmp(&self, other: &Self) -> ::std::option::Option<::std::cmp::Ordering> {
+                ::std::option::Option::Some(::std::cmp::Ord::cmp(self, other))
+            }
+        }
+
+        #ord_impl {
+            fn cmp(&self, other: &Self) -> ::std::cmp::Ordering {
+                ::std::cmp::Ord::cmp(&self.#field, &other.#field)
+            }
+        }
+    })
+}
+
+/// Copy of the generics of the newtype, with the trait as a bound on every type parameter.
+fn bounded(generics: &Generics, bound: &TokenStream2) -> Generics {
+    let mut generics = generics.clone();
+    let params: Vec<_> = generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect();
+    let where_clause = generics.make_where_clause();
+    for param in params {
+        where_clause.predicates.push(parse_quote!(#param: #bound));
+    }
+    generics
+}
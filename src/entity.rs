@@ -0,0 +1,168 @@
+//! Invalidation by the entities which queries touch and mutations affect.
+//!
+//! This suits GraphQL APIs, where many queries return the same objects. Every query declares the
+//! entities it touches with [`EntityQuery`], and every mutation lists the entities it affects in
+//! an [`EntityMutation`]. A query is invalidated if any of these overlap, rather than having to
+//! match every mutation by hand.
+//!
+//! ```rust
+//! use wasm_cache::{Entity, EntityMutation, EntityQuery, Invalidatable};
+//!
+//! #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+//! struct UserWithPosts(u64);
+//!
+//! impl EntityQuery for UserWithPosts {
+//!     fn entities(&self) -> Vec<Entity> {
+//!         vec![Entity::new("User", self.0), Entity::all("Post")]
+//!     }
+//! }
+//!
+//! impl Invalidatable<EntityMutation> for UserWithPosts {
+//!     fn invalidated_by(&self, mutation: &EntityMutation) -> bool {
+//!         self.touched_by(mutation)
+//!     }
+//! }
+//!
+//! let query = UserWithPosts(1);
+//! assert!(query.invalidated_by(&EntityMutation::new([Entity::new("Post", 7)])));
+//! assert!(!query.invalidated_by(&EntityMutation::new([Entity::new("User", 2)])));
+//! ```
+
+use crate::Invalidator;
+
+/// Entity touched by a query or affected by a mutation, such as a GraphQL object.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Entity {
+    /// Type of the entity, such as the GraphQL `__typename`.
+    pub kind: String,
+    /// Identifier of the entity, or `None` for every entity of this type.
+    pub id: Option<String>,
+}
+
+impl Entity {
+    /// Entity of this type with this identifier.
+    pub fn new(kind: impl Into<String>, id: impl ToString) -> Self {
+        Self {
+            kind: kind.into(),
+            id: Some(id.to_string()),
+        }
+    }
+
+    /// Every entity of this type, for example for list queries or for creating entities.
+    pub fn all(kind: impl Into<String>) -> Self {
+        Self {
+            kind: kind.into(),
+            id: None,
+        }
+    }
+
+    /// Determine if these entities overlap.
+    ///
+    /// They overlap if they have the same type, and either the same identifier or at least one of
+    /// them covers every entity of the type.
+    pub fn overlaps(&self, other: &Entity) -> bool {
+        self.kind == other.kind
+            && match (&self.id, &other.id) {
+                (Some(id), Some(other)) => id == other,
+                _ => true,
+            }
+    }
+}
+
+/// Mutation affecting a set of entities.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EntityMutation {
+    /// Entities affected by this mutation.
+    pub entities: Vec<Entity>,
+}
+
+impl EntityMutation {
+    /// Mutation affecting these entities.
+    pub fn new(entities: impl IntoIterator<Item = Entity>) -> Self {
+        Self {
+            entities: entities.into_iter().collect(),
+        }
+    }
+
+    /// Determine if this mutation affects any of these entities.
+    pub fn affects(&self, entities: &[Entity]) -> bool {
+        self.entities
+            .iter()
+            .any(|affected| entities.iter().any(|entity| affected.overlaps(entity)))
+    }
+}
+
+impl Invalidator<EntityMutation> for EntityMutation {
+    fn mutations(&self) -> Vec<EntityMutation> {
+        vec![self.clone()]
+    }
+}
+
+/// Query which declares the entities it touches.
+///
+/// To invalidate the query by these, implement
+/// [`Invalidatable<EntityMutation>`](crate::Invalidatable) using [`EntityQuery::touched_by`].
+pub trait EntityQuery {
+    /// Entities which the result of this query contains.
+    fn entities(&self) -> Vec<Entity>;
+
+    /// Determine if this mutation affects any of the entities of this query.
+    fn touched_by(&self, mutation: &EntityMutation) -> bool {
+        mutation.affects(&self.entities())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CacheKey, Invalidatable};
+
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    enum Query {
+        User(u64),
+        Users,
+    }
+
+    impl EntityQuery for Query {
+        fn entities(&self) -> Vec<Entity> {
+            match self {
+                Query::User(id) => vec![Entity::new("User", id)],
+                Query::Users => vec![Entity::all("User")],
+            }
+        }
+    }
+
+    impl Invalidatable<EntityMutation> for Query {
+        fn invalidated_by(&self, mutation: &EntityMutation) -> bool {
+            self.touched_by(mutation)
+        }
+    }
+
+    #[test]
+    fn entities_overlap() {
+        assert!(Entity::new("User", 1).overlaps(&Entity::new("User", "1")));
+        assert!(!Entity::new("User", 1).overlaps(&Entity::new("User", 2)));
+        assert!(Entity::new("User", 1).overlaps(&Entity::all("User")));
+        assert!(Entity::all("User").overlaps(&Entity::new("User", 1)));
+        assert!(!Entity::all("User").overlaps(&Entity::all("Post")));
+    }
+
+    #[test]
+    fn mutation_invalidates_overlapping_queries() {
+        let keys: Vec<Box<dyn CacheKey<EntityMutation>>> = vec![
+            Box::new(Query::User(1)),
+            Box::new(Query::User(2)),
+            Box::new(Query::Users),
+        ];
+        let rename = EntityMutation::new([Entity::new("User", 1)]);
+        let invalidated = rename.invalidates(keys.iter());
+        assert_eq!(invalidated.len(), 2);
+        assert!(invalidated[0].any_eq(&Query::User(1)));
+        assert!(invalidated[1].any_eq(&Query::Users));
+
+        let create = EntityMutation::new([Entity::all("User")]);
+        assert_eq!(create.invalidates(keys.iter()).len(), 3);
+        let unrelated = EntityMutation::new([Entity::new("Post", 1)]);
+        assert!(unrelated.invalidates(keys.iter()).is_empty());
+    }
+}
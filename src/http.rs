@@ -0,0 +1,250 @@
+//! Cache items for JSON endpoints, using [`gloo_net`].
+//!
+//! Implement [`JsonGet`] for a type and wrap it in [`Json`] to get a [`CacheItem`] which fetches
+//! its value from a URL and deserializes the JSON response. Items which need more control, such
+//! as custom headers or [cache hints](CacheItem::cache_control), can implement [`CacheItem`]
+//! themselves and call [`get_json`] from their [`send`](CacheItem::send). For quick prototyping,
+//! [`HttpGetRequest`] caches the response of a URL without defining a key type.
+
+use crate::{CacheItem, CacheKey, Invalidatable};
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use std::{
+    cmp::Ordering,
+    error::Error,
+    fmt::{Debug, Display, Formatter, Result as FmtResult},
+    marker::PhantomData,
+};
+
+/// Error of a JSON request, see [`get_json`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JsonError {
+    /// The request could not be sent, such as when the network is unavailable.
+    Request(String),
+    /// The server responded with a status code which does not indicate success.
+    Status(u16),
+    /// The response is not valid JSON of the expected type.
+    Parse(String),
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Request(error) => write!(f, "error sending request: {error}"),
+            Self::Status(status) => write!(f, "request failed with status {status}"),
+            Self::Parse(error) => write!(f, "error parsing response: {error}"),
+        }
+    }
+}
+
+impl Error for JsonError {}
+
+/// Key of a JSON endpoint whose value is fetched with a `GET` request.
+///
+/// Wrapped in [`Json`], this is a [`CacheItem`] with [`JsonError`] as its error type. Mutations
+/// invalidate it by its own [`Invalidatable`] implementation.
+///
+/// ```rust,no_run
+/// use wasm_cache::{Invalidatable, Json, JsonGet};
+///
+/// #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// pub struct GroupMembers(pub u64);
+///
+/// impl Invalidatable<()> for GroupMembers {}
+///
+/// impl JsonGet for GroupMembers {
+///     type Value = Vec<String>;
+///
+///     fn url(&self) -> String {
+///         format!("/api/groups/{}/members", self.0)
+///     }
+/// }
+///
+/// let item = Json(GroupMembers(1));
+/// ```
+pub trait JsonGet<M = ()>: CacheKey<M> + Clone + Ord {
+    type Value: DeserializeOwned + Clone + Debug + PartialEq + 'static;
+
+    /// URL to fetch the value from.
+    fn url(&self) -> String;
+}
+
+/// Cache item fetching the value of a [`JsonGet`] key.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Json<T>(pub T);
+
+impl<M, T: Invalidatable<M>> Invalidatable<M> for Json<T> {
+    fn invalidated_by(&self, mutation: &M) -> bool {
+        self.0.invalidated_by(mutation)
+    }
+}
+
+#[async_trait(?Send)]
+impl<M: 'static, T: JsonGet<M>> CacheItem<M> for Json<T> {
+    type Value = T::Value;
+    type Error = JsonError;
+
+    async fn send(&self) -> Result<Self::Value, Self::Error> {
+        get_json(&self.0.url()).await
+    }
+}
+
+/// Cache item for a `GET` request of a URL, whose JSON response is deserialized into `T`.
+///
+/// The key is the URL. Every mutation invalidates it, since there is no way to tell which ones
+/// affect it. Define a key type implementing [`JsonGet`] for finer control.
+pub struct HttpGetRequest<T> {
+    /// URL to fetch.
+    pub url: String,
+    value: PhantomData<fn() -> T>,
+}
+
+impl<T> HttpGetRequest<T> {
+    /// Request of this URL.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            value: PhantomData,
+        }
+    }
+}
+
+impl<T> Clone for HttpGetRequest<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.url.clone())
+    }
+}
+
+impl<T> Debug for HttpGetRequest<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_tuple("HttpGetRequest").field(&self.url).finish()
+    }
+}
+
+impl<T> PartialEq for HttpGetRequest<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.url == other.url
+    }
+}
+
+impl<T> Eq for HttpGetRequest<T> {}
+
+impl<T> PartialOrd for HttpGetRequest<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for HttpGetRequest<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.url.cmp(&other.url)
+    }
+}
+
+impl<M, T> Invalidatable<M> for HttpGetRequest<T> {}
+
+#[async_trait(?Send)]
+impl<M: 'static, T> CacheItem<M> for HttpGetRequest<T>
+where
+    T: DeserializeOwned + Clone + Debug + PartialEq + 'static,
+{
+    type Value = T;
+    type Error = JsonError;
+
+    async fn send(&self) -> Result<Self::Value, Self::Error> {
+        get_json(&self.url).await
+    }
+}
+
+/// Fetch this URL with a `GET` request and deserialize the JSON response.
+pub async fn get_json<T: DeserializeOwned>(url: &str) -> Result<T, JsonError> {
+    let response = gloo_net::http::Request::get(url)
+        .send()
+        .await
+        .map_err(|error| JsonError::Request(error.to_string()))?;
+    check_status(response.status())?;
+    let text = response
+        .text()
+        .await
+        .map_err(|error| JsonError::Request(error.to_string()))?;
+    parse(&text)
+}
+
+/// Check that this status code indicates success.
+fn check_status(status: u16) -> Result<(), JsonError> {
+    match status {
+        200..=299 => Ok(()),
+        status => Err(JsonError::Status(status)),
+    }
+}
+
+/// Deserialize the body of a response.
+fn parse<T: DeserializeOwned>(text: &str) -> Result<T, JsonError> {
+    serde_json::from_str(text).map_err(|error| JsonError::Parse(error.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct User(u64);
+
+    impl Invalidatable<u64> for User {
+        fn invalidated_by(&self, user: &u64) -> bool {
+            self.0 == *user
+        }
+    }
+
+    impl JsonGet<u64> for User {
+        type Value = String;
+
+        fn url(&self) -> String {
+            format!("/users/{}", self.0)
+        }
+    }
+
+    #[test]
+    fn json_delegates_invalidation() {
+        let item = Json(User(1));
+        assert!(item.invalidated_by(&1));
+        assert!(!item.invalidated_by(&2));
+        assert_eq!(item.0.url(), "/users/1");
+        assert_eq!(item.transform_response("user".into()), "user");
+    }
+
+    #[test]
+    fn http_get_request_keyed_by_url() {
+        let first: Box<dyn CacheKey> = Box::new(HttpGetRequest::<u64>::new("/a"));
+        let second: Box<dyn CacheKey> = Box::new(HttpGetRequest::<u64>::new("/b"));
+        let same: Box<dyn CacheKey> = Box::new(HttpGetRequest::<u64>::new("/a"));
+        assert!(first.any_eq(same.any()));
+        assert!(!first.any_eq(second.any()));
+        assert_eq!(first.any_ord(second.any()), Ordering::Less);
+        assert_eq!(format!("{first:?}"), "HttpGetRequest(\"/a\")");
+
+        let request = HttpGetRequest::<u64>::new("/a");
+        assert!(Invalidatable::<()>::invalidated_by(&request, &()));
+    }
+
+    #[test]
+    fn status_codes() {
+        assert_eq!(check_status(200), Ok(()));
+        assert_eq!(check_status(204), Ok(()));
+        assert_eq!(check_status(304), Err(JsonError::Status(304)));
+        assert_eq!(check_status(404), Err(JsonError::Status(404)));
+        assert_eq!(
+            JsonError::Status(500).to_string(),
+            "request failed with status 500"
+        );
+    }
+
+    #[test]
+    fn parse_responses() {
+        assert_eq!(parse::<Vec<u64>>("[1, 2]"), Ok(vec![1, 2]));
+        assert!(matches!(
+            parse::<Vec<u64>>("{\"id\": 1}"),
+            Err(JsonError::Parse(_))
+        ));
+    }
+}
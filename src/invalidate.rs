@@ -1,4 +1,5 @@
 //! Traits to handle invalidations.
+use crate::CacheKey;
 
 /// Something which can be invalidated.
 pub trait Invalidatable<M> {
@@ -7,7 +8,147 @@ pub trait Invalidatable<M> {
     }
 }
 
+/// Determine if a key is invalidated by a mutation, by matching it against a list of patterns.
+///
+/// This expands to a `match` which returns true for any of the patterns and false otherwise,
+/// which saves writing out the match in [`Invalidatable::invalidated_by`] when the mutations are
+/// an enum. The key is passed first, mirroring the signature of `invalidated_by`, and patterns may
+/// have guards which refer to its fields.
+///
+/// ```rust
+/// # use wasm_cache::{invalidated_by, Invalidatable};
+/// enum Mutation {
+///     CreateUser,
+///     UpdateUser(u64),
+///     DeleteUser(u64),
+///     UpdatePost(u64),
+/// }
+///
+/// struct User(u64);
+///
+/// impl Invalidatable<Mutation> for User {
+///     fn invalidated_by(&self, mutation: &Mutation) -> bool {
+///         invalidated_by!(
+///             self,
+///             mutation,
+///             [Mutation::UpdateUser(id) | Mutation::DeleteUser(id) if *id == self.0]
+///         )
+///     }
+/// }
+///
+/// struct UserList;
+///
+/// impl Invalidatable<Mutation> for UserList {
+///     fn invalidated_by(&self, mutation: &Mutation) -> bool {
+///         invalidated_by!(self, mutation, [Mutation::CreateUser, Mutation::DeleteUser(_)])
+///     }
+/// }
+///
+/// assert!(User(1).invalidated_by(&Mutation::UpdateUser(1)));
+/// assert!(!User(1).invalidated_by(&Mutation::DeleteUser(2)));
+/// assert!(UserList.invalidated_by(&Mutation::CreateUser));
+/// assert!(!UserList.invalidated_by(&Mutation::UpdatePost(1)));
+/// ```
+#[macro_export]
+macro_rules! invalidated_by {
+    ($key:expr, $mutation:expr, [$($pattern:pat $(if $guard:expr)?),* $(,)?]) => {{
+        let _ = &$key;
+        match $mutation {
+            $($pattern $(if $guard)? => true,)*
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }};
+}
+
 /// Something which produces invalidations.
 pub trait Invalidator<M> {
     fn mutations(&self) -> Vec<M>;
+
+    /// Determine which of these keys are invalidated by the mutations this produces.
+    #[allow(clippy::borrowed_box)]
+    fn invalidates<'a>(
+        &self,
+        keys: impl Iterator<Item = &'a Box<dyn CacheKey<M>>>,
+    ) -> Vec<&'a Box<dyn CacheKey<M>>>
+    where
+        M: 'a,
+    {
+        let mutations = self.mutations();
+        keys.filter(|key| {
+            mutations
+                .iter()
+                .any(|mutation| key.invalidated_by(mutation))
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    enum Mutation {
+        User(u64),
+        Post(u64),
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct User(u64);
+
+    impl Invalidatable<Mutation> for User {
+        fn invalidated_by(&self, mutation: &Mutation) -> bool {
+            mutation == &Mutation::User(self.0)
+        }
+    }
+
+    /// Feed of a user, which shows their posts.
+    struct Feed(u64);
+
+    impl Invalidatable<Mutation> for Feed {
+        fn invalidated_by(&self, mutation: &Mutation) -> bool {
+            crate::invalidated_by!(
+                self,
+                mutation,
+                [Mutation::User(id) if *id == self.0, Mutation::Post(_)]
+            )
+        }
+    }
+
+    struct Update(Vec<Mutation>);
+
+    impl Invalidator<Mutation> for Update {
+        fn mutations(&self) -> Vec<Mutation> {
+            self.0.clone()
+        }
+    }
+
+    #[test]
+    fn invalidates_matching_keys() {
+        let keys: Vec<Box<dyn CacheKey<Mutation>>> =
+            vec![Box::new(User(1)), Box::new(User(2)), Box::new(User(3))];
+        let update = Update(vec![
+            Mutation::User(1),
+            Mutation::Post(2),
+            Mutation::User(3),
+        ]);
+        let invalidated = update.invalidates(keys.iter());
+        assert_eq!(invalidated.len(), 2);
+        assert!(invalidated[0].any_eq(&User(1)));
+        assert!(invalidated[1].any_eq(&User(3)));
+    }
+
+    #[test]
+    fn invalidates_nothing() {
+        let keys: Vec<Box<dyn CacheKey<Mutation>>> = vec![Box::new(User(1))];
+        assert!(Update(vec![]).invalidates(keys.iter()).is_empty());
+    }
+
+    #[test]
+    fn invalidated_by_patterns() {
+        assert!(Feed(1).invalidated_by(&Mutation::User(1)));
+        assert!(!Feed(1).invalidated_by(&Mutation::User(2)));
+        assert!(Feed(1).invalidated_by(&Mutation::Post(2)));
+    }
 }
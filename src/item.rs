@@ -1,6 +1,31 @@
 use crate::CacheKey;
 use async_trait::async_trait;
-use std::{error::Error, fmt::Debug};
+use std::{borrow::Cow, error::Error, fmt::Debug, time::Duration};
+
+/// Hints on how the value of a [`CacheItem`] should be cached.
+///
+/// These are analogous to the directives of the HTTP `Cache-Control` header.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheControlHints {
+    /// Time after which the value is stale and needs to be refetched.
+    pub max_age: Option<Duration>,
+    /// Time after the value has become stale during which it is still served while it is being
+    /// refetched. Once this has elapsed, the stale value is discarded.
+    pub stale_while_revalidate: Option<Duration>,
+    /// Do not store the value beyond its current use. The value is shown to current subscribers,
+    /// but the entry is evicted as soon as it has none.
+    pub no_store: bool,
+}
+
+/// Response to a conditional request, see [`CacheItem::send_conditional`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Conditional<V> {
+    /// The value has changed, optionally with the validator to send with the next request, such
+    /// as the `ETag` header.
+    Modified { value: V, validator: Option<String> },
+    /// The value has not changed since the validator was issued, such as a `304 Not Modified`.
+    NotModified,
+}
 
 /// Represents some action that can be cached.
 ///
@@ -17,7 +42,113 @@ pub trait CacheItem<M = ()>: CacheKey<M> + Clone + Ord {
 
     async fn send(&self) -> Result<Self::Value, Self::Error>;
 
+    /// Send a conditional request, given the validator of the cached value.
+    ///
+    /// This allows using HTTP caching, for example by sending the validator as `If-None-Match`
+    /// and returning [`Conditional::NotModified`] for a `304 Not Modified` response, so that the
+    /// cached value is revalidated rather than replaced. The cache stores the validator of the
+    /// last [`Conditional::Modified`] response. By default, this calls [`send`](CacheItem::send).
+    async fn send_conditional(
+        &self,
+        _validator: Option<&str>,
+    ) -> Result<Conditional<Self::Value>, Self::Error> {
+        self.send().await.map(|value| Conditional::Modified {
+            value,
+            validator: None,
+        })
+    }
+
+    /// Normalized key of this item, which determines the cache entry it uses.
+    ///
+    /// Items with the same normalized key share one entry, so that they are only fetched once.
+    /// Override this for items with fields which do not affect the response, such as an
+    /// authentication token or a correlation id, by returning a copy of the item with those fields
+    /// cleared. Fetches still send the item as it was passed to the cache. By default, the whole
+    /// item is the key.
+    fn cache_key(&self) -> Cow<'_, Self> {
+        Cow::Borrowed(self)
+    }
+
     fn superset(&self) -> Vec<Self> {
         vec![]
     }
+
+    /// Normalize the raw response of [`send`](CacheItem::send) before it is cached.
+    ///
+    /// This is called by the cache for every value it stores. By default, the value is stored
+    /// unchanged.
+    fn transform_response(&self, raw: Self::Value) -> Self::Value {
+        raw
+    }
+
+    /// Resolve a write of this item which conflicts with the value already in the cache.
+    ///
+    /// This is called whenever a value is stored while the entry holds one, valid or stale, for
+    /// example when a fetch completes after the value was set. It receives the normalized
+    /// `incoming` value, and returns the one to store. Override this to merge values, such as
+    /// taking the union of two partial lists or keeping the one with the newer timestamp. By
+    /// default, the incoming value wins.
+    fn resolve(&self, _existing: &Self::Value, incoming: Self::Value) -> Self::Value {
+        incoming
+    }
+
+    /// Hints on how the value of this item should be cached.
+    fn cache_control(&self) -> CacheControlHints {
+        Default::default()
+    }
+
+    /// Whether stale data of this item is refetched when the window regains focus.
+    ///
+    /// Only has an effect if focus refetching is enabled on the cache. Override this to exclude
+    /// expensive queries.
+    fn refetch_on_focus(&self) -> bool {
+        true
+    }
+
+    /// Time to wait before fetching this item, to rate-limit rapid refetches.
+    ///
+    /// Every fetch requested while waiting, such as by a subscriber after the entry was
+    /// invalidated, restarts the timer instead of starting another fetch. This is useful for
+    /// queries which are invalidated on every keystroke, like search results. By default, fetches
+    /// start immediately.
+    fn debounce(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Number of times a failed fetch of this item is retried before giving up.
+    ///
+    /// Once exceeded, the entry is failed terminally and no longer refetched until it is
+    /// invalidated or refetched manually. By default, the cache-wide
+    /// [`CacheOptions::max_retries`](crate::yew::CacheOptions::max_retries) applies.
+    fn max_retries(&self) -> Option<u32> {
+        None
+    }
+
+    /// Backoff of fetches of this item after failures.
+    ///
+    /// By default, the cache-wide [`CacheOptions::backoff`](crate::yew::CacheOptions::backoff)
+    /// applies.
+    #[cfg(feature = "yew")]
+    fn backoff(&self) -> Option<crate::yew::Backoff> {
+        None
+    }
+
+    /// Priority of fetches of this item, higher values are more important.
+    ///
+    /// When [`CacheOptions::max_concurrent_fetches`](crate::yew::CacheOptions) is reached, queued
+    /// fetches start in order of priority, and in the order they were queued within the same
+    /// priority. Raise this for data which is visible right away, and lower it for background data.
+    fn fetch_priority(&self) -> u8 {
+        128
+    }
+
+    /// Determine if a subscriber should trigger a background fetch of this entry.
+    ///
+    /// By default, this is [`Entry::needs_fetch`](crate::yew::Entry::needs_fetch). Override this
+    /// for custom refetch logic, for example to refetch valid values after a certain age, or to
+    /// never refetch after a failure. Entries which are being fetched are never fetched again.
+    #[cfg(feature = "yew")]
+    fn should_refetch(&self, entry: &crate::yew::Entry, now: f64) -> bool {
+        entry.needs_fetch(now)
+    }
 }
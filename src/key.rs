@@ -1,6 +1,11 @@
 //! Arbitrary cache keys.
 use super::Invalidatable;
-use std::{any::Any, cmp::Ordering, fmt::Debug};
+use std::{
+    any::Any,
+    cmp::Ordering,
+    fmt::Debug,
+    hash::{Hash, Hasher},
+};
 
 /// Trait for arbitrary cache keys.
 ///
@@ -8,13 +13,21 @@ use std::{any::Any, cmp::Ordering, fmt::Debug};
 pub trait CacheKey<M = ()>: Debug + Invalidatable<M> + 'static {
     fn any(&self) -> &(dyn Any + 'static);
     fn any_eq(&self, other: &dyn Any) -> bool;
+    /// Compare this key with another one of the same type.
+    ///
+    /// Keys of different types are ordered by their [`TypeId`](std::any::TypeId), which differs
+    /// between builds. The [`Ord`] implementation of `dyn CacheKey` orders them by their type
+    /// name instead.
     fn any_ord(&self, other: &dyn Any) -> Ordering;
     fn clone_boxed(&self) -> Box<dyn CacheKey<M>>;
+
+    /// Name of the type of this key, see [`std::any::type_name`].
+    fn type_name(&self) -> &'static str;
 }
 
 impl<M: 'static> PartialOrd<Self> for dyn CacheKey<M> {
     fn partial_cmp(&self, other: &dyn CacheKey<M>) -> Option<Ordering> {
-        Some(self.any_ord(other.any()))
+        Some(self.cmp(other))
     }
 }
 
@@ -26,9 +39,14 @@ impl<M: 'static> PartialEq<Self> for dyn CacheKey<M> {
 
 impl<M: 'static> Eq for dyn CacheKey<M> {}
 
+/// Keys of different types are ordered by their [type name](CacheKey::type_name), so that the
+/// order of entries is the same in every build. Only types which share the same name, such as from
+/// different versions of a crate, fall back to ordering by [`TypeId`](std::any::TypeId).
 impl<M: 'static> Ord for dyn CacheKey<M> {
     fn cmp(&self, other: &dyn CacheKey<M>) -> Ordering {
-        self.any_ord(other.any())
+        self.type_name()
+            .cmp(other.type_name())
+            .then_with(|| self.any_ord(other.any()))
     }
 }
 
@@ -41,10 +59,7 @@ impl<M: 'static> Clone for Box<dyn CacheKey<M>> {
 impl<M, T: Debug + Eq + Ord + Any + Clone + Invalidatable<M> + 'static> CacheKey<M> for T {
     fn any_eq(&self, other: &dyn Any) -> bool {
         match other.downcast_ref::<T>() {
-            Some(other) => {
-                println!("Using eq: {}", std::any::type_name::<T>());
-                self.eq(other)
-            }
+            Some(other) => self.eq(other),
             None => false,
         }
     }
@@ -66,15 +81,84 @@ impl<M, T: Debug + Eq + Ord + Any + Clone + Invalidatable<M> + 'static> CacheKey
     fn clone_boxed(&self) -> Box<dyn CacheKey<M>> {
         Box::new(self.clone())
     }
+
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
+}
+
+/// Trait for arbitrary cache keys which are hashable.
+///
+/// Unlike [`CacheKey`], this does not require the key to be sortable, so it supports keys without a
+/// natural ordering, such as structs containing a `HashMap`. Boxed keys can be used in hash-based
+/// collections.
+pub trait HashCacheKey<M = ()>: Debug + Invalidatable<M> + 'static {
+    fn any(&self) -> &(dyn Any + 'static);
+    fn any_eq(&self, other: &dyn Any) -> bool;
+    /// Feed this key and its type into the hasher.
+    fn any_hash(&self, state: &mut dyn Hasher);
+    fn clone_boxed(&self) -> Box<dyn HashCacheKey<M>>;
+
+    /// Name of the type of this key, see [`std::any::type_name`].
+    fn type_name(&self) -> &'static str;
+}
+
+impl<M: 'static> PartialEq<Self> for dyn HashCacheKey<M> {
+    fn eq(&self, other: &dyn HashCacheKey<M>) -> bool {
+        self.any_eq(other.any())
+    }
+}
+
+impl<M: 'static> Eq for dyn HashCacheKey<M> {}
+
+impl<M: 'static> Hash for dyn HashCacheKey<M> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.any_hash(state);
+    }
+}
+
+impl<M: 'static> Clone for Box<dyn HashCacheKey<M>> {
+    fn clone(&self) -> Self {
+        ((&**self) as &dyn HashCacheKey<M>).clone_boxed()
+    }
+}
+
+impl<M, T: Debug + Eq + Hash + Any + Clone + Invalidatable<M> + 'static> HashCacheKey<M> for T {
+    fn any_eq(&self, other: &dyn Any) -> bool {
+        match other.downcast_ref::<T>() {
+            Some(other) => self.eq(other),
+            None => false,
+        }
+    }
+
+    fn any_hash(&self, mut state: &mut dyn Hasher) {
+        self.type_id().hash(&mut state);
+        self.hash(&mut state);
+    }
+
+    fn any(&self) -> &(dyn Any + 'static) {
+        self as &(dyn Any + 'static)
+    }
+
+    fn clone_boxed(&self) -> Box<dyn HashCacheKey<M>> {
+        Box::new(self.clone())
+    }
+
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::op_ref, clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
-    use std::collections::BTreeMap;
+    use std::collections::{BTreeMap, HashMap};
 
     impl Invalidatable<()> for String {}
     impl Invalidatable<()> for Vec<usize> {}
+    impl Invalidatable<()> for u64 {}
+    impl Invalidatable<()> for bool {}
 
     #[test]
     fn cache_item_eq_identity() {
@@ -84,7 +168,6 @@ mod tests {
 
     #[test]
     fn cache_item_eq_equal() {
-        println!("Running test");
         let string1: Box<dyn CacheKey> = Box::new(String::from("Hello"));
         let string2: Box<dyn CacheKey> = Box::new(String::from("Hello"));
         assert!(&string1 == &string2);
@@ -106,10 +189,100 @@ mod tests {
         assert_eq!(string_hello.any_eq(&array_empty), false);
     }
 
+    #[test]
+    fn cache_key_type_name() {
+        let string: Box<dyn CacheKey> = Box::new(String::from("Hello"));
+        assert_eq!(string.type_name(), std::any::type_name::<String>());
+    }
+
+    #[test]
+    fn cache_key_order_across_types() {
+        let mut map: BTreeMap<Box<dyn CacheKey>, &str> = Default::default();
+        map.insert(Box::new(String::from("b")), "string b");
+        map.insert(Box::new(2u64), "u64 2");
+        map.insert(Box::new(true), "bool");
+        map.insert(Box::new(vec![1usize]), "vec");
+        map.insert(Box::new(String::from("a")), "string a");
+        map.insert(Box::new(1u64), "u64 1");
+
+        // ordered by type name, then by value
+        let values: Vec<_> = map.values().copied().collect();
+        assert_eq!(
+            values,
+            ["string a", "string b", "vec", "bool", "u64 1", "u64 2"]
+        );
+        let names: Vec<_> = map.keys().map(|key| key.type_name()).collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
+    }
+
     #[test]
     fn test_cache_key() {
         let mut map: BTreeMap<Box<dyn CacheKey>, &str> = Default::default();
         map.insert(Box::new(String::from("Hello")), "String Hello");
         map.insert(Box::new(String::from("World")), "String World");
     }
+
+    #[test]
+    fn hash_cache_key_without_ord() {
+        #[derive(Clone, Debug, PartialEq, Eq)]
+        struct Filter(HashMap<String, u64>);
+
+        impl Hash for Filter {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                let mut entries: Vec<_> = self.0.iter().collect();
+                entries.sort();
+                entries.hash(state);
+            }
+        }
+
+        impl Invalidatable<()> for Filter {}
+
+        let filter = |limit| Filter(HashMap::from([("limit".to_string(), limit)]));
+        let mut map: HashMap<Box<dyn HashCacheKey>, &str> = Default::default();
+        map.insert(Box::new(filter(10)), "first");
+        map.insert(Box::new(filter(20)), "second");
+        map.insert(Box::new(String::from("limit")), "string");
+        map.insert(Box::new(filter(10)), "replaced");
+        assert_eq!(map.len(), 3);
+        let key: Box<dyn HashCacheKey> = Box::new(filter(10));
+        assert_eq!(map[&key], "replaced");
+        assert!(key.any_eq(key.clone().any()));
+        assert_eq!(key.type_name(), std::any::type_name::<Filter>());
+    }
+
+    #[cfg(feature = "macros")]
+    #[test]
+    fn transparent_newtypes_are_distinct() {
+        #[crate::cache_key(transparent)]
+        struct UserId(u64);
+
+        #[crate::cache_key(transparent)]
+        struct GroupId {
+            id: u64,
+        }
+
+        #[crate::cache_key(transparent)]
+        struct Tagged<T>(T);
+
+        impl Invalidatable<()> for UserId {}
+        impl Invalidatable<()> for GroupId {}
+        impl<T> Invalidatable<()> for Tagged<T> {}
+
+        let user: Box<dyn CacheKey> = Box::new(UserId(1));
+        let group: Box<dyn CacheKey> = Box::new(GroupId { id: 1 });
+        assert!(user != group);
+        assert!(user == Box::new(UserId(1)) as Box<dyn CacheKey>);
+        assert!(UserId(1) < UserId(2));
+        assert_eq!(format!("{user:?}"), "UserId(1)");
+        assert_eq!(format!("{:?}", GroupId { id: 1 }), "GroupId { id: 1 }");
+
+        // generic newtypes require the traits of their type parameters
+        let tagged: Box<dyn CacheKey> = Box::new(Tagged(1u64));
+        assert!(tagged == Box::new(Tagged(1u64)) as Box<dyn CacheKey>);
+        assert!(tagged != Box::new(Tagged(1u32)) as Box<dyn CacheKey>);
+        assert!(Tagged("a") < Tagged("b"));
+        assert_eq!(format!("{tagged:?}"), "Tagged(1)");
+    }
 }
@@ -6,6 +6,9 @@
 ///
 /// It is intended to be used with the Yew framework, although more integrations may be added in
 /// the future.
+mod entity;
+#[cfg(feature = "gloo-net")]
+mod http;
 mod invalidate;
 mod item;
 mod key;
@@ -13,4 +16,8 @@ mod value;
 #[cfg(feature = "yew")]
 pub mod yew;
 
-pub use crate::{invalidate::*, item::*, key::*, value::*};
+#[cfg(feature = "gloo-net")]
+pub use crate::http::*;
+pub use crate::{entity::*, invalidate::*, item::*, key::*, value::*};
+#[cfg(feature = "macros")]
+pub use wasm_cache_macros::cache_key;
@@ -3,18 +3,73 @@
 //! This module contains a dynamic value type that is agnostic over the storage container,
 //! [`Value`]. It also contains aliases and implementations for [`RcValue`] and [`ArcValue`], which
 //! use the [`Rc`] and [`Arc`] reference-counted containers, respectively.
-use std::{any::Any, rc::Rc, sync::Arc};
+use std::{
+    any::Any,
+    fmt::{Display, Formatter, Result},
+    rc::Rc,
+    sync::Arc,
+};
 
 /// Dynamic value.
 ///
 /// Cache values are dynamic, but need to be able to be cast into a concrete type. The [`Value`]
 /// type helps here. By default, it contains a `dyn Any`, so is able to store any type of data. It
-/// is able to be cast into a concrete type. It also contains as state the validity of the type.
+/// is able to be cast into a concrete type. It also contains as state the validity of the type,
+/// see [`ValueState`].
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Value<T> {
-    /// Valid means the data is usable.
-    valid: bool,
-    data: Option<T>,
+    state: ValueState<T>,
+}
+
+/// State of a [`Value`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ValueState<T> {
+    /// No data has been loaded yet.
+    #[default]
+    Empty,
+    /// Data has been loaded, but has since been invalidated.
+    Stale(T),
+    /// Data is loaded and usable.
+    Valid(T),
+}
+
+impl<T> ValueState<T> {
+    /// Return an option with a reference to the data.
+    pub fn data(&self) -> Option<&T> {
+        match self {
+            Self::Empty => None,
+            Self::Stale(data) | Self::Valid(data) => Some(data),
+        }
+    }
+
+    /// Map the data, keeping the state.
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> ValueState<U> {
+        match self {
+            Self::Empty => ValueState::Empty,
+            Self::Stale(data) => ValueState::Stale(f(data)),
+            Self::Valid(data) => ValueState::Valid(f(data)),
+        }
+    }
+
+    /// Map the data with a fallible function, keeping the state.
+    pub fn try_map<U, F: FnOnce(T) -> Option<U>>(self, f: F) -> Option<ValueState<U>> {
+        Some(match self {
+            Self::Empty => ValueState::Empty,
+            Self::Stale(data) => ValueState::Stale(f(data)?),
+            Self::Valid(data) => ValueState::Valid(f(data)?),
+        })
+    }
+}
+
+/// Renders the state and data, such as `valid(1)`, `stale(1)` or `empty`.
+impl<T: Display> Display for ValueState<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::Empty => write!(f, "empty"),
+            Self::Stale(data) => write!(f, "stale({data})"),
+            Self::Valid(data) => write!(f, "valid({data})"),
+        }
+    }
 }
 
 pub type RcValue<V = dyn Any> = Value<Rc<V>>;
@@ -22,60 +77,137 @@ pub type ArcValue<V = dyn Any + Send + Sync> = Value<Arc<V>>;
 
 impl Value<Rc<dyn Any>> {
     pub fn downcast<T: 'static>(self) -> Option<Value<Rc<T>>> {
-        let value = Value {
-            valid: self.valid,
-            data: match self.data {
-                None => None,
-                Some(data) => Some(data.downcast::<T>().ok()?),
-            },
-        };
-        Some(value)
+        let state = self.state.try_map(|data| data.downcast::<T>().ok())?;
+        Some(state.into())
+    }
+
+    /// Determine if this value holds data of type `T` which is equal to `other`.
+    pub fn value_eq<T: PartialEq + 'static>(&self, other: &T) -> bool {
+        self.data()
+            .and_then(|data| data.downcast_ref::<T>())
+            .map(|data| data == other)
+            .unwrap_or(false)
     }
 }
 
 impl Value<Arc<dyn Any + Send + Sync>> {
     pub fn downcast<T: Any + Send + Sync>(self) -> Option<Value<Arc<T>>> {
-        let value = Value {
-            valid: self.valid,
-            data: match self.data {
-                None => None,
-                Some(data) => Some(data.downcast::<T>().ok()?),
-            },
-        };
-        Some(value)
+        let state = self.state.try_map(|data| data.downcast::<T>().ok())?;
+        Some(state.into())
     }
 }
 
 impl<T> Default for Value<T> {
     fn default() -> Self {
         Self {
-            valid: false,
-            data: None,
+            state: ValueState::Empty,
         }
     }
 }
 
+/// Renders the state of the value, see the [`Display`] implementation of [`ValueState`].
+impl<T: Display> Display for Value<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        self.state.fmt(f)
+    }
+}
+
+impl<T> From<ValueState<T>> for Value<T> {
+    fn from(state: ValueState<T>) -> Self {
+        Self { state }
+    }
+}
+
 impl<T> Value<T> {
     /// Create new value with the given data.
     pub fn new(data: T) -> Self {
         Self {
-            data: Some(data),
-            valid: true,
+            state: ValueState::Valid(data),
         }
     }
 
+    /// State of this value.
+    pub fn state(&self) -> &ValueState<T> {
+        &self.state
+    }
+
+    /// Convert this value into its state.
+    pub fn into_state(self) -> ValueState<T> {
+        self.state
+    }
+
     /// Return an option with a reference to the data.
     pub fn data(&self) -> Option<&T> {
-        self.data.as_ref()
+        self.state.data()
     }
 
     /// Determine if this data is valid.
     pub fn valid(&self) -> bool {
-        self.valid
+        matches!(self.state, ValueState::Valid(_))
+    }
+
+    /// Mark stale data as valid again, because it is known to be unchanged.
+    ///
+    /// Returns whether the value holds data, empty values stay empty.
+    pub fn revalidate(&mut self) -> bool {
+        self.state = match std::mem::take(&mut self.state) {
+            ValueState::Stale(data) => ValueState::Valid(data),
+            state => state,
+        };
+        self.data().is_some()
     }
 
     /// Invalidate this data.
+    ///
+    /// Valid data becomes stale, an empty value stays empty.
     pub fn invalidate(&mut self) {
-        self.valid = false;
+        self.state = match std::mem::take(&mut self.state) {
+            ValueState::Valid(data) => ValueState::Stale(data),
+            state => state,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalidate_keeps_data() {
+        let mut value = Value::new(1);
+        assert_eq!(value.state(), &ValueState::Valid(1));
+        value.invalidate();
+        assert_eq!(value.state(), &ValueState::Stale(1));
+        assert!(!value.valid());
+        assert_eq!(value.data(), Some(&1));
+
+        assert!(value.revalidate());
+        assert_eq!(value.state(), &ValueState::Valid(1));
+
+        let mut empty = Value::<u64>::default();
+        empty.invalidate();
+        assert!(!empty.revalidate());
+        assert_eq!(empty.into_state(), ValueState::Empty);
+    }
+
+    #[test]
+    fn display_state() {
+        let mut value = Value::new(Rc::new(1));
+        assert_eq!(value.to_string(), "valid(1)");
+        value.invalidate();
+        assert_eq!(value.to_string(), "stale(1)");
+        assert_eq!(Value::<u64>::default().to_string(), "empty");
+    }
+
+    #[test]
+    fn downcast_keeps_state() {
+        let mut value: RcValue = Value::new(Rc::new(1u64) as Rc<dyn Any>);
+        value.invalidate();
+        let value = value.downcast::<u64>().unwrap();
+        assert_eq!(value.state(), &ValueState::Stale(Rc::new(1)));
+        assert!(RcValue::default().downcast::<u64>().is_some());
+        assert!(Value::new(Rc::new(1u64) as Rc<dyn Any>)
+            .downcast::<String>()
+            .is_none());
     }
 }
@@ -1,7 +1,28 @@
-use crate::{CacheKey, RcValue, CacheItem};
-use prokio::time::sleep;
+//! Yew integration.
+//!
+//! Wrap your application in a [`CacheProvider`] and use [`use_cached`] to fetch data from within
+//! components. Use [`use_cache`] to get a handle to the [`Cache`], which allows you to invalidate
+//! entries imperatively, for example from callbacks.
+//!
+//! The cache also works on native targets, for example in tests. There, fetches are spawned with
+//! `tokio::task::spawn_local` and need to run inside of a tokio `LocalSet`, unless another
+//! executor is set with [`Cache::with_spawn_fn`].
+
+// The `#[hook]` macro duplicates generic bounds into a where clause.
+#![allow(clippy::multiple_bound_locations)]
+
+use crate::{
+    CacheControlHints, CacheItem, CacheKey, Conditional, Invalidator, RcValue, ValueState,
+};
 use std::{
-    any::Any, collections::BTreeMap, rc::Rc, sync::Mutex, time::Duration,
+    any::{Any, TypeId},
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, BinaryHeap},
+    future::Future,
+    pin::Pin,
+    rc::{Rc, Weak},
+    sync::{Mutex, MutexGuard},
+    time::Duration,
 };
 use yew::{
     functional::{UseStateHandle, UseStateSetter},
@@ -11,65 +32,651 @@ use yew::{
 const DELAY_INITIAL: Duration = Duration::from_millis(100);
 const DELAY_MULTIPLIER: f64 = 1.5;
 
-#[derive(Clone, Default, Debug)]
+/// Current timestamp in milliseconds, as returned by `performance.now()`.
+#[cfg(target_arch = "wasm32")]
+fn now() -> f64 {
+    web_sys::window()
+        .and_then(|window| window.performance())
+        .map(|performance| performance.now())
+        .unwrap_or_default()
+}
+
+/// Current timestamp in milliseconds, relative to the first call.
+#[cfg(not(target_arch = "wasm32"))]
+fn now() -> f64 {
+    use std::{sync::OnceLock, time::Instant};
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_secs_f64() * 1000.0
+}
+
+/// Function which spawns a future, see [`Cache::with_spawn_fn`].
+pub type SpawnFn = fn(Pin<Box<dyn Future<Output = ()>>>);
+
+/// Spawn a future on the current thread.
+#[cfg(target_arch = "wasm32")]
+fn spawn_local(future: Pin<Box<dyn Future<Output = ()>>>) {
+    wasm_bindgen_futures::spawn_local(future);
+}
+
+/// Spawn a future on the current thread, which needs to run a tokio `LocalSet`.
+#[cfg(not(target_arch = "wasm32"))]
+fn spawn_local(future: Pin<Box<dyn Future<Output = ()>>>) {
+    tokio::task::spawn_local(future);
+}
+
+/// Random number between -1 and 1, used to spread out retries.
+fn random_unit() -> f64 {
+    use std::{
+        collections::hash_map::RandomState,
+        hash::{BuildHasher, Hasher},
+    };
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(now().to_bits());
+    hasher.finish() as f64 / u64::MAX as f64 * 2.0 - 1.0
+}
+
+/// Convert a duration between two timestamps into a [`Duration`].
+fn elapsed(from: f64, to: f64) -> Duration {
+    Duration::from_secs_f64((to - from).max(0.0) / 1000.0)
+}
+
+/// Subscriber to the value of a cache entry.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Subscriber {
+    /// State of a component, which is set to the new value.
+    State(UseStateSetter<RcValue>),
+    /// Callback, which is called with the new value.
+    Callback(Callback<RcValue>),
+}
+
+impl Subscriber {
+    /// Notify this subscriber of a new value.
+    pub fn notify(&self, value: RcValue) {
+        match self {
+            Self::State(setter) => setter.set(value),
+            Self::Callback(callback) => callback.emit(value),
+        }
+    }
+}
+
+impl From<UseStateSetter<RcValue>> for Subscriber {
+    fn from(setter: UseStateSetter<RcValue>) -> Self {
+        Self::State(setter)
+    }
+}
+
+impl From<Callback<RcValue>> for Subscriber {
+    fn from(callback: Callback<RcValue>) -> Self {
+        Self::Callback(callback)
+    }
+}
+
+/// Notifications of subscribers, which are sent once the lock of the cache is released.
+///
+/// When many entries change at once, every subscriber is only notified once, with the value of the
+/// last entry queued for it. This avoids rendering components which subscribe to several of the
+/// entries over and over, since their subscriber ignores the value and rerenders anyway.
+#[derive(Debug, Default)]
+pub struct Broadcast {
+    notifications: Vec<(Subscriber, RcValue)>,
+}
+
+impl Broadcast {
+    /// Queue a notification of this subscriber, replacing any previous one.
+    pub fn queue(&mut self, subscriber: &Subscriber, value: &RcValue) {
+        match self.notifications.iter_mut().find(|(s, _)| s == subscriber) {
+            Some((_, queued)) => *queued = value.clone(),
+            None => self.notifications.push((subscriber.clone(), value.clone())),
+        }
+    }
+
+    /// Notify all queued subscribers.
+    pub fn send(self) {
+        for (subscriber, value) in self.notifications {
+            subscriber.notify(value);
+        }
+    }
+}
+
+/// State of the circuit breaker of an entry.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CircuitState {
+    /// Fetches are allowed.
+    #[default]
+    Closed,
+    /// Fetches are suspended until the timestamp.
+    Open { until: f64 },
+    /// A single trial fetch is in progress, which decides whether the circuit closes or opens
+    /// again.
+    HalfOpen,
+}
+
+/// Configuration of the circuit breaker.
+///
+/// When fetching an entry fails repeatedly, the circuit breaker suspends fetching it for a
+/// cooldown period. After the cooldown, a single trial fetch is attempted. If it succeeds, the
+/// circuit is closed again, otherwise it reopens for another cooldown period.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CircuitBreaker {
+    /// Number of consecutive failures after which the circuit opens.
+    pub threshold: u32,
+    /// Time for which fetches are suspended once the circuit opens.
+    pub cooldown: Duration,
+}
+
+/// Exponential backoff of fetches after failures, see [`CacheOptions::backoff`].
+///
+/// The delay starts at 100ms and grows by half after every consecutive failure, up to the maximum.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Backoff {
+    /// Maximum delay, before jitter is applied.
+    pub max_delay: Duration,
+    /// Maximum random deviation of every delay, as a fraction of it. With the default of `0.2`,
+    /// delays vary by up to 20% in either direction, so that entries which fail at the same time
+    /// do not retry in lockstep.
+    pub jitter: f64,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            max_delay: Duration::from_secs(30),
+            jitter: 0.2,
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay before the next fetch, after this many consecutive failures.
+    pub fn delay(&self, failures: u32) -> Duration {
+        let exponent = failures.saturating_sub(1).try_into().unwrap_or(i32::MAX);
+        let delay = (DELAY_INITIAL.as_secs_f64() * DELAY_MULTIPLIER.powi(exponent))
+            .min(self.max_delay.as_secs_f64());
+        let jitter = self.jitter.clamp(0.0, 1.0) * random_unit();
+        Duration::from_secs_f64(delay * (1.0 + jitter))
+    }
+}
+
+#[derive(Clone, Default)]
 pub struct Entry {
     /// Delay to use for next request
     pub delay: Option<Duration>,
+    /// Backoff which determines the delay, see [`CacheItem::backoff`].
+    pub backoff: Backoff,
+    /// Timestamp of when this entry was first inserted.
+    pub created_at: Option<f64>,
+    /// Timestamp of when the value of this entry was last updated.
+    pub last_updated: Option<f64>,
+    /// Timestamp of when this entry was last accessed, see [`Entry::touch`].
+    pub last_used: Cell<Option<f64>>,
+    /// Time after which the value of this entry expires.
+    pub ttl: Option<Duration>,
+    /// Time after expiry during which the stale value is still served.
+    pub stale_while_revalidate: Option<Duration>,
+    /// Evict this entry as soon as it has no subscribers.
+    pub no_store: bool,
+    /// Exclude this entry from refetching on focus, see [`CacheItem::refetch_on_focus`].
+    pub no_focus_refetch: bool,
+    /// Time to wait before fetching, see [`CacheItem::debounce`].
+    pub debounce_duration: Option<Duration>,
+    /// Cancel flag of the debounce timer of the queued fetch, if any.
+    pub debounce_cancel: Option<Rc<Cell<bool>>>,
     /// Fetch in-progress
     pub progress: bool,
+    /// Priority of the last fetch of this entry, see [`CacheItem::fetch_priority`].
+    pub priority: u8,
+    /// Generation of this entry, which changes when a value is written with [`Cache::set_value`]
+    /// or the entry is replaced, to discard results of older fetches.
+    pub generation: u64,
+    /// Number of consecutive failed fetches.
+    pub failures: u32,
+    /// Whether fetching has been given up after too many failures, see
+    /// [`CacheOptions::max_retries`].
+    pub terminal: bool,
+    /// Debug representation of the error of the last failed fetch.
+    pub last_error: Option<String>,
+    /// Number of errors since the last successful fetch.
+    pub last_error_count: u32,
+    /// Error of the last failed fetch, see [`Cache::error_of`].
+    pub error: Option<Rc<dyn Any>>,
+    /// Validator of the value, such as an `ETag`, see [`CacheItem::send_conditional`].
+    pub validator: Option<String>,
+    /// State of the circuit breaker.
+    pub circuit: CircuitState,
     /// Current cached value.
     pub value: RcValue,
+    /// Type name of the cached value, recorded when it is stored by [`Cache::cache`] or
+    /// [`Cache::seed`].
+    pub value_type: Option<&'static str>,
     /// List of subscribers to this value.
-    pub subscriptions: Vec<UseStateSetter<RcValue>>,
+    pub subscriptions: Vec<Subscriber>,
+    /// Stale times requested by subscribers, see [`QueryOptions::stale_time`].
+    pub stale_times: Vec<(Subscriber, Duration)>,
+    /// Fetch listeners of subscribers, see [`Cache::set_fetch_listener`].
+    pub listeners: Vec<(Subscriber, FetchListener)>,
+    /// Suspension of components waiting for the value, see [`use_cached_suspense`].
+    pub suspension: Option<EntrySuspension>,
+    /// Source pushing values of this entry, see [`Cache::attach_source`].
+    pub source: Option<EntrySource>,
+}
+
+impl std::fmt::Debug for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Entry")
+            .field("progress", &self.progress)
+            .field("delay", &self.delay)
+            .field("valid", &self.value.valid())
+            .field("subscriber_count", &self.subscriptions.len())
+            .finish_non_exhaustive()
+    }
 }
 
 impl Entry {
+    /// Record an access to this entry, for least-recently-used eviction.
+    pub fn touch(&self, now: f64) {
+        self.last_used.set(Some(now));
+    }
+
     /// Broadcast the current value of the cache entry to all subscribers.
     pub fn broadcast(&self) {
         for subscriber in &self.subscriptions {
-            subscriber.set(self.value.clone());
+            subscriber.notify(self.value.clone());
+        }
+    }
+
+    /// Queue the current value of the cache entry for all subscribers, see [`Broadcast`].
+    pub fn broadcast_into(&self, broadcast: &mut Broadcast) {
+        for subscriber in &self.subscriptions {
+            broadcast.queue(subscriber, &self.value);
         }
     }
 
     /// Subscribe for updates
-    pub fn subscribe(&mut self, setter: &UseStateSetter<RcValue>) {
-        if !self.subscriptions.iter().any(|i| i == setter) {
-            self.subscriptions.push(setter.clone());
+    pub fn subscribe(&mut self, subscriber: &Subscriber) {
+        if !self.subscriptions.iter().any(|i| i == subscriber) {
+            self.subscriptions.push(subscriber.clone());
         }
     }
 
     /// Unsubscribe for updates
-    pub fn unsubscribe(&mut self, setter: &UseStateSetter<RcValue>) {
-        self.subscriptions.retain(|s| s != setter);
+    pub fn unsubscribe(&mut self, subscriber: &Subscriber) {
+        self.subscriptions.retain(|s| s != subscriber);
+        self.stale_times.retain(|(s, _)| s != subscriber);
+        self.listeners.retain(|(s, _)| s != subscriber);
+    }
+
+    /// Set the fetch listener of this subscriber, replacing any previous one. An empty listener
+    /// removes it.
+    pub fn set_listener(&mut self, subscriber: &Subscriber, listener: FetchListener) {
+        self.listeners.retain(|(s, _)| s != subscriber);
+        if !listener.is_empty() {
+            self.listeners.push((subscriber.clone(), listener));
+        }
     }
 
-    /// Get current delay and update.
+    /// Set the stale time requested by this subscriber.
+    pub fn set_stale_time(&mut self, subscriber: &Subscriber, stale_time: Option<Duration>) {
+        self.stale_times.retain(|(s, _)| s != subscriber);
+        if let Some(stale_time) = stale_time {
+            self.stale_times.push((subscriber.clone(), stale_time));
+        }
+    }
+
+    /// Strictest stale time requested by any of the subscribers.
+    pub fn stale_time(&self) -> Option<Duration> {
+        self.stale_times.iter().map(|(_, time)| *time).min()
+    }
+
+    /// Determine if the value is older than the strictest stale time of the subscribers.
+    pub fn is_stale_for_subscribers(&self, now: f64) -> bool {
+        match (self.stale_time(), self.since_updated(now)) {
+            (Some(stale_time), Some(since_updated)) => since_updated > stale_time,
+            _ => false,
+        }
+    }
+
+    /// Update the delay for the number of consecutive failures, see [`Backoff::delay`].
     pub fn delay_update(&mut self) {
-        self.delay = match self.delay {
-            Some(current) => Some(Duration::from_secs_f64(
-                current.as_secs_f64() * DELAY_MULTIPLIER,
-            )),
-            None => Some(DELAY_INITIAL),
-        };
+        self.delay = Some(self.backoff.delay(self.failures.max(1)));
     }
 
     pub fn delay_reset(&mut self) {
         self.delay = None;
     }
 
-    pub fn needs_fetch(&self) -> bool {
-        !self.value.valid() && !self.progress
+    /// Determine if this entry needs to be fetched, because its value is invalid or stale.
+    ///
+    /// Entries without a TTL are never stale by time. Entries which are being fetched, or whose
+    /// circuit is open or which have failed terminally, do not need to be fetched.
+    pub fn needs_fetch(&self, now: f64) -> bool {
+        (!self.value.valid() || self.is_stale(now))
+            && !self.progress
+            && !self.circuit_open(now)
+            && !self.terminal
+    }
+
+    /// Clear the terminal failure state, the count of failures and the backoff delay, so that the
+    /// entry is fetched again right away.
+    ///
+    /// This is done when the entry is invalidated or refetched manually, since earlier failures no
+    /// longer say anything about the next fetch.
+    pub fn retry(&mut self) {
+        self.terminal = false;
+        self.failures = 0;
+        self.delay_reset();
+    }
+
+    /// Determine if the circuit breaker currently suspends fetches.
+    pub fn circuit_open(&self, now: f64) -> bool {
+        matches!(self.circuit, CircuitState::Open { until } if now < until)
+    }
+
+    /// Record the start of a fetch. If the circuit cooldown has passed, this is the trial fetch.
+    pub fn fetch_started(&mut self) {
+        self.progress = true;
+        if let CircuitState::Open { .. } = self.circuit {
+            self.circuit = CircuitState::HalfOpen;
+        }
+    }
+
+    /// Record a failed fetch, opening the circuit if needed.
+    pub fn fetch_failed(&mut self, now: f64, breaker: Option<&CircuitBreaker>) {
+        self.progress = false;
+        self.failures += 1;
+        self.delay_update();
+        let Some(breaker) = breaker else {
+            return;
+        };
+        if self.circuit == CircuitState::HalfOpen || self.failures >= breaker.threshold {
+            self.circuit = CircuitState::Open {
+                until: now + breaker.cooldown.as_secs_f64() * 1000.0,
+            };
+        }
+    }
+
+    /// Record a successful fetch, closing the circuit.
+    pub fn fetch_succeeded(&mut self, now: f64) {
+        self.progress = false;
+        self.failures = 0;
+        self.terminal = false;
+        self.last_error = None;
+        self.last_error_count = 0;
+        self.error = None;
+        self.circuit = CircuitState::Closed;
+        self.last_updated = Some(now);
+        self.delay_reset();
+    }
+
+    /// Time since this entry was created.
+    pub fn age(&self, now: f64) -> Option<Duration> {
+        self.created_at.map(|created_at| elapsed(created_at, now))
+    }
+
+    /// Time since the value of this entry was last updated.
+    pub fn since_updated(&self, now: f64) -> Option<Duration> {
+        self.last_updated
+            .map(|last_updated| elapsed(last_updated, now))
+    }
+
+    /// Determine if the value of this entry has outlived its TTL.
+    pub fn is_expired(&self, now: f64) -> bool {
+        match (self.ttl, self.since_updated(now)) {
+            (Some(ttl), Some(since_updated)) => since_updated > ttl,
+            _ => false,
+        }
+    }
+
+    /// Determine if this entry holds a value which has outlived its TTL.
+    pub fn is_stale(&self, now: f64) -> bool {
+        self.value.data().is_some() && self.is_expired(now)
+    }
+
+    /// Determine if the value of this entry has outlived its TTL and the stale-while-revalidate
+    /// window, meaning that it should no longer be served.
+    pub fn is_discardable(&self, now: f64) -> bool {
+        match (self.ttl, self.since_updated(now)) {
+            (Some(ttl), Some(since_updated)) => {
+                since_updated > ttl + self.stale_while_revalidate.unwrap_or_default()
+            }
+            _ => false,
+        }
+    }
+
+    /// Apply the cache control hints of an item to this entry.
+    pub fn apply_hints(&mut self, hints: &CacheControlHints) {
+        self.ttl = hints.max_age;
+        self.stale_while_revalidate = hints.stale_while_revalidate;
+        self.no_store = hints.no_store;
+    }
+
+    /// Invalidate the value of this entry if it has expired, and discard it if it is past the
+    /// stale-while-revalidate window. Returns true if the value was changed.
+    pub fn expire(&mut self, now: f64) -> bool {
+        if self.value.data().is_some() && self.is_discardable(now) {
+            self.value = Default::default();
+            true
+        } else if self.value.valid() && self.is_expired(now) {
+            self.value.invalidate();
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Lightweight metadata of a cache entry, see [`Cache::peek_entry`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntryMetadata {
+    /// Whether the value is valid.
+    pub valid: bool,
+    /// Whether a fetch is in progress.
+    pub progress: bool,
+    /// Delay to use for the next fetch.
+    pub delay: Option<Duration>,
+    /// Number of subscribers.
+    pub subscribers: usize,
+    /// Number of consecutive failed fetches.
+    pub failures: u32,
+    /// Whether fetching has been given up, see [`Entry::terminal`].
+    pub terminal: bool,
+    /// Type name of the cached value, see [`Entry::value_type`].
+    pub value_type: Option<&'static str>,
+}
+
+impl From<&Entry> for EntryMetadata {
+    fn from(entry: &Entry) -> Self {
+        Self {
+            valid: entry.value.valid(),
+            progress: entry.progress,
+            delay: entry.delay,
+            subscribers: entry.subscriptions.len(),
+            failures: entry.failures,
+            terminal: entry.terminal,
+            value_type: entry.value_type,
+        }
+    }
+}
+
+/// Statistics about the contents of a [`Cache`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheStatistics {
+    /// Number of entries in the cache.
+    pub entries: usize,
+    /// Age of the oldest entry.
+    pub oldest_entry_age: Option<Duration>,
+    /// Age of the newest entry.
+    pub newest_entry_age: Option<Duration>,
+}
+
+/// Options which control the behaviour of a [`Cache`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct CacheOptions {
+    /// Keep the last known good value of an entry when fetching it fails.
+    ///
+    /// Invalidating an entry marks its value as stale, but keeps it around so that it can be shown
+    /// until a fetch succeeds. When this is enabled (the default), a failed fetch keeps serving
+    /// the stale value, so that transient network errors do not blank the UI. When disabled, a
+    /// failed fetch discards the value.
+    pub keep_stale_on_failure: bool,
+    /// Circuit breaker for entries which fail repeatedly, disabled by default.
+    pub circuit_breaker: Option<CircuitBreaker>,
+    /// Maximum number of fetches which run at the same time, unlimited by default. Fetches
+    /// beyond this limit are queued until a running fetch completes.
+    ///
+    /// Setting this avoids a thundering herd of requests, for example when the whole cache is
+    /// invalidated at once.
+    pub max_concurrent_fetches: Option<usize>,
+    /// Number of times a failed fetch is retried before giving up, unlimited by default. Items
+    /// can override this with [`CacheItem::max_retries`].
+    ///
+    /// Once an entry has failed more often, it is not refetched until it is invalidated or
+    /// [refetched](Cache::refetch) manually, so that a missing resource is not requested forever.
+    pub max_retries: Option<u32>,
+    /// Backoff of fetches after failures, which items can override with [`CacheItem::backoff`].
+    pub backoff: Backoff,
+}
+
+impl Default for CacheOptions {
+    fn default() -> Self {
+        Self {
+            keep_stale_on_failure: true,
+            circuit_breaker: None,
+            max_concurrent_fetches: None,
+            max_retries: None,
+            backoff: Backoff::default(),
+        }
+    }
+}
+
+/// Options for a single use of a cached item, see [`use_cached_with_options`].
+///
+/// The defaults match the behaviour of [`use_cached`]. Options are evaluated whenever the
+/// component subscribes. They take precedence over the [`CacheControlHints`] of the item, which in
+/// turn take precedence over the cache-wide [`CacheOptions`]. When several components subscribe to
+/// the same key with different freshness requirements, the strictest one wins.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryOptions {
+    /// Time after which a cached value is considered stale, and refetched when subscribing.
+    pub stale_time: Option<Duration>,
+    /// Refetch the value when the component is mounted, even if it is cached and valid.
+    pub refetch_on_mount: bool,
+    /// Keep returning the previous value while the value for a new key is loading.
+    pub keep_previous_data: bool,
+    /// Whether the value is fetched. When disabled, the component is still subscribed and updates
+    /// when something else fetches the value.
+    pub enabled: bool,
+    /// Callbacks of this component for completed fetches, see [`QueryOptions::on_success`] and
+    /// [`QueryOptions::on_error`].
+    pub listener: FetchListener,
+}
+
+impl Default for QueryOptions {
+    fn default() -> Self {
+        Self {
+            stale_time: None,
+            refetch_on_mount: false,
+            keep_previous_data: false,
+            enabled: true,
+            listener: FetchListener::default(),
+        }
+    }
+}
+
+/// Starts a fetch on the given cache.
+type StartFetch<M> = Box<dyn FnOnce(&Cache<M>)>;
+
+/// Fetch which is waiting for a free slot.
+///
+/// Pending fetches are ordered by priority, and by the order in which they were queued within the
+/// same priority, so that the greatest one runs next.
+pub struct PendingFetch<M: 'static = ()> {
+    /// Key of the entry which is fetched.
+    pub key: Box<dyn CacheKey<M>>,
+    /// Whether the entry had subscribers when the fetch was queued.
+    pub subscribed: bool,
+    /// Priority of the fetch, see [`CacheItem::fetch_priority`].
+    pub priority: u8,
+    /// Position in the queue, to keep fetches of the same priority in order.
+    sequence: u64,
+    start: StartFetch<M>,
+}
+
+impl<M: 'static> PartialEq for PendingFetch<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl<M: 'static> Eq for PendingFetch<M> {}
+
+impl<M: 'static> PartialOrd for PendingFetch<M> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<M: 'static> Ord for PendingFetch<M> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.sequence.cmp(&self.sequence))
     }
 }
 
 pub struct BTreeCache<M: 'static = ()> {
     pub entries: BTreeMap<Box<dyn CacheKey<M>>, Entry>,
+    /// Options of this cache.
+    pub options: CacheOptions,
+    /// Cancel flag of the running eviction task, if any.
+    pub eviction: Option<Rc<Cell<bool>>>,
+    /// Number of fetches currently running.
+    pub fetching: usize,
+    /// Fetches waiting for a free slot, the greatest one runs next.
+    pub pending: BinaryHeap<PendingFetch<M>>,
+    /// Number of fetches queued so far, see [`PendingFetch`].
+    pub queued: u64,
+    /// Source of timestamps and timers.
+    pub clock: Rc<dyn Clock>,
+    /// Number of entries which are currently being fetched, including queued fetches.
+    pub in_flight: usize,
+    /// Subscribers to changes of [`BTreeCache::in_flight`].
+    pub activity: Vec<Callback<()>>,
+    /// Function used to spawn fetches and background tasks.
+    pub spawn: SpawnFn,
+    /// Last generation handed out to an entry, see [`Entry::generation`].
+    pub generation: u64,
+    /// Focus listeners, if focus refetching is enabled.
+    pub focus: Option<FocusListeners>,
+    /// Key types whose values are persisted, see [`Cache::persist`].
+    #[cfg(feature = "storage")]
+    pub storage: Vec<PersistedType<M>>,
+    /// Channel invalidations are posted to, see [`Cache::enable_sync`].
+    #[cfg(feature = "cross-tab")]
+    pub sync: Option<CrossTabSync<M>>,
+    /// Parent which missing entries are inherited from, see [`CacheScope::Layered`].
+    pub parent: Option<Weak<Mutex<BTreeCache<M>>>>,
 }
 
 impl<M: 'static> Clone for BTreeCache<M> {
     fn clone(&self) -> Self {
         Self {
             entries: self.entries.clone(),
+            options: self.options.clone(),
+            eviction: None,
+            fetching: 0,
+            pending: Default::default(),
+            queued: 0,
+            clock: self.clock.clone(),
+            in_flight: 0,
+            activity: vec![],
+            spawn: self.spawn,
+            generation: self.generation,
+            focus: None,
+            #[cfg(feature = "storage")]
+            storage: self.storage.clone(),
+            #[cfg(feature = "cross-tab")]
+            sync: None,
+            parent: self.parent.clone(),
         }
     }
 }
@@ -78,6 +685,22 @@ impl<M: 'static> Default for BTreeCache<M> {
     fn default() -> Self {
         Self {
             entries: Default::default(),
+            options: Default::default(),
+            eviction: None,
+            fetching: 0,
+            pending: Default::default(),
+            queued: 0,
+            clock: Rc::new(SystemClock),
+            in_flight: 0,
+            activity: vec![],
+            spawn: spawn_local,
+            generation: 0,
+            focus: None,
+            #[cfg(feature = "storage")]
+            storage: vec![],
+            #[cfg(feature = "cross-tab")]
+            sync: None,
+            parent: None,
         }
     }
 }
@@ -102,6 +725,20 @@ impl<M: 'static> Default for Cache<M> {
     }
 }
 
+/// Prints the number of entries and their keys. Prints `<locked>` if the cache is locked, such as
+/// when printing it from within one of its callbacks.
+impl<M: 'static> std::fmt::Debug for Cache<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Ok(cache) = self.cache.try_lock() else {
+            return f.write_str("Cache { <locked> }");
+        };
+        f.debug_struct("Cache")
+            .field("entries", &cache.entries.len())
+            .field("keys", &cache.entries.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
 impl<M: 'static> PartialEq for Cache<M> {
     fn eq(&self, other: &Self) -> bool {
         Rc::ptr_eq(&self.cache, &other.cache)
@@ -109,269 +746,3943 @@ impl<M: 'static> PartialEq for Cache<M> {
 }
 
 impl<M: 'static> BTreeCache<M> {
-    /// Unsubscribe to the value of this data.
-    pub fn mutate<T: CacheKey<M>, R, F: FnOnce(&mut Entry) -> R>(
+    /// Mutate the entry of this data, returning the result of the closure.
+    ///
+    /// Returns `None` without calling the closure if there is no entry for this data. The entry is
+    /// touched, which counts as a use for least-recently-used eviction. Like all lookups, this uses
+    /// the [normalized key](CacheItem::cache_key) of the data.
+    #[must_use]
+    pub fn mutate<T: CacheItem<M>, R, F: FnOnce(&mut Entry) -> R>(
         &mut self,
         data: &T,
         mutate: F,
     ) -> Option<R> {
-        if let Some(entry) = self.entries.get_mut(data as &dyn CacheKey<M>) {
-            Some(mutate(entry))
-        } else {
-            None
-        }
+        let now = self.clock.now();
+        let key = data.cache_key();
+        let key: &dyn CacheKey<M> = &*key;
+        self.entries.get_mut(key).map(|entry| {
+            entry.touch(now);
+            mutate(entry)
+        })
     }
 
-    /// Unsubscribe to the value of this data.
+    /// Mutate every entry, in the order of their keys.
+    ///
+    /// Unlike [`BTreeCache::mutate`], entries are not touched.
     pub fn mutate_all<F: Fn(&Box<dyn CacheKey<M>>, &mut Entry)>(&mut self, mutate: F) {
         for (key, entry) in &mut self.entries {
             mutate(key, entry);
         }
     }
 
-    /// Unsubscribe to the value of this data.
-    pub fn insert<T: CacheKey<M>>(&mut self, data: T, entry: Entry) {
-        let key = Box::new(data);
+    /// Iterate over all entries mutably, in the order of their keys.
+    ///
+    /// Unlike [`BTreeCache::mutate_all`], this can be combined with iterator adaptors, for example
+    /// to stop early. Entries are not touched, so this does not count as a use for
+    /// least-recently-used eviction.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&Box<dyn CacheKey<M>>, &mut Entry)> {
+        self.entries.iter_mut()
+    }
+
+    /// Insert the entry of this data.
+    ///
+    /// If an entry already exists for this data, it is replaced, and its subscribers are dropped
+    /// without being notified. The creation time is set unless the entry already has one. The
+    /// entry is stored under the [normalized key](CacheItem::cache_key) of the data.
+    pub fn insert<T: CacheItem<M>>(&mut self, data: T, mut entry: Entry) {
+        entry.created_at.get_or_insert_with(|| self.clock.now());
+        entry.generation = self.generation;
+        let key = Box::new(data.cache_key().into_owned());
         self.entries.insert(key, entry);
     }
 
-    pub fn get<T: CacheKey<M>>(&self, data: &T) -> Option<&Entry> {
-        self.entries.get(data as &dyn CacheKey<M>)
+    /// Remove the entry of this data, returning it.
+    ///
+    /// If the entry was being fetched, the fetch no longer counts as in flight, and its result is
+    /// discarded even if a new entry is inserted for the same data in the meantime.
+    pub fn remove<T: CacheItem<M>>(&mut self, data: &T) -> Option<Entry> {
+        self.remove_erased(&*data.cache_key())
     }
-}
 
-impl<M: 'static> Cache<M> {
-    fn subscribe<R: CacheItem<M>>(&self, request: &R, handle: UseStateHandle<RcValue>)
-    where
-        R::Value: PartialEq,
-    {
-        let setter = handle.setter();
-        let mut cache = self.cache.lock().expect("Failure to lock cache");
+    /// Remove the entry of this type-erased key, returning it, see [`BTreeCache::remove`].
+    pub fn remove_erased(&mut self, key: &dyn CacheKey<M>) -> Option<Entry> {
+        let entry = self.entries.remove(key)?;
+        self.generation += 1;
+        if entry.progress {
+            self.fetch_done();
+        }
+        Some(entry)
+    }
 
-        // add self as subscriber to cache value, if exists.
-        let mutated = cache.mutate(request, |entry| {
-            entry.subscribe(&setter);
+    /// Remove all entries, returning them.
+    ///
+    /// Like [`BTreeCache::remove`], the results of fetches which are in flight are discarded.
+    pub fn clear(&mut self) -> BTreeMap<Box<dyn CacheKey<M>>, Entry> {
+        let entries = std::mem::take(&mut self.entries);
+        self.generation += 1;
+        for _ in entries.values().filter(|entry| entry.progress) {
+            self.fetch_done();
+        }
+        entries
+    }
 
-            // only set it if it is different
-            let value = entry.value.clone().downcast::<R::Value>().unwrap();
-            let current = (*handle).clone().downcast::<R::Value>().unwrap();
-            if value != current {
-                setter.set(entry.value.clone());
+    pub fn get<T: CacheItem<M>>(&self, data: &T) -> Option<&Entry> {
+        let entry = self.entries.get(&*data.cache_key() as &dyn CacheKey<M>)?;
+        entry.touch(self.clock.now());
+        Some(entry)
+    }
+
+    /// Number of fetches which are currently running.
+    pub fn in_progress_count(&self) -> usize {
+        self.fetching
+    }
+
+    /// Keys of the fetches waiting for a free slot, in the order they will run.
+    pub fn pending_keys(&self) -> impl Iterator<Item = &dyn CacheKey<M>> {
+        let mut pending: Vec<_> = self.pending.iter().collect();
+        pending.sort_by(|left, right| right.cmp(left));
+        pending.into_iter().map(|pending| &*pending.key)
+    }
+
+    /// Acquire a slot for a fetch, returns false if the concurrency limit is reached.
+    pub fn acquire_fetch(&mut self) -> bool {
+        match self.options.max_concurrent_fetches {
+            Some(limit) if self.fetching >= limit => false,
+            _ => {
+                self.fetching += 1;
+                true
             }
+        }
+    }
 
-            entry.clone()
+    /// Queue a fetch, behind the pending fetches of the same or a higher priority.
+    pub fn enqueue_fetch(
+        &mut self,
+        key: Box<dyn CacheKey<M>>,
+        subscribed: bool,
+        priority: u8,
+        start: StartFetch<M>,
+    ) {
+        self.pending.push(PendingFetch {
+            key,
+            subscribed,
+            priority,
+            sequence: self.queued,
+            start,
         });
+        self.queued += 1;
+    }
 
-        match mutated {
-            None => {
-                cache.insert(
-                    request.clone(),
-                    Entry {
-                        progress: false,
-                        subscriptions: vec![setter.clone()],
-                        ..Default::default()
-                    },
-                );
-                drop(cache);
-                self.fetch(request, None);
-            }
-            Some(entry) if entry.needs_fetch() => {
-                log::debug!("{entry:?}");
-                let delay = entry.delay;
-                drop(cache);
-                self.fetch(request, delay);
+    /// Take the next pending fetch, if a slot is free, acquiring the slot for it.
+    ///
+    /// Pending fetches for entries which have lost all of their subscribers since they were queued
+    /// are dropped.
+    pub fn next_fetch(&mut self) -> Option<PendingFetch<M>> {
+        while !self.pending.is_empty() && self.acquire_fetch() {
+            let pending = self.pending.pop()?;
+            match self.entries.get_mut(&pending.key) {
+                Some(entry) if pending.subscribed && entry.subscriptions.is_empty() => {
+                    entry.progress = false;
+                    self.fetching -= 1;
+                    self.fetch_done();
+                }
+                Some(_) => return Some(pending),
+                None => self.fetching -= 1,
             }
-            _ => {}
         }
+        None
     }
 
-    /// Trigger a fetch of this data.
-    fn fetch<T: CacheItem<M>>(&self, data: &T, delay: Option<Duration>) {
-        let mut cache = self.cache.lock().expect("Failure to lock cache");
-        cache.mutate(data, |entry| {
-            entry.progress = true
+    /// Release the slot of a completed fetch, and hand it to the next pending fetch.
+    pub fn release_fetch(&mut self) -> Option<PendingFetch<M>> {
+        self.fetching = self.fetching.saturating_sub(1);
+        self.next_fetch()
+    }
+
+    /// Record that the fetch of an entry has started, notifying activity subscribers.
+    pub fn fetch_begun(&mut self) {
+        self.in_flight += 1;
+        self.notify_activity();
+    }
+
+    /// Record that the fetch of an entry is done, notifying activity subscribers.
+    ///
+    /// The count never drops below zero, even if a fetch is reported as done more than once.
+    pub fn fetch_done(&mut self) {
+        if self.in_flight > 0 {
+            self.in_flight -= 1;
+            self.notify_activity();
+        }
+    }
+
+    fn notify_activity(&self) {
+        for subscriber in &self.activity {
+            subscriber.emit(());
+        }
+    }
+
+    /// Number of entries whose key matches the filter and which are currently being fetched.
+    pub fn in_flight_where<F: Fn(&dyn CacheKey<M>) -> bool>(&self, filter: F) -> usize {
+        self.entries
+            .iter()
+            .filter(|(key, entry)| entry.progress && filter(key.as_ref()))
+            .count()
+    }
+
+    /// Evict the least recently used entries, until at most `capacity` entries are left.
+    ///
+    /// Entries with subscribers are never evicted, so more entries may remain. Entries which have
+    /// never been used are evicted first. Returns the number of removed entries.
+    pub fn evict_lru(&mut self, capacity: usize) -> usize {
+        let excess = self.entries.len().saturating_sub(capacity);
+        let mut candidates: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.subscriptions.is_empty())
+            .map(|(key, entry)| (entry.last_used.get(), key.clone()))
+            .collect();
+        candidates.sort_by(|(left, _), (right, _)| {
+            left.partial_cmp(right).unwrap_or(std::cmp::Ordering::Equal)
         });
-        let data = data.clone();
-        let cache = self.clone();
-        wasm_bindgen_futures::spawn_local(async move {
-            if let Some(delay) = delay {
-                sleep(delay).await;
-            }
-            match data.send().await {
-                Ok(result) => cache.cache(&data, Rc::new(result)),
-                Err(error) => cache.failure(&data, error),
+        for (_, key) in candidates.iter().take(excess) {
+            self.remove_erased(key.as_ref());
+        }
+        excess.min(candidates.len())
+    }
+
+    /// Evict entries whose value has expired.
+    ///
+    /// Expired entries and entries marked as `no_store` are removed if they have no subscribers.
+    /// Expired entries which still have subscribers are invalidated instead, so that they are
+    /// refetched. Returns the number of removed entries.
+    pub fn evict_expired(&mut self, now: f64) -> usize {
+        let mut evicted = vec![];
+        for (key, entry) in self.entries.iter_mut() {
+            if !entry.subscriptions.is_empty() {
+                if entry.expire(now) {
+                    entry.broadcast();
+                }
+            } else if entry.no_store || entry.is_expired(now) {
+                evicted.push(key.clone());
             }
-        });
+        }
+        for key in &evicted {
+            self.remove_erased(key.as_ref());
+        }
+        evicted.len()
     }
 
-    /// Handle failure.
-    pub fn failure<T: CacheItem<M>>(&self, data: &T, error: T::Error) {
-    log::error!("error fetching {data:?}: {error}");
-        self.cache
-            .lock()
-            .expect("Failure to lock cache")
-            .mutate(data, move |entry| {
-                entry.delay_update();
-                entry.progress = false;
-                entry.broadcast();
-            });
+    /// Compute statistics about the entries of this cache.
+    pub fn statistics(&self, now: f64) -> CacheStatistics {
+        let ages = self.entries.values().filter_map(|entry| entry.age(now));
+        CacheStatistics {
+            entries: self.entries.len(),
+            oldest_entry_age: ages.clone().max(),
+            newest_entry_age: ages.min(),
+        }
     }
+}
 
-    /// Cache this data.
-    pub fn cache<T: CacheItem<M>>(&self, data: &T, value: Rc<T::Value>) {
-        self.cache
-            .lock()
-            .expect("Failure to lock cache")
-            .mutate(data, move |entry| {
-                entry.delay_reset();
-                entry.value = RcValue::new(value as Rc<dyn Any>);
-                entry.progress = false;
-                entry.broadcast();
-            });
+impl<M: 'static> Cache<M> {
+    /// Create a new, empty cache with the given options.
+    pub fn with_options(options: CacheOptions) -> Self {
+        let cache = BTreeCache {
+            options,
+            ..Default::default()
+        };
+        Self {
+            cache: Rc::new(Mutex::new(cache)),
+        }
     }
 
-    /// Unsubscribe to the value of this data.
-    pub fn unsubscribe<T: CacheItem<M>>(&self, data: &T, setter: &UseStateSetter<RcValue>) {
-        self.cache
-            .lock()
-            .expect("Failure to lock cache")
-            .mutate(data, |entry| {
-                entry.unsubscribe(setter);
-            });
+    /// Use this clock for all time-based logic of the cache.
+    ///
+    /// Defaults to the [`SystemClock`]. Tests can pass a [`ManualClock`] to control time.
+    pub fn with_clock(self, clock: impl Clock + 'static) -> Self {
+        self.cache.lock().expect("Failure to lock cache").clock = Rc::new(clock);
+        self
     }
 
-    /// Invalidate this invalidation.
-    pub fn invalidate(&self, mutation: &M) {
+    /// Use this function to spawn fetches and background tasks.
+    ///
+    /// Defaults to `wasm_bindgen_futures::spawn_local` on WebAssembly and to
+    /// `tokio::task::spawn_local` on native targets. Tests can pass a synchronous executor such as
+    /// `futures::executor::block_on`, which runs fetches to completion immediately.
+    pub fn with_spawn_fn(self, spawn: SpawnFn) -> Self {
+        self.cache.lock().expect("Failure to lock cache").spawn = spawn;
+        self
+    }
+
+    /// Spawn this future with the spawn function of this cache.
+    fn spawn<F: Future<Output = ()> + 'static>(&self, future: F) {
+        let spawn = self.cache.lock().expect("Failure to lock cache").spawn;
+        spawn(Box::pin(future));
+    }
+
+    /// Global cache of the current thread, for this mutation type.
+    ///
+    /// This is used by [`use_global_cached`] when no [`CacheProvider`] is present. Every call on
+    /// the same thread returns a handle to the same cache.
+    pub fn global() -> Self {
+        thread_local! {
+            static GLOBAL: RefCell<BTreeMap<TypeId, Box<dyn Any>>> = Default::default();
+        }
+        GLOBAL.with(|global| {
+            global
+                .borrow_mut()
+                .entry(TypeId::of::<M>())
+                .or_insert_with(|| Box::new(Self::default()))
+                .downcast_ref::<Self>()
+                .expect("Global cache is of wrong type")
+                .clone()
+        })
+    }
+
+    /// Current timestamp of the clock of this cache.
+    fn now(&self) -> f64 {
         self.cache
             .lock()
             .expect("Failure to lock cache")
-            .mutate_all(|key, entry| {
-                if key.invalidated_by(mutation) {
-                    entry.value.invalidate();
-                    entry.broadcast();
-                }
-            });
+            .clock
+            .now()
     }
 
-    /// Invalidate this key.
-    pub fn invalidate_key<T: CacheItem<M>>(&self, data: &T) {
+    /// Future which resolves after `duration` has passed on the clock of this cache.
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()>>> {
         self.cache
             .lock()
             .expect("Failure to lock cache")
-            .mutate(data, |entry| {
-                entry.value.invalidate();
-                entry.broadcast();
-            });
+            .clock
+            .sleep(duration)
     }
 
-    /// Invalidates entire cache.
-    pub fn invalidate_all(&self) {
+    /// Subscribe to the value of this data, fetching it if needed and `fetch` is set.
+    fn subscribe<R: CacheItem<M>>(
+        &self,
+        request: &R,
+        handle: UseStateHandle<RcValue>,
+        fetch: bool,
+    ) {
+        let subscriber = Subscriber::State(handle.setter());
+        self.subscribe_with(request, &subscriber, Some(&handle), fetch);
+    }
+
+    /// Subscribe to the value of this data, fetching it if needed and `fetch` is set.
+    ///
+    /// If the `current` value of the subscriber is known, it is notified of the cached value if it
+    /// differs. Subscribing is idempotent, so this can be called repeatedly.
+    fn subscribe_with<R: CacheItem<M>>(
+        &self,
+        request: &R,
+        subscriber: &Subscriber,
+        current: Option<&RcValue>,
+        fetch: bool,
+    ) {
+        let options = QueryOptions {
+            enabled: fetch,
+            ..Default::default()
+        };
+        self.subscribe_with_options(request, subscriber, current, &options, false);
+    }
+
+    /// Subscribe to the value of this data using the given options.
+    ///
+    /// If `refetch` is set, a valid value is invalidated so that it is refetched. Connects the
+    /// [push source](Cache::attach_source) of the entry, if it has one. A missing entry is
+    /// inherited from the [parent](CacheScope::Layered) of the cache, if it has a valid value.
+    fn subscribe_with_options<R: CacheItem<M>>(
+        &self,
+        request: &R,
+        subscriber: &Subscriber,
+        current: Option<&RcValue>,
+        options: &QueryOptions,
+        refetch: bool,
+    ) {
+        self.inherit_from_parent(request);
+        #[cfg(feature = "storage")]
+        let restore = !self.contains(request);
+        self.subscribe_locked(request, subscriber, current, options, refetch);
+        #[cfg(feature = "storage")]
+        if restore {
+            self.load_persisted(request);
+        }
+        self.connect_source(request);
+    }
+
+    /// Subscribe to the value of this data, see [`Cache::subscribe_with_options`].
+    fn subscribe_locked<R: CacheItem<M>>(
+        &self,
+        request: &R,
+        subscriber: &Subscriber,
+        current: Option<&RcValue>,
+        options: &QueryOptions,
+        refetch: bool,
+    ) {
+        let fetch = options.enabled;
+        options.listener.check_value_type::<R::Value>();
         let mut cache = self.cache.lock().expect("Failure to lock cache");
-        cache.mutate_all(|_key, entry| {
-            entry.value.invalidate();
-            entry.broadcast();
+
+        // add self as subscriber to cache value, if exists.
+        let timestamp = cache.clock.now();
+        let mutated = cache.mutate(request, |entry| {
+            entry.subscribe(subscriber);
+            entry.set_stale_time(subscriber, options.stale_time);
+            entry.set_listener(subscriber, options.listener.clone());
+            entry.no_focus_refetch = !request.refetch_on_focus();
+            let mut changed = entry.expire(timestamp);
+            if entry.value.valid() && (refetch || entry.is_stale_for_subscribers(timestamp)) {
+                entry.value.invalidate();
+                changed = true;
+            }
+            if changed {
+                entry.broadcast();
+            }
+
+            // only set it if it is different
+            if let Some(current) = current {
+                let value = entry.value.clone().downcast::<R::Value>().unwrap();
+                let current = current.clone().downcast::<R::Value>().unwrap();
+                if value != current {
+                    subscriber.notify(entry.value.clone());
+                }
+            }
+
+            entry.clone()
         });
+
+        match mutated {
+            None => {
+                let mut entry = Entry {
+                    progress: false,
+                    subscriptions: vec![subscriber.clone()],
+                    no_focus_refetch: !request.refetch_on_focus(),
+                    ..Default::default()
+                };
+                entry.set_stale_time(subscriber, options.stale_time);
+                entry.set_listener(subscriber, options.listener.clone());
+                cache.insert(request.clone(), entry);
+                if fetch {
+                    self.fetch_locked(cache, request, None);
+                }
+            }
+            Some(entry)
+                if fetch && !entry.progress && request.should_refetch(&entry, timestamp) =>
+            {
+                log::debug!("{entry:?}");
+                self.fetch_locked(cache, request, entry.delay);
+            }
+            _ => {}
+        }
     }
-}
 
-#[cfg(feature = "websocket")]
-mod websocket {
-    use super::*;
-    use serde::de::DeserializeOwned;
-    use std::{time::Duration, fmt::Debug};
-    use gloo_net::websocket::{Message, futures::WebSocket};
-    use futures::StreamExt;
+    /// Update the subscriptions of a subscriber to a dynamic list of keys, see
+    /// [`use_cached_many`].
+    ///
+    /// Unsubscribes from the keys which were dropped from `subscribed`, subscribes to the new
+    /// ones, and fetches the remaining ones only if they need it, without subscribing to them
+    /// again. Afterwards, `subscribed` holds the `keys`.
+    fn subscribe_many<R: CacheItem<M>>(
+        &self,
+        subscriber: &Subscriber,
+        subscribed: &mut Vec<R>,
+        keys: &[R],
+    ) {
+        for key in subscribed.iter().filter(|key| !keys.contains(key)) {
+            self.unsubscribe(key, subscriber);
+        }
+        for key in keys {
+            if !subscribed.contains(key) || !self.fetch_if_subscribed(key, subscriber) {
+                self.subscribe_with(key, subscriber, None, true);
+            }
+        }
+        *subscribed = keys.to_vec();
+    }
 
-    impl<M: 'static + DeserializeOwned + Debug> Cache<M> {
-        pub fn websocket_listener(&self, url: &str) {
-            let url = url.to_string();
-            let cache = self.clone();
-            wasm_bindgen_futures::spawn_local(async move {
-                let mut first = true;
-                loop {
-                    if !first {
-                        prokio::time::sleep(Duration::from_secs(1)).await;
-                    } else {
-                        first = false;
-                    }
+    /// Fetch this data if it needs it and the subscriber is subscribed to it.
+    ///
+    /// Returns false if the subscriber is not subscribed, for example because the entry has been
+    /// removed in the meantime.
+    fn fetch_if_subscribed<R: CacheItem<M>>(&self, request: &R, subscriber: &Subscriber) -> bool {
+        let cache = self.cache.lock().expect("Failure to lock cache");
+        let now = cache.clock.now();
+        let fetch = match cache.get(request) {
+            Some(entry) if entry.subscriptions.contains(subscriber) => {
+                (!entry.progress && request.should_refetch(entry, now)).then_some(entry.delay)
+            }
+            _ => return false,
+        };
+        if let Some(delay) = fetch {
+            self.fetch_locked(cache, request, delay);
+        }
+        true
+    }
 
-                    let mut websocket = match WebSocket::open(&url) {
-                        Ok(websocket) => websocket,
-                        Err(error) => {
-                            log::error!("Error in WebSocket: {error}");
-                            continue;
-                        }
-                    };
+    /// Current value of this data, or an empty value if there is no entry.
+    fn value_of<R: CacheItem<M>>(&self, request: &R) -> RcValue<R::Value> {
+        self.cache
+            .lock()
+            .expect("Failure to lock cache")
+            .get(request)
+            .and_then(|entry| entry.value.clone().downcast())
+            .unwrap_or_default()
+    }
 
-                    while let Some(message) = websocket.next().await {
-                        let message = match message {
-                            Ok(message) => message,
-                            Err(error) => {
-                                log::error!("Error in WebSocket: {error}");
-                                continue;
-                            },
-                        };
+    /// Current value of this data, without subscribing to it or fetching it.
+    ///
+    /// Returns `None` if there is no entry for this data. This has no side effects, it does not
+    /// count as a use of the entry for [least-recently-used eviction](Cache::evict_lru).
+    pub fn peek<T: CacheItem<M>>(&self, key: &T) -> Option<RcValue<T::Value>> {
+        self.cache
+            .lock()
+            .expect("Failure to lock cache")
+            .entries
+            .get(&*key.cache_key() as &dyn CacheKey<M>)
+            .and_then(|entry| entry.value.clone().downcast())
+    }
 
-                        let text = match message {
-                            Message::Text(text) => text,
-                            _ => unreachable!(),
-                        };
+    /// Metadata of the entry of this data, for debugging.
+    ///
+    /// Like [`Cache::peek`], this has no side effects.
+    pub fn peek_entry<T: CacheItem<M>>(&self, key: &T) -> Option<EntryMetadata> {
+        self.cache
+            .lock()
+            .expect("Failure to lock cache")
+            .entries
+            .get(&*key.cache_key() as &dyn CacheKey<M>)
+            .map(EntryMetadata::from)
+    }
 
-                        let mutation: M = match serde_json::from_str(&text) {
-                            Ok(message) => message,
-                            Err(error) => {
-                                log::error!("Error in WebSocket: {error}");
-                                continue;
-                            }
-                        };
+    /// Current type-erased value of this data.
+    fn raw_value_of<T: CacheItem<M>>(&self, key: &T) -> Option<RcValue> {
+        self.cache
+            .lock()
+            .expect("Failure to lock cache")
+            .get(key)
+            .map(|entry| entry.value.clone())
+    }
 
-                        log::debug!("WebSocket mutation: {mutation:?}");
+    /// Trigger a fetch of this data.
+    ///
+    /// The fetch is performed in the background after the optional `delay`, once it is done the
+    /// result is stored in the cache and broadcast to all subscribers. This does nothing if no
+    /// entry exists for this data. If [`CacheOptions::max_concurrent_fetches`] is reached, the
+    /// fetch is queued until a slot frees up.
+    pub fn fetch<T: CacheItem<M>>(&self, data: &T, delay: Option<Duration>) {
+        let cache = self.cache.lock().expect("Failure to lock cache");
+        self.fetch_locked(cache, data, delay);
+    }
 
-                        cache.invalidate(&mutation);
-                    }
+    /// Fetch this data right away, even if its value is still valid.
+    ///
+    /// Any backoff delay from previous failures is ignored. Returns whether a fetch was started,
+    /// which is not the case if no entry exists for this data or it is already being fetched.
+    pub fn force_fetch<T: CacheItem<M>>(&self, data: &T) -> bool {
+        let cache = self.cache.lock().expect("Failure to lock cache");
+        match cache.get(data) {
+            Some(entry) if !entry.progress => {}
+            _ => return false,
+        }
+        self.fetch_locked(cache, data, None);
+        true
+    }
+
+    /// Fetch this data right away, clearing any terminal failure state, see [`Entry::retry`].
+    ///
+    /// Returns whether a fetch was started, like [`Cache::force_fetch`].
+    pub fn refetch<T: CacheItem<M>>(&self, data: &T) -> bool {
+        let retried = self
+            .cache
+            .lock()
+            .expect("Failure to lock cache")
+            .mutate(data, Entry::retry);
+        retried.is_some() && self.force_fetch(data)
+    }
+
+    /// Trigger a fetch of this data, using the already acquired lock of the cache.
+    ///
+    /// The lock is only released once the entry has been marked as in progress, so that callers
+    /// which checked [`Entry::needs_fetch`] under the same lock cannot race with each other.
+    ///
+    /// If the item is [debounced](CacheItem::debounce), the fetch is queued behind a timer instead,
+    /// which restarts whenever another fetch is requested before it expires.
+    fn fetch_locked<T: CacheItem<M>>(
+        &self,
+        mut cache: MutexGuard<'_, BTreeCache<M>>,
+        data: &T,
+        delay: Option<Duration>,
+    ) {
+        let debounce = data.debounce();
+        let cancel = Rc::new(Cell::new(false));
+        let debounced = cache.mutate(data, |entry| {
+            entry.debounce_duration = debounce;
+            let duration = debounce?;
+            if let Some(previous) = entry.debounce_cancel.replace(cancel.clone()) {
+                previous.set(true);
+            }
+            Some(duration)
+        });
+        match debounced.flatten() {
+            Some(duration) => self.debounce_locked(cache, data, delay, duration, cancel),
+            None => self.start_fetch_locked(cache, data, delay),
+        }
+    }
+
+    /// Start the fetch of this data once the debounce timer expires, unless it was cancelled.
+    fn debounce_locked<T: CacheItem<M>>(
+        &self,
+        cache: MutexGuard<'_, BTreeCache<M>>,
+        data: &T,
+        delay: Option<Duration>,
+        duration: Duration,
+        cancel: Rc<Cell<bool>>,
+    ) {
+        let timer = cache.clock.sleep(duration);
+        drop(cache);
+        let handle = self.clone();
+        let data = data.clone();
+        self.spawn(async move {
+            timer.await;
+            if cancel.get() {
+                return;
+            }
+            let mut cache = handle.cache.lock().expect("Failure to lock cache");
+            let due = cache.mutate(&data, |entry| {
+                let current = entry
+                    .debounce_cancel
+                    .as_ref()
+                    .is_some_and(|current| Rc::ptr_eq(current, &cancel));
+                if current {
+                    entry.debounce_cancel = None;
                 }
+                current && !entry.progress
             });
+            if due == Some(true) {
+                handle.start_fetch_locked(cache, &data, delay);
+            }
+        });
+    }
+
+    /// Mark this data as in progress and queue its fetch, which starts right away if a fetch slot
+    /// is free.
+    fn start_fetch_locked<T: CacheItem<M>>(
+        &self,
+        mut cache: MutexGuard<'_, BTreeCache<M>>,
+        data: &T,
+        delay: Option<Duration>,
+    ) {
+        let priority = data.fetch_priority();
+        let Some((subscribed, started, generation)) = cache.mutate(data, |entry| {
+            let started = !entry.progress;
+            entry.fetch_started();
+            entry.priority = priority;
+            (!entry.subscriptions.is_empty(), started, entry.generation)
+        }) else {
+            return;
+        };
+        if started {
+            cache.fetch_begun();
+        }
+
+        let key = Box::new(data.cache_key().into_owned());
+        let data = data.clone();
+        let start = Box::new(move |cache: &Cache<M>| cache.spawn_fetch(data, delay, generation));
+        cache.enqueue_fetch(key, subscribed, priority, start);
+        let next = cache.next_fetch();
+        drop(cache);
+        if let Some(next) = next {
+            (next.start)(self);
         }
     }
-}
 
-#[derive(Properties)]
-pub struct CacheProviderProps<M: 'static = ()> {
-    pub children: Children,
-    #[prop_or_default]
-    pub cache: Cache<M>,
-}
+    /// Spawn the fetch of this data, which holds a fetch slot.
+    ///
+    /// The result is discarded if a value has been written with [`Cache::set_value`] since the
+    /// fetch was triggered, as indicated by the `generation` of the entry.
+    fn spawn_fetch<T: CacheItem<M>>(&self, data: T, delay: Option<Duration>, generation: u64) {
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!(
+            "cache.fetch",
+            key = ?data,
+            delay = ?delay,
+            outcome = tracing::field::Empty,
+        );
 
-impl<M: 'static> PartialEq<Self> for CacheProviderProps<M> {
-    fn eq(&self, other: &Self) -> bool {
-        self.children.eq(&other.children) && self.cache.eq(&other.cache)
+        let cache = self.clone();
+        let future = async move {
+            if let Some(delay) = delay {
+                cache.sleep(delay).await;
+            }
+            let validator = cache.validator_of(&data);
+            let result = data.send_conditional(validator.as_deref()).await;
+            #[cfg(feature = "tracing")]
+            tracing::Span::current().record(
+                "outcome",
+                if result.is_ok() { "success" } else { "failure" },
+            );
+            if cache.generation_of(&data) != Some(generation) {
+                log::debug!("discarding outdated fetch of {data:?}");
+            } else {
+                match result {
+                    Ok(Conditional::Modified { value, validator }) => {
+                        cache.cache_fetched(&data, Rc::new(value));
+                        cache.set_validator(&data, validator);
+                    }
+                    Ok(Conditional::NotModified) => cache.not_modified(&data),
+                    Err(error) => cache.failure(&data, error),
+                }
+            }
+            cache.fetch_finished();
+        };
+
+        #[cfg(feature = "tracing")]
+        let future = tracing::Instrument::instrument(future, span);
+
+        self.spawn(future);
     }
-}
 
-#[function_component]
-pub fn CacheProvider<M: 'static = ()>(props: &CacheProviderProps<M>) -> Html {
-    html! {
-        <ContextProvider<Cache<M>> context={props.cache.clone()}>
-        { for props.children.iter() }
-        </ContextProvider<Cache<M>>>
+    /// Generation of the entry of this data, see [`Entry::generation`].
+    fn generation_of<T: CacheItem<M>>(&self, data: &T) -> Option<u64> {
+        self.cache
+            .lock()
+            .expect("Failure to lock cache")
+            .get(data)
+            .map(|entry| entry.generation)
     }
-}
 
-#[hook]
-pub fn use_cached<M: 'static, R: CacheItem<M>>(data: R) -> RcValue<R::Value>
-where
-    R::Value: PartialEq,
-{
-    log::debug!("use_data({data:?})");
-    let cache = use_context::<Cache<M>>().expect("Cache not present");
-    let state = use_state(|| RcValue::default());
-    let state_clone = state.clone();
-    use_effect(move || {
-        cache.subscribe(&data, state_clone.clone());
-        move || {
-            cache.unsubscribe(&data, &state_clone.setter());
+    /// Validator of the value of this data, see [`Entry::validator`].
+    fn validator_of<T: CacheItem<M>>(&self, data: &T) -> Option<String> {
+        self.cache
+            .lock()
+            .expect("Failure to lock cache")
+            .get(data)
+            .and_then(|entry| entry.validator.clone())
+    }
+
+    /// Store the validator of the value of this data.
+    fn set_validator<T: CacheItem<M>>(&self, data: &T, validator: Option<String>) {
+        let _ = self
+            .cache
+            .lock()
+            .expect("Failure to lock cache")
+            .mutate(data, |entry| entry.validator = validator);
+    }
+
+    /// Handle a conditional fetch whose value has not been modified.
+    ///
+    /// The cached value is marked as valid again, without replacing it, and broadcast to all
+    /// subscribers, whose fetch listeners are called with it. If the entry has no value to
+    /// revalidate, its validator is dropped so that the next fetch is unconditional.
+    pub fn not_modified<T: CacheItem<M>>(&self, data: &T) {
+        let mut cache = self.cache.lock().expect("Failure to lock cache");
+        let now = cache.clock.now();
+        let Some((in_progress, value, listeners)) = cache.mutate(data, |entry| {
+            let in_progress = entry.progress;
+            entry.fetch_succeeded(now);
+            entry.apply_hints(&data.cache_control());
+            entry.resume();
+            if !entry.value.revalidate() {
+                entry.validator = None;
+            }
+            entry.broadcast();
+            let value = entry.value.data().cloned();
+            (in_progress, value, entry.listeners.clone())
+        }) else {
+            return;
+        };
+        if in_progress {
+            cache.fetch_done();
         }
-    });
-    let value = (*state).clone();
-    value.downcast().expect("Value is of wrong type")
+        drop(cache);
+
+        let Some(value) = value else {
+            return;
+        };
+        for (_, listener) in listeners {
+            listener.emit_value(&value, true);
+        }
+    }
+
+    /// Release the slot of a completed fetch, starting the next pending fetch.
+    fn fetch_finished(&self) {
+        let next = self
+            .cache
+            .lock()
+            .expect("Failure to lock cache")
+            .release_fetch();
+        if let Some(next) = next {
+            (next.start)(self);
+        }
+    }
+
+    /// Prefetch this data without subscribing to it.
+    ///
+    /// Creates the entry if it does not exist yet and fetches it in the background, so that later
+    /// subscribers can use the value without triggering another request. Does nothing if the entry
+    /// already has a valid value or is currently being fetched. If previous fetches of this data
+    /// have failed, the current backoff delay is respected.
+    pub fn prefetch<T: CacheItem<M>>(&self, data: T) {
+        let mut cache = self.cache.lock().expect("Failure to lock cache");
+        let delay = match cache.get(&data) {
+            None => {
+                cache.insert(data.clone(), Entry::default());
+                None
+            }
+            Some(entry) if entry.needs_fetch(cache.clock.now()) => entry.delay,
+            Some(_) => return,
+        };
+        self.fetch_locked(cache, &data, delay);
+    }
+
+    /// Ensure an entry exists for this data, fetching it if needed.
+    ///
+    /// This is the building block for warming the cache, for example when the route changes, and
+    /// behaves like [`Cache::prefetch`]: a new entry is marked as in progress and fetched without
+    /// a subscriber. Returns whether the entry was created.
+    pub fn insert_entry_if_absent<T: CacheItem<M>>(&self, key: T) -> bool {
+        let created = !self.contains(&key);
+        self.prefetch(key);
+        created
+    }
+
+    /// Seed the entry of this data with an initial value.
+    ///
+    /// The value is stored as stale, so that it is shown immediately but still refetched, and
+    /// broadcast to all subscribers. This never overwrites an existing value, so the first seed
+    /// wins. Returns whether the value was seeded.
+    pub fn seed<T: CacheItem<M>>(&self, data: &T, value: T::Value) -> bool {
+        let mut value = RcValue::new(Rc::new(value) as Rc<dyn Any>);
+        value.invalidate();
+        let mut cache = self.cache.lock().expect("Failure to lock cache");
+        let key = data.cache_key();
+        let Some(entry) = cache.entries.get_mut(&*key as &dyn CacheKey<M>) else {
+            cache.insert(
+                data.clone(),
+                Entry {
+                    value,
+                    value_type: Some(std::any::type_name::<T::Value>()),
+                    ..Default::default()
+                },
+            );
+            return true;
+        };
+        if entry.value.data().is_some() {
+            return false;
+        }
+        entry.value = value;
+        entry.value_type = Some(std::any::type_name::<T::Value>());
+        entry.broadcast();
+        true
+    }
+
+    /// Handle failure.
+    ///
+    /// Increases the delay for the next fetch and notifies subscribers. Unless disabled by
+    /// [`CacheOptions::keep_stale_on_failure`], the last known value is kept. The error is stored
+    /// on the entry until a fetch succeeds, see [`Cache::error_of`]. Once the entry has failed more
+    /// often than [`CacheOptions::max_retries`] allows, it is failed terminally.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "cache.failure", skip_all, fields(key = ?data, error = %error))
+    )]
+    pub fn failure<T: CacheItem<M>>(&self, data: &T, error: T::Error) {
+    log::error!("error fetching {data:?}: {error}");
+        let mut cache = self.cache.lock().expect("Failure to lock cache");
+        let keep_stale = cache.options.keep_stale_on_failure;
+        let breaker = cache.options.circuit_breaker;
+        let max_retries = data.max_retries().or(cache.options.max_retries);
+        let backoff = data.backoff().unwrap_or(cache.options.backoff);
+        let now = cache.clock.now();
+        let last_error = format!("{error:?}");
+        let error = Rc::new(error);
+        let stored: Rc<dyn Any> = error.clone();
+        let Some((in_progress, listeners)) = cache.mutate(data, move |entry| {
+            let in_progress = entry.progress;
+            entry.backoff = backoff;
+            entry.fetch_failed(now, breaker.as_ref());
+            if max_retries.is_some_and(|max_retries| entry.failures > max_retries) {
+                log::warn!("giving up on {data:?} after {} failures", entry.failures);
+                entry.terminal = true;
+            }
+            entry.last_error = Some(last_error);
+            entry.error = Some(stored);
+            entry.last_error_count += 1;
+            if !keep_stale {
+                entry.value = Default::default();
+                entry.validator = None;
+            }
+            entry.broadcast();
+            entry.resume();
+            (in_progress, entry.listeners.clone())
+        }) else {
+            return;
+        };
+        if in_progress {
+            cache.fetch_done();
+        }
+        drop(cache);
+
+        for (_, listener) in listeners {
+            if let Some(on_error) = listener.on_error {
+                on_error.emit(error.clone() as Rc<dyn std::error::Error>);
+            }
+        }
+    }
+
+    /// Cache this data.
+    ///
+    /// Stores the value for an existing entry and broadcasts it to all subscribers. The value is
+    /// normalized with [`CacheItem::transform_response`] first, and merged with the current value
+    /// of the entry, if any, using [`CacheItem::resolve`]. If the entry already holds an equal
+    /// valid value, subscribers are not notified. The [`on_success`](QueryOptions::on_success)
+    /// callbacks of the subscribers are not called, since the value does not come from a fetch.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "cache.cache", skip_all, fields(key = ?data))
+    )]
+    pub fn cache<T: CacheItem<M>>(&self, data: &T, value: Rc<T::Value>) {
+        self.store(data, value, false);
+    }
+
+    /// Cache the result of a successful fetch of this data, see [`Cache::cache`].
+    ///
+    /// Unlike [`Cache::cache`], this calls the `on_success` callbacks of the subscribers.
+    pub(super) fn cache_fetched<T: CacheItem<M>>(&self, data: &T, value: Rc<T::Value>) {
+        self.store(data, value, true);
+    }
+
+    /// Store this value of this data, calling the fetch listeners if it has been `fetched`.
+    fn store<T: CacheItem<M>>(&self, data: &T, value: Rc<T::Value>, fetched: bool) {
+        let value = Rc::try_unwrap(value).unwrap_or_else(|value| (*value).clone());
+        let value = data.transform_response(value);
+        let existing = self
+            .raw_value_of(data)
+            .and_then(|existing| existing.data()?.clone().downcast::<T::Value>().ok());
+        let value = Rc::new(match existing {
+            Some(existing) => data.resolve(&existing, value),
+            None => value,
+        });
+        let mut cache = self.cache.lock().expect("Failure to lock cache");
+        let now = cache.clock.now();
+        let stored = value.clone();
+        let Some((in_progress, listeners)) = cache.mutate(data, move |entry| {
+            let in_progress = entry.progress;
+            entry.fetch_succeeded(now);
+            entry.apply_hints(&data.cache_control());
+            entry.resume();
+            if !entry.value.valid() || !entry.value.value_eq::<T::Value>(&stored) {
+                entry.value = RcValue::new(stored as Rc<dyn Any>);
+                entry.value_type = Some(std::any::type_name::<T::Value>());
+                entry.broadcast();
+            }
+            (in_progress, entry.listeners.clone())
+        }) else {
+            return;
+        };
+        if in_progress {
+            cache.fetch_done();
+        }
+        drop(cache);
+        #[cfg(feature = "storage")]
+        self.store_persisted(data, &value);
+
+        let value: Rc<dyn Any> = value;
+        for (_, listener) in listeners {
+            listener.emit_value(&value, fetched);
+        }
+    }
+
+    /// Set the value of this data, creating the entry if it does not exist.
+    ///
+    /// This is the way to populate or update the cache from sources other than a fetch, such as
+    /// WebSocket push updates, see [`Cache::set_value`].
+    pub fn set<T: CacheItem<M>>(&self, key: T, value: T::Value) {
+        self.set_value(&key, value);
+    }
+
+    /// Set the value of this data, creating the entry if it does not exist.
+    ///
+    /// The value is stored as valid and broadcast like the result of a fetch, see [`Cache::cache`].
+    /// Accepts either a value or an `Rc` of it, to avoid a clone. The backoff delay is reset.
+    ///
+    /// The later write wins: if a fetch of this data is in flight, its result is discarded once it
+    /// completes, since it may be older than this value. The value itself is still merged with the
+    /// current one using [`CacheItem::resolve`].
+    /// The [validator](Entry::validator) of the previous value is dropped.
+    pub fn set_value<T: CacheItem<M>>(&self, key: &T, value: impl Into<Rc<T::Value>>) {
+        let mut cache = self.cache.lock().expect("Failure to lock cache");
+        if cache.get(key).is_none() {
+            cache.insert(key.clone(), Entry::default());
+        }
+        cache.generation += 1;
+        let generation = cache.generation;
+        let in_progress = cache.mutate(key, |entry| {
+            entry.generation = generation;
+            entry.validator = None;
+            std::mem::take(&mut entry.progress)
+        });
+        if in_progress == Some(true) {
+            cache.fetch_done();
+        }
+        drop(cache);
+        self.cache(key, value.into());
+    }
+
+    /// Debug representation of the error of the last failed fetch of this data.
+    ///
+    /// This is reset once a fetch succeeds.
+    pub fn last_error<T: CacheItem<M>>(&self, data: &T) -> Option<String> {
+        self.cache
+            .lock()
+            .expect("Failure to lock cache")
+            .get(data)
+            .and_then(|entry| entry.last_error.clone())
+    }
+
+    /// Transform the cached value of this data in place.
+    ///
+    /// The result of `f` is stored as the new value and broadcast to all subscribers, keeping the
+    /// validity of the current value. It remains the value of the entry until the next fetch
+    /// replaces it. Does nothing if there is no cached value, or if it has the wrong type.
+    pub fn apply<T: CacheItem<M>, F: FnOnce(&T::Value) -> T::Value>(&self, key: &T, f: F) {
+        self.update_value(key, f);
+    }
+
+    /// Update the cached value of this data with a closure.
+    ///
+    /// Behaves like [`Cache::apply`]. The result is stored in a new `Rc`, so holders of the current
+    /// value are not affected. Returns false, doing nothing, if there is no cached value or if it
+    /// has the wrong type.
+    pub fn update_value<T: CacheItem<M>>(
+        &self,
+        key: &T,
+        f: impl FnOnce(&T::Value) -> T::Value,
+    ) -> bool {
+        self.cache
+            .lock()
+            .expect("Failure to lock cache")
+            .mutate(key, |entry| {
+                let Some(current) = entry.value.clone().downcast::<T::Value>() else {
+                    return false;
+                };
+                let Some(data) = current.data() else {
+                    return false;
+                };
+                let mut value = RcValue::new(Rc::new(f(data)) as Rc<dyn Any>);
+                if !entry.value.valid() {
+                    value.invalidate();
+                }
+                entry.value = value;
+                entry.broadcast();
+                true
+            })
+            .unwrap_or(false)
+    }
+
+    /// Patch the cached value of this data in place.
+    ///
+    /// The value is only cloned if it is shared, for example with subscribed components, so that
+    /// they are not mutated behind their backs. The result is stored with the validity of the
+    /// current value and broadcast to all subscribers. Returns false, doing nothing, if there is
+    /// no cached value or if it has the wrong type.
+    pub fn update<T: CacheItem<M>, F: FnOnce(&mut T::Value)>(&self, key: &T, f: F) -> bool {
+        self.cache
+            .lock()
+            .expect("Failure to lock cache")
+            .mutate(key, |entry| {
+                if !entry.value.data().is_some_and(|data| data.is::<T::Value>()) {
+                    return false;
+                }
+                let current = std::mem::take(&mut entry.value);
+                let valid = current.valid();
+                let data = current.data().cloned().expect("Value has data");
+                drop(current);
+                let mut data = data.downcast::<T::Value>().expect("Value is of wrong type");
+                f(Rc::make_mut(&mut data));
+                entry.value = RcValue::new(data as Rc<dyn Any>);
+                if !valid {
+                    entry.value.invalidate();
+                }
+                entry.broadcast();
+                true
+            })
+            .unwrap_or(false)
+    }
+
+    /// Unsubscribe to the value of this data.
+    ///
+    /// Once the last subscriber is gone, the [push source](Cache::attach_source) of the entry is
+    /// disconnected, and entries marked as [`no_store`](CacheControlHints::no_store) are removed.
+    pub fn unsubscribe<T: CacheItem<M>>(&self, data: &T, subscriber: &Subscriber) {
+        let mut cache = self.cache.lock().expect("Failure to lock cache");
+        let Some((connection, discard)) = cache.mutate(data, |entry| {
+            entry.unsubscribe(subscriber);
+            let discard = entry.no_store && entry.subscriptions.is_empty();
+            (entry.disconnect_if_unsubscribed(), discard)
+        }) else {
+            return;
+        };
+        let removed = discard.then(|| cache.remove(data));
+        drop(cache);
+        drop((connection, removed));
+    }
+
+    /// Invalidate this invalidation.
+    ///
+    /// Marks every entry whose key is [invalidated by](crate::Invalidatable::invalidated_by) this
+    /// mutation as invalid. Subscribed components will refetch it, without waiting for the backoff
+    /// of earlier failures, see [`Entry::retry`]. If cross-tab sync is enabled, the mutation is
+    /// posted to the other tabs.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "cache.invalidate", skip_all)
+    )]
+    pub fn invalidate(&self, mutation: &M) {
+        self.invalidate_local(mutation);
+        #[cfg(feature = "cross-tab")]
+        self.post_sync(mutation);
+    }
+
+    /// Invalidate this invalidation in this cache only, without posting it to other tabs.
+    fn invalidate_local(&self, mutation: &M) {
+        let mut broadcast = Broadcast::default();
+        let mut cache = self.cache.lock().expect("Failure to lock cache");
+        for (key, entry) in cache.iter_mut() {
+            if key.invalidated_by(mutation) {
+                entry.value.invalidate();
+                entry.retry();
+                entry.broadcast_into(&mut broadcast);
+            }
+        }
+        drop(cache);
+        broadcast.send();
+    }
+
+    /// Invalidate the entries affected by the mutations of this invalidator.
+    ///
+    /// Call this after a successful write, to invalidate exactly the entries affected by it. See
+    /// [`Cache::invalidate`].
+    pub fn apply_mutation<I: Invalidator<M>>(&self, invalidator: &I) {
+        for mutation in invalidator.mutations() {
+            self.invalidate(&mutation);
+        }
+    }
+
+    /// Describe the entries which would be invalidated by the mutations of this invalidator.
+    ///
+    /// Returns the debug representation of the affected keys, which is useful to show the
+    /// consequences of an action to users.
+    pub fn would_invalidate<T: Invalidator<M>>(&self, invalidator: &T) -> Vec<String> {
+        let cache = self.cache.lock().expect("Failure to lock cache");
+        invalidator
+            .invalidates(cache.entries.keys())
+            .into_iter()
+            .map(|key| format!("{key:?}"))
+            .collect()
+    }
+
+    /// State of the circuit breaker for this data, if an entry exists.
+    pub fn circuit_state<T: CacheItem<M>>(&self, data: &T) -> Option<CircuitState> {
+        self.cache
+            .lock()
+            .expect("Failure to lock cache")
+            .get(data)
+            .map(|entry| entry.circuit)
+    }
+
+    /// Invalidate this key.
+    ///
+    /// Marks the entry for this data as invalid, if it exists.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "cache.invalidate_key", skip_all, fields(key = ?data))
+    )]
+    pub fn invalidate_key<T: CacheItem<M>>(&self, data: &T) {
+        self.invalidate_erased(&*data.cache_key());
+    }
+
+    /// Invalidate the entry of this type-erased key, see [`Cache::invalidate_key`].
+    ///
+    /// Returns whether the entry exists.
+    pub fn invalidate_erased(&self, key: &dyn CacheKey<M>) -> bool {
+        let mut cache = self.cache.lock().expect("Failure to lock cache");
+        let Some(entry) = cache.entries.get_mut(key) else {
+            return false;
+        };
+        entry.value.invalidate();
+        entry.retry();
+        entry.broadcast();
+        true
+    }
+
+    /// Invalidates entire cache.
+    ///
+    /// Marks every entry as invalid, but keeps the current values. Subscribers keep showing them
+    /// while they refetch. Use [`Cache::clear`] to discard the values instead. Subscribers are
+    /// notified once the cache is unlocked, and only once even if they subscribe to several
+    /// entries.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "cache.invalidate_all", skip_all)
+    )]
+    pub fn invalidate_all(&self) {
+        let mut broadcast = Broadcast::default();
+        let mut cache = self.cache.lock().expect("Failure to lock cache");
+        for entry in cache.entries.values_mut() {
+            entry.value.invalidate();
+            entry.retry();
+            entry.broadcast_into(&mut broadcast);
+        }
+        drop(cache);
+        broadcast.send();
+    }
+
+    /// Invalidate every entry whose key is of type `T`, returning the number of entries.
+    ///
+    /// Like [`Cache::invalidate_all`], the current values are kept while subscribers refetch them.
+    pub fn invalidate_all_of_type<T: CacheKey<M>>(&self) -> usize {
+        let mut broadcast = Broadcast::default();
+        let mut cache = self.cache.lock().expect("Failure to lock cache");
+        let count = cache
+            .iter_mut()
+            .filter(|(key, _)| key.any().is::<T>())
+            .map(|(_, entry)| {
+                entry.value.invalidate();
+                entry.retry();
+                entry.broadcast_into(&mut broadcast);
+            })
+            .count();
+        drop(cache);
+        broadcast.send();
+        count
+    }
+
+    /// Remove all entries, discarding their values.
+    ///
+    /// Every subscriber is notified with an empty value, then all entries are dropped including
+    /// their subscriptions. Components subscribe again and refetch on their next render. The
+    /// results of fetches which are in flight are discarded. Unlike [`Cache::invalidate_all`], no
+    /// data is kept, which makes this suitable for logging out. The [persisted](Cache::persist)
+    /// values of the entries are removed from the storage as well.
+    pub fn clear(&self) {
+        let entries = self.cache.lock().expect("Failure to lock cache").clear();
+        #[cfg(feature = "storage")]
+        for key in entries.keys() {
+            self.remove_persisted(key.as_ref());
+        }
+        for entry in entries.values() {
+            for subscriber in &entry.subscriptions {
+                subscriber.notify(RcValue::default());
+            }
+        }
+    }
+
+    /// Remove the entry of this data.
+    ///
+    /// Subscribers are notified with an empty value first, so that they do not keep showing the
+    /// removed data. The result of a fetch which is in flight is discarded, see
+    /// [`BTreeCache::remove`]. A [persisted](Cache::persist) value is removed from the storage.
+    /// Returns whether the entry existed.
+    pub fn remove<T: CacheItem<M>>(&self, data: &T) -> bool {
+        self.remove_erased(&*data.cache_key())
+    }
+
+    /// Remove the entry of this type-erased key, see [`Cache::remove`].
+    pub fn remove_erased(&self, key: &dyn CacheKey<M>) -> bool {
+        let entry = self
+            .cache
+            .lock()
+            .expect("Failure to lock cache")
+            .remove_erased(key);
+        #[cfg(feature = "storage")]
+        self.remove_persisted(key);
+        let Some(entry) = entry else {
+            return false;
+        };
+        for subscriber in &entry.subscriptions {
+            subscriber.notify(RcValue::default());
+        }
+        true
+    }
+
+    /// Number of entries which are currently being fetched, including queued fetches.
+    pub fn in_flight(&self) -> usize {
+        self.cache.lock().expect("Failure to lock cache").in_flight
+    }
+
+    /// Subscribe to changes of the number of entries being fetched.
+    pub fn subscribe_activity(&self, subscriber: &Callback<()>) {
+        let mut cache = self.cache.lock().expect("Failure to lock cache");
+        if !cache.activity.contains(subscriber) {
+            cache.activity.push(subscriber.clone());
+        }
+    }
+
+    /// Unsubscribe from changes of the number of entries being fetched.
+    pub fn unsubscribe_activity(&self, subscriber: &Callback<()>) {
+        self.cache
+            .lock()
+            .expect("Failure to lock cache")
+            .activity
+            .retain(|current| current != subscriber);
+    }
+
+    /// Determine if an entry exists for this data, regardless of whether it is valid.
+    pub fn contains<T: CacheItem<M>>(&self, data: &T) -> bool {
+        self.cache
+            .lock()
+            .expect("Failure to lock cache")
+            .get(data)
+            .is_some()
+    }
+
+    /// Determine if an entry with a valid value exists for this data.
+    pub fn contains_valid<T: CacheItem<M>>(&self, data: &T) -> bool {
+        self.cache
+            .lock()
+            .expect("Failure to lock cache")
+            .get(data)
+            .map(|entry| entry.value.valid())
+            .unwrap_or(false)
+    }
+
+    /// Number of entries in the cache.
+    pub fn len(&self) -> usize {
+        self.cache
+            .lock()
+            .expect("Failure to lock cache")
+            .entries
+            .len()
+    }
+
+    /// Determine if the cache has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.cache
+            .lock()
+            .expect("Failure to lock cache")
+            .entries
+            .is_empty()
+    }
+
+    /// Statistics about the current contents of the cache.
+    pub fn statistics(&self) -> CacheStatistics {
+        let now = self.now();
+        self.cache
+            .lock()
+            .expect("Failure to lock cache")
+            .statistics(now)
+    }
+
+    /// Evict the least recently used entries, see [`BTreeCache::evict_lru`].
+    pub fn evict_lru(&self, capacity: usize) -> usize {
+        self.cache
+            .lock()
+            .expect("Failure to lock cache")
+            .evict_lru(capacity)
+    }
+
+    /// Evict entries whose value has expired, see [`BTreeCache::evict_expired`].
+    pub fn evict_expired(&self) -> usize {
+        let now = self.now();
+        self.cache
+            .lock()
+            .expect("Failure to lock cache")
+            .evict_expired(now)
+    }
+
+    /// Start a background task which periodically evicts expired entries.
+    ///
+    /// Replaces the currently running eviction task, if any.
+    pub fn start_eviction_task(&self, interval: Duration) {
+        let cancel = Rc::new(Cell::new(false));
+        let previous = self
+            .cache
+            .lock()
+            .expect("Failure to lock cache")
+            .eviction
+            .replace(cancel.clone());
+        if let Some(previous) = previous {
+            previous.set(true);
+        }
+
+        let cache = self.clone();
+        self.spawn(async move {
+            loop {
+                cache.sleep(interval).await;
+                if cancel.get() {
+                    break;
+                }
+                cache.evict_expired();
+            }
+        });
+    }
+
+    /// Stop the running eviction task, if any.
+    pub fn stop_eviction_task(&self) {
+        let current = self
+            .cache
+            .lock()
+            .expect("Failure to lock cache")
+            .eviction
+            .take();
+        if let Some(current) = current {
+            current.set(true);
+        }
+    }
+}
+
+mod builder;
+mod callbacks;
+mod clock;
+#[cfg(feature = "cross-tab")]
+mod cross_tab;
+#[cfg(feature = "devtools")]
+mod devtools;
+mod entries;
+mod errors;
+#[cfg(feature = "debug")]
+mod explain;
+mod focus;
+mod invalidate;
+mod mutation;
+mod pages;
+mod polling;
+mod push;
+mod revalidate;
+mod scope;
+mod snapshot;
+mod stats;
+mod status;
+#[cfg(feature = "storage")]
+mod storage;
+mod suspense;
+#[cfg(feature = "test-util")]
+mod test_util;
+mod tuple;
+mod wait;
+
+pub use builder::*;
+pub use callbacks::*;
+pub use clock::*;
+#[cfg(feature = "cross-tab")]
+pub use cross_tab::*;
+#[cfg(feature = "devtools")]
+pub use devtools::*;
+pub use errors::*;
+#[cfg(feature = "debug")]
+pub use explain::*;
+pub use focus::*;
+pub use invalidate::*;
+pub use mutation::*;
+pub use pages::*;
+pub use polling::*;
+pub use push::*;
+pub use revalidate::*;
+pub use scope::*;
+pub use snapshot::*;
+pub use stats::*;
+pub use status::*;
+#[cfg(feature = "storage")]
+pub use storage::*;
+pub use suspense::*;
+#[cfg(feature = "test-util")]
+pub use test_util::*;
+pub use tuple::*;
+
+#[cfg(feature = "websocket")]
+mod websocket {
+    use super::*;
+    use serde::de::DeserializeOwned;
+    use std::{time::Duration, fmt::Debug};
+    use gloo_net::websocket::{Message, futures::WebSocket};
+    use futures::StreamExt;
+
+    impl<M: 'static + DeserializeOwned + Debug> Cache<M> {
+        pub fn websocket_listener(&self, url: &str) {
+            let url = url.to_string();
+            let cache = self.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let mut first = true;
+                loop {
+                    if !first {
+                        prokio::time::sleep(Duration::from_secs(1)).await;
+                    } else {
+                        first = false;
+                    }
+
+                    let mut websocket = match WebSocket::open(&url) {
+                        Ok(websocket) => websocket,
+                        Err(error) => {
+                            log::error!("Error in WebSocket: {error}");
+                            continue;
+                        }
+                    };
+
+                    while let Some(message) = websocket.next().await {
+                        let message = match message {
+                            Ok(message) => message,
+                            Err(error) => {
+                                log::error!("Error in WebSocket: {error}");
+                                continue;
+                            },
+                        };
+
+                        let text = match message {
+                            Message::Text(text) => text,
+                            _ => unreachable!(),
+                        };
+
+                        let mutation: M = match serde_json::from_str(&text) {
+                            Ok(message) => message,
+                            Err(error) => {
+                                log::error!("Error in WebSocket: {error}");
+                                continue;
+                            }
+                        };
+
+                        log::debug!("WebSocket mutation: {mutation:?}");
+
+                        cache.invalidate(&mutation);
+                    }
+                }
+            });
+        }
+    }
+
+    impl<M: 'static> Cache<M> {
+        /// Attach a WebSocket as the [push source](Cache::attach_source) of this data.
+        ///
+        /// While the data has subscribers, the WebSocket is kept open and every text message is
+        /// deserialized as JSON and pushed as the value of the data. The WebSocket is closed once
+        /// the last subscriber is gone.
+        pub fn attach_websocket<T>(&self, key: &T, url: &str)
+        where
+            T: CacheItem<M>,
+            T::Value: DeserializeOwned,
+        {
+            let url = url.to_string();
+            self.attach_source(key, move |sink| {
+                let url = url.clone();
+                let (future, handle) = futures::future::abortable(async move {
+                    let mut websocket = match WebSocket::open(&url) {
+                        Ok(websocket) => websocket,
+                        Err(error) => {
+                            log::error!("Error in WebSocket: {error}");
+                            return;
+                        }
+                    };
+                    while let Some(message) = websocket.next().await {
+                        let text = match message {
+                            Ok(Message::Text(text)) => text,
+                            Ok(Message::Bytes(_)) => continue,
+                            Err(error) => {
+                                log::error!("Error in WebSocket: {error}");
+                                continue;
+                            }
+                        };
+                        match serde_json::from_str(&text) {
+                            Ok(value) => {
+                                sink.push(value);
+                            }
+                            Err(error) => log::error!("Error in WebSocket: {error}"),
+                        }
+                    }
+                });
+                wasm_bindgen_futures::spawn_local(async move {
+                    let _ = future.await;
+                });
+                AbortOnDrop(handle)
+            });
+        }
+    }
+
+    /// Connection of a WebSocket source, which closes it when dropped.
+    struct AbortOnDrop(futures::future::AbortHandle);
+
+    impl Drop for AbortOnDrop {
+        fn drop(&mut self) {
+            self.0.abort();
+        }
+    }
+}
+
+#[derive(Properties)]
+pub struct CacheProviderProps<M: 'static = ()> {
+    pub children: Children,
+    #[prop_or_default]
+    pub cache: Cache<M>,
+    /// Interval at which expired entries are evicted from the cache.
+    #[prop_or_default]
+    pub eviction_interval: Option<Duration>,
+    /// Refetch stale data when the window regains focus, see [`Cache::enable_focus_refetch`].
+    #[prop_or_default]
+    pub refetch_on_focus: bool,
+    /// How the cache relates to the cache of a surrounding provider, see [`CacheScope`].
+    #[prop_or_default]
+    pub scope: CacheScope<M>,
+}
+
+impl<M: 'static> PartialEq<Self> for CacheProviderProps<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.children.eq(&other.children)
+            && self.cache.eq(&other.cache)
+            && self.eviction_interval.eq(&other.eviction_interval)
+            && self.refetch_on_focus.eq(&other.refetch_on_focus)
+            && self.scope.eq(&other.scope)
+    }
+}
+
+#[function_component]
+pub fn CacheProvider<M: 'static = ()>(props: &CacheProviderProps<M>) -> Html {
+    use_effect_with_deps(
+        |(cache, interval)| {
+            if let Some(interval) = interval {
+                cache.start_eviction_task(*interval);
+            }
+            let cache = cache.clone();
+            move || cache.stop_eviction_task()
+        },
+        (props.cache.clone(), props.eviction_interval),
+    );
+    use_effect_with_deps(
+        |(cache, refetch_on_focus)| {
+            if *refetch_on_focus {
+                cache.enable_focus_refetch();
+            }
+            let cache = cache.clone();
+            move || cache.disable_focus_refetch()
+        },
+        (props.cache.clone(), props.refetch_on_focus),
+    );
+    // linked while rendering, since children subscribe in their effects which run first
+    use_memo(
+        |(cache, scope)| cache.set_scope(scope),
+        (props.cache.clone(), props.scope.clone()),
+    );
+    use_effect_with_deps(
+        |cache| {
+            let cache = cache.clone();
+            move || cache.set_scope(&CacheScope::Isolated)
+        },
+        props.cache.clone(),
+    );
+
+    html! {
+        <ContextProvider<Cache<M>> context={props.cache.clone()}>
+        { for props.children.iter() }
+        </ContextProvider<Cache<M>>>
+    }
+}
+
+/// Get a handle to the [`Cache`] of the surrounding [`CacheProvider`].
+///
+/// This is the supported way of accessing the cache imperatively, for example to invalidate
+/// entries from within callbacks.
+///
+/// ```rust
+/// # use wasm_cache::yew::*;
+/// # use yew::prelude::*;
+/// #[function_component]
+/// fn Refresh() -> Html {
+///     let cache = use_cache::<()>();
+///     let onclick = move |_| cache.invalidate_all();
+///     html! {
+///         <button {onclick}>{"Refresh"}</button>
+///     }
+/// }
+/// ```
+///
+/// # Panics
+///
+/// Panics when called from a component that is not wrapped in a [`CacheProvider`]. Use
+/// [`use_cache_or_global`] to fall back to the global cache instead.
+#[hook]
+pub fn use_cache<M: 'static>() -> Cache<M> {
+    use_context::<Cache<M>>().unwrap_or_else(|| panic!("{}", missing_provider::<M>()))
+}
+
+/// Message for a missing [`CacheProvider`], naming the mutation type.
+fn missing_provider<M: 'static>() -> String {
+    format!(
+        "Cache not present, wrap this component in a CacheProvider<{}>",
+        std::any::type_name::<M>()
+    )
+}
+
+/// Get a callback which prefetches this data.
+///
+/// The callback ignores its argument, so it can be used directly as an event handler, such as
+/// `onmouseover` or `onfocus`. See [`Cache::prefetch`] for details.
+///
+/// ```rust
+/// # use wasm_cache::{yew::*, CacheItem, Invalidatable};
+/// # use yew::prelude::*;
+/// # #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// # struct ProjectTasks(u64);
+/// # impl Invalidatable<()> for ProjectTasks {}
+/// # #[async_trait::async_trait(?Send)]
+/// # impl CacheItem for ProjectTasks {
+/// #     type Value = Vec<String>;
+/// #     type Error = std::fmt::Error;
+/// #     async fn send(&self) -> Result<Self::Value, Self::Error> {
+/// #         Ok(vec![])
+/// #     }
+/// # }
+/// #[function_component]
+/// fn ProjectCard() -> Html {
+///     let onmouseover = use_prefetch::<(), _, _>(ProjectTasks(1));
+///     html! {
+///         <div {onmouseover}>{"Project"}</div>
+///     }
+/// }
+/// ```
+#[hook]
+pub fn use_prefetch<M: 'static, R: CacheItem<M>, E: 'static>(data: R) -> Callback<E> {
+    let cache = use_cache::<M>();
+    use_callback(
+        |_event: E, (cache, data)| cache.prefetch(data.clone()),
+        (cache, data),
+    )
+}
+
+/// Get a handle to the [`Cache`] of the surrounding [`CacheProvider`], if any, or else to the
+/// [global cache](Cache::global).
+///
+/// A warning is logged the first time the global cache is used for a mutation type. Components
+/// use the provider as soon as one is mounted above them.
+#[hook]
+pub fn use_cache_or_global<M: 'static>() -> Cache<M> {
+    cache_or_global(use_context::<Cache<M>>())
+}
+
+/// Use the cache of the provider, falling back to the global cache with a warning.
+fn cache_or_global<M: 'static>(provided: Option<Cache<M>>) -> Cache<M> {
+    thread_local! {
+        static WARNED: RefCell<Vec<TypeId>> = const { RefCell::new(Vec::new()) };
+    }
+    if let Some(cache) = provided {
+        return cache;
+    }
+    let first = WARNED.with(|warned| {
+        let mut warned = warned.borrow_mut();
+        let first = !warned.contains(&TypeId::of::<M>());
+        if first {
+            warned.push(TypeId::of::<M>());
+        }
+        first
+    });
+    if first {
+        log::warn!("{}, using the global cache", missing_provider::<M>());
+    }
+    Cache::global()
+}
+
+#[hook]
+pub fn use_cached<M: 'static, R: CacheItem<M>>(data: R) -> RcValue<R::Value> {
+    log::debug!("use_data({data:?})");
+    let cache = use_cache::<M>();
+    use_cached_in(cache, data)
+}
+
+/// Use cached data, without requiring a [`CacheProvider`].
+///
+/// Behaves like [`use_cached`], but falls back to the [global cache](Cache::global) when there is
+/// no [`CacheProvider`]. This is useful for small applications, which do not need isolated caches.
+#[hook]
+pub fn use_global_cached<M: 'static, R: CacheItem<M>>(data: R) -> RcValue<R::Value> {
+    log::debug!("use_global_cached({data:?})");
+    let cache = use_cache_or_global::<M>();
+    use_cached_in(cache, data)
+}
+
+/// Use cached data from this cache.
+#[hook]
+fn use_cached_in<M: 'static, R: CacheItem<M>>(cache: Cache<M>, data: R) -> RcValue<R::Value> {
+    // start out with the current value, so that stale data is rendered right away on mount
+    let state = {
+        let cache = cache.clone();
+        let data = data.clone();
+        use_state(move || cache.raw_value_of(&data).unwrap_or_default())
+    };
+    let state_clone = state.clone();
+    use_effect(move || {
+        cache.subscribe(&data, state_clone.clone(), true);
+        move || {
+            cache.unsubscribe(&data, &state_clone.setter().into());
+        }
+    });
+    let value = (*state).clone();
+    value.downcast().expect("Value is of wrong type")
+}
+
+/// Use cached data, seeding it with initial data.
+///
+/// If the entry has no data yet, it is [seeded](Cache::seed) with `initial`, so that the
+/// component renders it immediately while the data is revalidated. This is useful when the value
+/// is already known, for example from a list view. Existing values are never overwritten.
+#[hook]
+pub fn use_cached_with_initial<M: 'static, R: CacheItem<M>>(
+    data: R,
+    initial: Option<R::Value>,
+) -> RcValue<R::Value> {
+    log::debug!("use_cached_with_initial({data:?})");
+    let cache = use_cache::<M>();
+    {
+        let cache = cache.clone();
+        use_memo(
+            move |data| {
+                if let Some(initial) = initial {
+                    cache.seed(data, initial);
+                }
+            },
+            data.clone(),
+        );
+    }
+
+    let state = {
+        let cache = cache.clone();
+        let data = data.clone();
+        use_state(move || cache.raw_value_of(&data).unwrap_or_default())
+    };
+    let state_clone = state.clone();
+    use_effect(move || {
+        cache.subscribe(&data, state_clone.clone(), true);
+        move || {
+            cache.unsubscribe(&data, &state_clone.setter().into());
+        }
+    });
+    let value = (*state).clone();
+    value.downcast().expect("Value is of wrong type")
+}
+
+/// Use cached data, showing placeholder data until it has loaded.
+///
+/// While the entry has no data, the `placeholder` is returned as stale data, so that the component
+/// renders it as if it were loaded. Unlike [`use_cached_with_initial`], the placeholder is never
+/// written to the cache, so it does not suppress the fetch and can be derived on every render.
+///
+/// This avoids handling the loading state separately when there is a sensible placeholder, such
+/// as an empty list. The placeholder is never [valid](crate::Value::valid), so the loading state
+/// can still be told apart.
+///
+/// ```rust
+/// # use wasm_cache::{yew::*, CacheItem, Invalidatable};
+/// # use yew::prelude::*;
+/// # #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// # struct Notifications;
+/// # impl Invalidatable<()> for Notifications {}
+/// # #[async_trait::async_trait(?Send)]
+/// # impl CacheItem for Notifications {
+/// #     type Value = Vec<String>;
+/// #     type Error = std::fmt::Error;
+/// #     async fn send(&self) -> Result<Self::Value, Self::Error> {
+/// #         Ok(vec![])
+/// #     }
+/// # }
+/// #[function_component]
+/// fn NotificationCount() -> Html {
+///     let notifications = use_cached_with_placeholder::<(), _>(Notifications, vec![]);
+///     let count = notifications.data().map(|list| list.len()).unwrap_or_default();
+///     html! {
+///         <span>{count}</span>
+///     }
+/// }
+/// ```
+#[hook]
+pub fn use_cached_with_placeholder<M: 'static, R: CacheItem<M>>(
+    data: R,
+    placeholder: R::Value,
+) -> RcValue<R::Value> {
+    let value = use_cached::<M, R>(data);
+    with_placeholder(value, placeholder)
+}
+
+/// Replace a value without data by the placeholder, as stale data.
+fn with_placeholder<T>(value: RcValue<T>, placeholder: T) -> RcValue<T> {
+    match value.data() {
+        Some(_) => value,
+        None => ValueState::Stale(Rc::new(placeholder)).into(),
+    }
+}
+
+/// Use cached data, if the key is available.
+///
+/// While `data` is `None`, this returns an empty value and does not subscribe to anything. Once it
+/// is `Some`, this behaves like [`use_cached`]. When it changes back to `None`, the component is
+/// unsubscribed from the previous key.
+#[hook]
+pub fn use_cached_opt<M: 'static, R: CacheItem<M>>(data: Option<R>) -> RcValue<R::Value> {
+    log::debug!("use_cached_opt({data:?})");
+    let cache = use_cache::<M>();
+    let state = use_state(RcValue::default);
+    let enabled = data.is_some();
+
+    let state_clone = state.clone();
+    use_effect(move || {
+        let subscriber = Subscriber::State(state_clone.setter());
+        if let Some(data) = &data {
+            cache.subscribe(data, state_clone, true);
+        }
+        move || {
+            if let Some(data) = &data {
+                cache.unsubscribe(data, &subscriber);
+            }
+        }
+    });
+
+    if !enabled {
+        return RcValue::default();
+    }
+    let value = (*state).clone();
+    value.downcast().expect("Value is of wrong type")
+}
+
+/// Use cached data, if the key is available, returning `None` otherwise.
+///
+/// Behaves like [`use_cached_opt`], but distinguishes a missing key from an entry without data.
+#[hook]
+pub fn use_cached_optional<M: 'static, R: CacheItem<M>>(
+    data: Option<R>,
+) -> Option<RcValue<R::Value>> {
+    let enabled = data.is_some();
+    let value = use_cached_opt::<M, R>(data);
+    enabled.then_some(value)
+}
+
+/// Use cached data with options specific to this use.
+///
+/// See [`QueryOptions`] for the available options. With the default options, this behaves the
+/// same as [`use_cached`].
+#[hook]
+pub fn use_cached_with_options<M: 'static, R: CacheItem<M>>(
+    data: R,
+    options: QueryOptions,
+) -> RcValue<R::Value> {
+    use_cached_keep_previous::<M, R>(data, options).0
+}
+
+/// Use cached data with options, flagging previous data.
+///
+/// Behaves like [`use_cached_with_options`], but additionally returns whether the value is the
+/// previous data kept by [`QueryOptions::keep_previous_data`]. While the entry of a new key has no
+/// data yet, the last value which had data is returned with the flag set, so that it can be shown
+/// dimmed until the new data arrives.
+///
+/// ```rust
+/// # use wasm_cache::{yew::*, CacheItem, Invalidatable};
+/// # use yew::prelude::*;
+/// # #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// # struct TasksPage(u64);
+/// # impl Invalidatable<()> for TasksPage {}
+/// # #[async_trait::async_trait(?Send)]
+/// # impl CacheItem for TasksPage {
+/// #     type Value = Vec<String>;
+/// #     type Error = std::fmt::Error;
+/// #     async fn send(&self) -> Result<Self::Value, Self::Error> {
+/// #         Ok(vec![])
+/// #     }
+/// # }
+/// #[derive(Properties, PartialEq)]
+/// struct TasksProps {
+///     page: u64,
+/// }
+///
+/// #[function_component]
+/// fn Tasks(props: &TasksProps) -> Html {
+///     let options = QueryOptions {
+///         keep_previous_data: true,
+///         ..Default::default()
+///     };
+///     let (tasks, previous) = use_cached_keep_previous::<(), _>(TasksPage(props.page), options);
+///     let class = previous.then_some("dimmed");
+///     let tasks = tasks.data().map(|tasks| (**tasks).clone()).unwrap_or_default();
+///     html! {
+///         <ul {class}>
+///         { for tasks.iter().map(|task| html! { <li>{task}</li> }) }
+///         </ul>
+///     }
+/// }
+/// ```
+#[hook]
+pub fn use_cached_keep_previous<M: 'static, R: CacheItem<M>>(
+    data: R,
+    options: QueryOptions,
+) -> (RcValue<R::Value>, bool) {
+    log::debug!("use_cached_keep_previous({data:?}, {options:?})");
+    let cache = use_cache::<M>();
+    let state = use_state(RcValue::default);
+    let mounted = use_mut_ref(|| false);
+    // last value which had data and its key, kept across key changes independently of the cache
+    let previous = use_state(|| None::<(R, RcValue)>);
+
+    let keep_previous_data = options.keep_previous_data;
+    let current = cache.raw_value_of(&data).unwrap_or_default();
+    let mut remembered = (*previous).clone();
+    let result = keep_previous(&data, current, &mut remembered, keep_previous_data);
+    let state_clone = state.clone();
+    use_effect(move || {
+        let refetch = !mounted.replace(true) && options.refetch_on_mount;
+        let subscriber = Subscriber::State(state_clone.setter());
+        cache.subscribe_with_options(&data, &subscriber, Some(&state_clone), &options, refetch);
+        move || {
+            cache.unsubscribe(&data, &subscriber);
+        }
+    });
+
+    use_effect(move || {
+        let changed = match (&*previous, &remembered) {
+            (Some((previous_key, previous)), Some((remembered_key, remembered))) => {
+                previous_key != remembered_key
+                    || match (previous.data(), remembered.data()) {
+                        (Some(previous), Some(remembered)) => !Rc::ptr_eq(previous, remembered),
+                        _ => true,
+                    }
+            }
+            (previous, remembered) => previous.is_some() != remembered.is_some(),
+        };
+        if changed {
+            previous.set(remembered);
+        }
+    });
+    result
+}
+
+/// Resolve the value to show, given the current value of the entry.
+///
+/// Remembers the current value along with its key in `previous` if it has data, the hook then
+/// stores it in its state. Otherwise, returns the remembered value if `keep` is set and it
+/// belongs to a different key, flagging it as previous data. If the same key loses its data,
+/// for example because it has been removed, the empty value is returned.
+fn keep_previous<K: Clone + PartialEq, T: 'static>(
+    key: &K,
+    current: RcValue,
+    previous: &mut Option<(K, RcValue)>,
+    keep: bool,
+) -> (RcValue<T>, bool) {
+    let (value, is_previous) = match previous {
+        _ if current.data().is_some() => {
+            *previous = Some((key.clone(), current.clone()));
+            (current, false)
+        }
+        Some((previous_key, previous)) if keep && previous_key != key => (previous.clone(), true),
+        _ => (current, false),
+    };
+    let value = value.downcast().expect("Value is of wrong type");
+    (value, is_previous)
+}
+
+/// Number of entries which are currently being fetched.
+///
+/// The component is rerendered whenever this changes, which is useful for global activity
+/// indicators such as a progress bar.
+#[hook]
+pub fn use_is_fetching<M: 'static>() -> usize {
+    let cache = use_cache::<M>();
+    use_fetch_activity(cache.clone());
+    cache.in_flight()
+}
+
+/// Number of entries whose key matches the filter and which are currently being fetched.
+///
+/// Behaves like [`use_is_fetching`], but only counts the keys matching `filter`. This allows
+/// scoping an activity indicator to a section of the application.
+#[hook]
+pub fn use_is_fetching_with<M: 'static, F: Fn(&dyn CacheKey<M>) -> bool + 'static>(
+    filter: F,
+) -> usize {
+    let cache = use_cache::<M>();
+    use_fetch_activity(cache.clone());
+    let count = cache
+        .cache
+        .lock()
+        .expect("Failure to lock cache")
+        .in_flight_where(filter);
+    count
+}
+
+/// Rerender the component whenever the number of entries being fetched changes.
+#[hook]
+fn use_fetch_activity<M: 'static>(cache: Cache<M>) {
+    let update = use_force_update();
+    use_effect_with_deps(
+        move |cache| {
+            let subscriber = Callback::from(move |_| update.force_update());
+            cache.subscribe_activity(&subscriber);
+            let cache = cache.clone();
+            move || cache.unsubscribe_activity(&subscriber)
+        },
+        cache,
+    );
+}
+
+/// Subscribe to a dynamic list of keys of the same type.
+///
+/// Returns the values in the same order as the keys. Keys can be added or removed between renders,
+/// the component is unsubscribed from keys which have been removed and entries for new keys are
+/// fetched. The component is rerendered when any of the values changes. Rerendering with the same
+/// keys does not subscribe to them again.
+#[hook]
+pub fn use_cached_many<M: 'static, R: CacheItem<M>>(keys: Vec<R>) -> Vec<RcValue<R::Value>> {
+    log::debug!("use_cached_many({keys:?})");
+    let cache = use_cache::<M>();
+    let update = use_force_update();
+    let notifications = use_mut_ref(|| 0u64);
+    let subscriber = {
+        let notifications = notifications.clone();
+        use_memo(
+            |_| {
+                Subscriber::Callback(Callback::from(move |_| {
+                    *notifications.borrow_mut() += 1;
+                    update.force_update();
+                }))
+            },
+            (),
+        )
+    };
+    let subscribed = use_mut_ref(Vec::<R>::new);
+
+    // only rerun when the keys change or a value is broadcast, which may need a refetch
+    {
+        let cache = cache.clone();
+        let subscriber = subscriber.clone();
+        let subscribed = subscribed.clone();
+        use_effect_with_deps(
+            move |(keys, _)| {
+                cache.subscribe_many(&subscriber, &mut subscribed.borrow_mut(), keys);
+                || ()
+            },
+            (keys.clone(), *notifications.borrow()),
+        );
+    }
+
+    {
+        let cache = cache.clone();
+        use_effect_with_deps(
+            move |_| {
+                move || {
+                    for key in subscribed.borrow().iter() {
+                        cache.unsubscribe(key, &subscriber);
+                    }
+                }
+            },
+            (),
+        );
+    }
+
+    keys.iter().map(|key| cache.value_of(key)).collect()
+}
+
+/// Subscribe to a family of keys, produced by a function.
+///
+/// The function is evaluated on every render, and the component is subscribed to the keys it
+/// returns, see [`use_cached_many`]. Returns each key paired with its value, which is useful to
+/// render lists with a loading state per item.
+#[hook]
+pub fn use_cached_family<M: 'static, R: CacheItem<M>, F: Fn() -> Vec<R> + 'static>(
+    keys: F,
+) -> Vec<(R, RcValue<R::Value>)> {
+    let keys = keys();
+    let values = use_cached_many::<M, R>(keys.clone());
+    keys.into_iter().zip(values).collect()
+}
+
+/// Selector of a derived value, see [`use_cached_select`].
+type Selector<T, S> = Rc<dyn Fn(&T) -> S>;
+
+/// Subscriber which only notifies `setter` when the selected output changes.
+///
+/// The output is `None` while the value has no data. `last` is the output the component currently
+/// holds.
+fn selecting_subscriber<T: 'static, S: PartialEq + Clone + 'static>(
+    select: Rc<RefCell<Selector<T, S>>>,
+    last: Option<S>,
+    setter: Callback<Option<S>>,
+) -> Subscriber {
+    let last = RefCell::new(last);
+    Callback::from(move |value: RcValue| {
+        let selected = value
+            .data()
+            .and_then(|data| data.downcast_ref::<T>())
+            .map(|data| (select.borrow())(data));
+        if *last.borrow() != selected {
+            *last.borrow_mut() = selected.clone();
+            setter.emit(selected);
+        }
+    })
+    .into()
+}
+
+/// Subscribe to a derived slice of the cached data.
+///
+/// The `select` function is applied to every new value. The component only rerenders when its
+/// output changes, which avoids renders when unrelated parts of a large value change. Returns
+/// `None` while there is no data.
+#[hook]
+pub fn use_cached_select<M, R, S, F>(data: R, select: F) -> Option<S>
+where
+    M: 'static,
+    R: CacheItem<M>,
+    S: PartialEq + Clone + 'static,
+    F: Fn(&R::Value) -> S + 'static,
+{
+    log::debug!("use_cached_select({data:?})");
+    let cache = use_cache::<M>();
+    let select: Selector<R::Value, S> = Rc::new(select);
+    let selector = use_mut_ref(|| select.clone());
+    *selector.borrow_mut() = select;
+
+    let state = {
+        let cache = cache.clone();
+        let data = data.clone();
+        let selector = selector.clone();
+        use_state(move || {
+            cache
+                .value_of(&data)
+                .data()
+                .map(|value| (selector.borrow())(value))
+        })
+    };
+    let subscriber = {
+        let setter = state.setter();
+        let last = (*state).clone();
+        let setter = Callback::from(move |selected| setter.set(selected));
+        use_memo(move |_| selecting_subscriber(selector, last, setter), ())
+    };
+
+    use_effect(move || {
+        cache.subscribe_with(&data, &subscriber, None, true);
+        move || {
+            cache.unsubscribe(&data, &subscriber);
+        }
+    });
+
+    (*state).clone()
+}
+
+/// Subscribe to this data, but only fetch it once triggered.
+///
+/// Returns the current value and a callback which triggers the initial fetch. Until then, the
+/// component is subscribed to the entry and updates when something else fetches it, but it does
+/// not fetch the value itself. Once triggered, the data is refetched when invalidated, the same way
+/// as with [`use_cached`].
+#[hook]
+pub fn use_cached_lazy<M: 'static, R: CacheItem<M>>(data: R) -> (RcValue<R::Value>, Callback<()>) {
+    log::debug!("use_cached_lazy({data:?})");
+    let cache = use_cache::<M>();
+    let state = use_state(RcValue::default);
+    let triggered = use_mut_ref(|| false);
+
+    let trigger = use_callback(
+        |_, (cache, data, triggered)| {
+            *triggered.borrow_mut() = true;
+            cache.prefetch(data.clone());
+        },
+        (cache.clone(), data.clone(), triggered.clone()),
+    );
+
+    let state_clone = state.clone();
+    use_effect(move || {
+        cache.subscribe(&data, state_clone.clone(), *triggered.borrow());
+        move || {
+            cache.unsubscribe(&data, &state_clone.setter().into());
+        }
+    });
+
+    let value = (*state).clone();
+    (value.downcast().expect("Value is of wrong type"), trigger)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Invalidatable;
+
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    pub(crate) struct Key(pub(crate) u64);
+
+    impl Invalidatable<()> for Key {}
+
+    #[derive(Debug)]
+    pub(crate) struct Failure;
+
+    impl std::fmt::Display for Failure {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "failure")
+        }
+    }
+
+    impl std::error::Error for Failure {}
+
+    #[async_trait::async_trait(?Send)]
+    impl CacheItem for Key {
+        type Value = u64;
+        type Error = Failure;
+
+        async fn send(&self) -> Result<Self::Value, Self::Error> {
+            Ok(self.0)
+        }
+    }
+
+    pub(crate) fn cache_with(keys: &[u64]) -> Cache {
+        let cache = Cache::default();
+        for key in keys {
+            cache.cache.lock().unwrap().insert(
+                Key(*key),
+                Entry {
+                    value: RcValue::new(Rc::new(*key) as Rc<dyn Any>),
+                    ..Default::default()
+                },
+            );
+        }
+        cache
+    }
+
+    pub(crate) fn subscribers(cache: &Cache, key: u64) -> usize {
+        let cache = cache.cache.lock().unwrap();
+        cache.get(&Key(key)).unwrap().subscriptions.len()
+    }
+
+    fn valid(cache: &Cache, key: u64) -> bool {
+        let cache = cache.cache.lock().unwrap();
+        cache.get(&Key(key)).unwrap().value.valid()
+    }
+
+    #[test]
+    fn cache_invalidate_key() {
+        let cache = cache_with(&[1, 2]);
+        cache.invalidate_key(&Key(1));
+        assert!(!valid(&cache, 1));
+        assert!(valid(&cache, 2));
+    }
+
+    #[test]
+    fn cache_invalidate_all() {
+        let cache = cache_with(&[1, 2]);
+        cache.invalidate_all();
+        assert!(!valid(&cache, 1));
+        assert!(!valid(&cache, 2));
+    }
+
+    #[test]
+    fn invalidation_broadcast_coalesced() {
+        let cache = cache_with(&[1, 2, 3]);
+        let (shared, shared_count) = counter();
+        let (single, single_count) = counter();
+        for key in 1..=3 {
+            cache.subscribe_with(&Key(key), &shared, None, false);
+        }
+        cache.subscribe_with(&Key(1), &single, None, false);
+
+        cache.invalidate_all();
+        assert_eq!(shared_count.get(), 1);
+        assert_eq!(single_count.get(), 1);
+        cache.invalidate(&());
+        assert_eq!(shared_count.get(), 2);
+        assert_eq!(cache.invalidate_all_of_type::<Key>(), 3);
+        assert_eq!(shared_count.get(), 3);
+        assert_eq!(single_count.get(), 3);
+
+        // subscribers are notified after the cache is unlocked
+        let handle = cache.clone();
+        let locked = Rc::new(Cell::new(false));
+        let subscriber = Subscriber::Callback(Callback::from({
+            let locked = locked.clone();
+            move |_| locked.set(handle.cache.try_lock().is_err())
+        }));
+        cache.subscribe_with(&Key(2), &subscriber, None, false);
+        cache.invalidate_all();
+        assert!(!locked.get());
+    }
+
+    #[test]
+    fn cache_invalidate_all_of_type() {
+        let cache = cache_with(&[1, 2, 3]);
+        cache.set(Urgent(1), 1);
+        let (subscriber, count) = counter();
+        cache.subscribe_with(&Key(1), &subscriber, None, false);
+        assert_eq!(cache.invalidate_all_of_type::<Key>(), 3);
+        assert_eq!(count.get(), 1);
+        assert!(!valid(&cache, 1));
+        assert!(!valid(&cache, 3));
+        assert!(cache.contains_valid(&Urgent(1)));
+        assert_eq!(cache.invalidate_all_of_type::<Tags>(), 0);
+    }
+
+    #[test]
+    fn btree_cache_mutate_missing_key() {
+        let cache = cache_with(&[1]);
+        let mut inner = cache.cache.lock().unwrap();
+        let mut called = false;
+        assert_eq!(inner.mutate(&Key(2), |_| called = true), None);
+        assert!(!called);
+        let valid = inner.mutate(&Key(1), |entry| entry.value.valid());
+        assert_eq!(valid, Some(true));
+    }
+
+    #[test]
+    fn btree_cache_insert_replaces() {
+        let cache = cache_with(&[1]);
+        let (subscriber, count) = counter();
+        cache.subscribe_with(&Key(1), &subscriber, None, false);
+        let mut inner = cache.cache.lock().unwrap();
+        inner.insert(Key(1), Entry::default());
+        let entry = inner.get(&Key(1)).unwrap();
+        assert!(entry.subscriptions.is_empty());
+        assert!(!entry.value.valid());
+        assert!(entry.created_at.is_some());
+        assert_eq!(count.get(), 0);
+        assert_eq!(inner.entries.len(), 1);
+    }
+
+    #[test]
+    fn btree_cache_iter_mut() {
+        let cache = cache_with(&[1, 2, 3]);
+        let mut inner = cache.cache.lock().unwrap();
+        let invalidated: Vec<String> = inner
+            .iter_mut()
+            .take(2)
+            .map(|(key, entry)| {
+                entry.value.invalidate();
+                format!("{key:?}")
+            })
+            .collect();
+        assert_eq!(invalidated, vec!["Key(1)", "Key(2)"]);
+        drop(inner);
+        assert!(!valid(&cache, 1));
+        assert!(!valid(&cache, 2));
+        assert!(valid(&cache, 3));
+    }
+
+    #[test]
+    fn cache_prefetch_skips_valid() {
+        let cache = cache_with(&[1]);
+        cache.prefetch(Key(1));
+        let cache = cache.cache.lock().unwrap();
+        let entry = cache.get(&Key(1)).unwrap();
+        assert!(!entry.progress);
+        assert!(entry.value.valid());
+    }
+
+    pub(crate) fn counter() -> (Subscriber, Rc<Cell<usize>>) {
+        let count = Rc::new(Cell::new(0));
+        let subscriber = Subscriber::Callback(Callback::from({
+            let count = count.clone();
+            move |_| count.set(count.get() + 1)
+        }));
+        (subscriber, count)
+    }
+
+    #[test]
+    fn cache_broadcasts_changed_value() {
+        let cache = Cache::default();
+        let (subscriber, count) = counter();
+        cache.cache.lock().unwrap().insert(
+            Key(1),
+            Entry {
+                subscriptions: vec![subscriber],
+                ..Default::default()
+            },
+        );
+
+        cache.cache(&Key(1), Rc::new(1));
+        assert_eq!(count.get(), 1);
+
+        // refetching identical data does not notify subscribers
+        cache.cache(&Key(1), Rc::new(1));
+        assert_eq!(count.get(), 1);
+
+        cache.cache(&Key(1), Rc::new(2));
+        assert_eq!(count.get(), 2);
+
+        // invalidated data is broadcast even if it is unchanged
+        cache.invalidate_key(&Key(1));
+        assert_eq!(count.get(), 3);
+        cache.cache(&Key(1), Rc::new(2));
+        assert_eq!(count.get(), 4);
+    }
+
+    #[test]
+    fn failure_keeps_stale_value() {
+        let cache = cache_with(&[1]);
+        cache.cache.lock().unwrap().options.backoff.jitter = 0.0;
+        cache.invalidate_key(&Key(1));
+        cache.failure(&Key(1), Failure);
+
+        let cache = cache.cache.lock().unwrap();
+        let entry = cache.get(&Key(1)).unwrap();
+        assert!(!entry.value.valid());
+        assert!(entry.value.value_eq(&1u64));
+        assert_eq!(entry.delay, Some(DELAY_INITIAL));
+    }
+
+    #[test]
+    fn failure_discards_stale_value() {
+        let cache = Cache::with_options(CacheOptions {
+            keep_stale_on_failure: false,
+            ..Default::default()
+        });
+        cache.cache.lock().unwrap().insert(
+            Key(1),
+            Entry {
+                value: RcValue::new(Rc::new(1u64) as Rc<dyn Any>),
+                ..Default::default()
+            },
+        );
+        cache.invalidate_key(&Key(1));
+        cache.failure(&Key(1), Failure);
+
+        let cache = cache.cache.lock().unwrap();
+        assert!(cache.get(&Key(1)).unwrap().value.data().is_none());
+    }
+
+    #[test]
+    fn circuit_breaker_state_machine() {
+        let breaker = CircuitBreaker {
+            threshold: 2,
+            cooldown: Duration::from_secs(1),
+        };
+        let mut entry = Entry::default();
+        assert!(entry.needs_fetch(0.0));
+
+        entry.fetch_started();
+        entry.fetch_failed(0.0, Some(&breaker));
+        assert_eq!(entry.circuit, CircuitState::Closed);
+        assert!(entry.needs_fetch(0.0));
+
+        // circuit opens after the threshold is reached
+        entry.fetch_started();
+        entry.fetch_failed(0.0, Some(&breaker));
+        assert_eq!(entry.circuit, CircuitState::Open { until: 1000.0 });
+        assert!(!entry.needs_fetch(500.0));
+        assert!(entry.needs_fetch(1000.0));
+
+        // failed trial fetch reopens the circuit
+        entry.fetch_started();
+        assert_eq!(entry.circuit, CircuitState::HalfOpen);
+        assert!(!entry.needs_fetch(1000.0));
+        entry.fetch_failed(1000.0, Some(&breaker));
+        assert_eq!(entry.circuit, CircuitState::Open { until: 2000.0 });
+
+        // successful trial fetch closes the circuit
+        entry.fetch_started();
+        entry.fetch_succeeded(2000.0);
+        assert_eq!(entry.circuit, CircuitState::Closed);
+        assert_eq!(entry.failures, 0);
+    }
+
+    #[test]
+    fn subscribe_with_is_idempotent() {
+        let cache = cache_with(&[1]);
+        let (subscriber, count) = counter();
+        cache.subscribe_with(&Key(1), &subscriber, None, true);
+        cache.subscribe_with(&Key(1), &subscriber, None, true);
+        assert_eq!(count.get(), 0);
+        assert_eq!(subscribers(&cache, 1), 1);
+        assert_eq!(cache.value_of(&Key(1)).data().map(|data| **data), Some(1));
+
+        cache.unsubscribe(&Key(1), &subscriber);
+        assert_eq!(subscribers(&cache, 1), 0);
+        assert!(cache.value_of(&Key(2)).data().is_none());
+    }
+
+    #[test]
+    fn subscribers_strictest_stale_time() {
+        let clock = ManualClock::new();
+        let cache = cache_with(&[1]).with_clock(clock.clone());
+        cache
+            .cache
+            .lock()
+            .unwrap()
+            .mutate(&Key(1), |entry| entry.last_updated = Some(0.0))
+            .unwrap();
+        clock.advance(Duration::from_secs(5));
+        let (first, count) = counter();
+        let (second, _) = counter();
+        let options = |stale_time| QueryOptions {
+            stale_time: Some(Duration::from_secs(stale_time)),
+            enabled: false,
+            ..Default::default()
+        };
+
+        // value is fresh enough for this subscriber
+        cache.subscribe_with_options(&Key(1), &first, None, &options(10), false);
+        assert!(valid(&cache, 1));
+
+        // but not for this one
+        cache.subscribe_with_options(&Key(1), &second, None, &options(1), false);
+        assert!(!valid(&cache, 1));
+        assert_eq!(count.get(), 1);
+
+        let stale_time = |cache: &Cache| {
+            let cache = cache.cache.lock().unwrap();
+            cache.get(&Key(1)).unwrap().stale_time()
+        };
+        assert_eq!(stale_time(&cache), Some(Duration::from_secs(1)));
+        cache.unsubscribe(&Key(1), &second);
+        assert_eq!(stale_time(&cache), Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn subscribe_refetch_on_mount() {
+        let cache = cache_with(&[1]);
+        let (subscriber, _) = counter();
+        let options = QueryOptions {
+            enabled: false,
+            ..Default::default()
+        };
+        cache.subscribe_with_options(&Key(1), &subscriber, None, &options, true);
+        assert!(!valid(&cache, 1));
+    }
+
+    #[test]
+    fn fetch_concurrency_limit() {
+        let mut cache = BTreeCache::<()>::default();
+        cache.options.max_concurrent_fetches = Some(1);
+        let (subscriber, _) = counter();
+        for key in 1..=3 {
+            cache.insert(
+                Key(key),
+                Entry {
+                    progress: true,
+                    subscriptions: vec![subscriber.clone()],
+                    ..Default::default()
+                },
+            );
+        }
+
+        assert!(cache.acquire_fetch());
+        assert!(!cache.acquire_fetch());
+        for key in 1..=3 {
+            cache.enqueue_fetch(Box::new(Key(key)), true, 128, Box::new(|_| {}));
+        }
+
+        // queued fetch is dropped once its entry has lost all subscribers
+        cache
+            .mutate(&Key(1), |entry| entry.unsubscribe(&subscriber))
+            .unwrap();
+        let next = cache.release_fetch().unwrap();
+        assert!(next.key.any_eq(&Key(2)));
+        assert!(!cache.get(&Key(1)).unwrap().progress);
+        assert_eq!(cache.fetching, 1);
+
+        let next = cache.release_fetch().unwrap();
+        assert!(next.key.any_eq(&Key(3)));
+        assert!(cache.release_fetch().is_none());
+        assert_eq!(cache.fetching, 0);
+    }
+
+    #[test]
+    fn fetch_queued_beyond_limit() {
+        let cache = cache_with(&[1, 2, 3]);
+        {
+            let mut cache = cache.cache.lock().unwrap();
+            cache.options.max_concurrent_fetches = Some(1);
+            assert!(cache.acquire_fetch());
+        }
+
+        cache.invalidate_all();
+        for key in 1..=3 {
+            cache.fetch(&Key(key), None);
+        }
+
+        let cache = cache.cache.lock().unwrap();
+        assert_eq!(cache.in_progress_count(), 1);
+        let pending: Vec<_> = cache.pending_keys().collect();
+        assert_eq!(pending.len(), 3);
+        assert!(pending[0].any_eq(&Key(1)));
+        assert!(pending[2].any_eq(&Key(3)));
+    }
+
+    /// Item which is fetched before items of the default priority.
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Urgent(u64);
+
+    impl Invalidatable<()> for Urgent {}
+
+    #[async_trait::async_trait(?Send)]
+    impl CacheItem for Urgent {
+        type Value = u64;
+        type Error = Failure;
+
+        async fn send(&self) -> Result<Self::Value, Self::Error> {
+            Ok(self.0)
+        }
+
+        fn fetch_priority(&self) -> u8 {
+            200
+        }
+    }
+
+    /// Tags of a document, which are merged when written concurrently.
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Tags;
+
+    impl Invalidatable<()> for Tags {}
+
+    #[async_trait::async_trait(?Send)]
+    impl CacheItem for Tags {
+        type Value = Vec<u64>;
+        type Error = Failure;
+
+        async fn send(&self) -> Result<Self::Value, Self::Error> {
+            Ok(vec![1, 2])
+        }
+
+        fn resolve(&self, existing: &Self::Value, mut incoming: Self::Value) -> Self::Value {
+            incoming.extend(existing);
+            incoming.sort_unstable();
+            incoming.dedup();
+            incoming
+        }
+    }
+
+    #[test]
+    fn resolve_merges_conflicting_writes() {
+        let cache = Cache::<()>::default().with_spawn_fn(|future| {
+            futures::executor::block_on(future);
+        });
+        cache.set(Tags, vec![3]);
+        cache.invalidate_all();
+        cache.fetch(&Tags, None);
+        assert_eq!(
+            cache.peek(&Tags).unwrap().data(),
+            Some(&Rc::new(vec![1, 2, 3]))
+        );
+
+        // items without a resolver keep the incoming value
+        let cache = cache_with(&[1]);
+        cache.set(Key(1), 5);
+        assert_eq!(cache.peek(&Key(1)).unwrap().data(), Some(&Rc::new(5)));
+    }
+
+    thread_local! {
+        static TOKENS: RefCell<Vec<&'static str>> = const { RefCell::new(vec![]) };
+    }
+
+    /// Request with an authentication token, which does not affect the response.
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Authenticated {
+        id: u64,
+        token: &'static str,
+    }
+
+    impl Invalidatable<()> for Authenticated {}
+
+    #[async_trait::async_trait(?Send)]
+    impl CacheItem for Authenticated {
+        type Value = u64;
+        type Error = Failure;
+
+        async fn send(&self) -> Result<Self::Value, Self::Error> {
+            TOKENS.with(|tokens| tokens.borrow_mut().push(self.token));
+            Ok(self.id)
+        }
+
+        fn cache_key(&self) -> std::borrow::Cow<'_, Self> {
+            std::borrow::Cow::Owned(Self {
+                token: "",
+                ..self.clone()
+            })
+        }
+    }
+
+    #[test]
+    fn requests_share_entry_of_normalized_key() {
+        let cache = Cache::<()>::default().with_spawn_fn(|future| {
+            futures::executor::block_on(future);
+        });
+        let (subscriber, count) = counter();
+        let first = Authenticated { id: 1, token: "a" };
+        let second = Authenticated { id: 1, token: "b" };
+        cache.subscribe_with(&first, &subscriber, None, true);
+        cache.subscribe_with(&second, &subscriber, None, true);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(count.get(), 1);
+        assert_eq!(TOKENS.with(|tokens| tokens.borrow().clone()), ["a"]);
+        assert_eq!(cache.peek(&second).unwrap().data(), Some(&Rc::new(1)));
+
+        cache.invalidate_key(&second);
+        assert!(!cache.contains_valid(&first));
+        assert!(cache.remove(&second));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn fetch_queue_ordered_by_priority() {
+        let cache = cache_with(&[1, 2]);
+        cache.set(Urgent(1), 1);
+        {
+            let mut cache = cache.cache.lock().unwrap();
+            cache.options.max_concurrent_fetches = Some(1);
+            assert!(cache.acquire_fetch());
+        }
+
+        cache.fetch(&Key(1), None);
+        cache.fetch(&Urgent(1), None);
+        cache.fetch(&Key(2), None);
+
+        let mut cache = cache.cache.lock().unwrap();
+        assert_eq!(cache.get(&Urgent(1)).unwrap().priority, 200);
+        assert_eq!(cache.get(&Key(1)).unwrap().priority, 128);
+        let pending: Vec<_> = cache.pending_keys().collect();
+        assert!(pending[0].any_eq(&Urgent(1)));
+        assert!(pending[1].any_eq(&Key(1)));
+        assert!(pending[2].any_eq(&Key(2)));
+
+        // fetches start in the same order
+        let next = cache.release_fetch().unwrap();
+        assert!(next.key.any_eq(&Urgent(1)));
+        assert_eq!(cache.fetching, 1);
+        assert!(cache.next_fetch().is_none());
+    }
+
+    #[test]
+    fn apply_transforms_value() {
+        let cache = cache_with(&[1]);
+        let (subscriber, count) = counter();
+        cache.subscribe_with(&Key(1), &subscriber, None, false);
+
+        cache.apply(&Key(1), |value| value + 10);
+        assert_eq!(cache.value_of(&Key(1)).data().map(|data| **data), Some(11));
+        assert!(valid(&cache, 1));
+        assert_eq!(count.get(), 1);
+
+        // missing entries are ignored
+        cache.apply(&Key(2), |value| value + 10);
+        assert!(cache.cache.lock().unwrap().get(&Key(2)).is_none());
+    }
+
+    #[test]
+    fn update_value_copies_on_write() {
+        let cache = cache_with(&[1]);
+        let before = cache.value_of(&Key(1));
+        assert!(cache.update_value(&Key(1), |value| value + 1));
+        assert_eq!(before.data().map(|data| **data), Some(1));
+        assert_eq!(cache.value_of(&Key(1)).data().map(|data| **data), Some(2));
+
+        // without a cached value, nothing is updated
+        let (subscriber, count) = counter();
+        cache.subscribe_with(&Key(2), &subscriber, None, false);
+        assert!(!cache.update_value(&Key(2), |value| value + 1));
+        assert!(!cache.update_value(&Key(3), |value| value + 1));
+        assert_eq!(count.get(), 0);
+    }
+
+    #[test]
+    fn update_patches_value() {
+        let cache = cache_with(&[1]);
+        let (subscriber, count) = counter();
+        cache.subscribe_with(&Key(1), &subscriber, None, false);
+        let shared = cache.value_of(&Key(1));
+        cache.invalidate_key(&Key(1));
+
+        assert!(cache.update(&Key(1), |value| *value += 1));
+        assert_eq!(shared.data().map(|data| **data), Some(1));
+        let value = cache.value_of(&Key(1));
+        assert_eq!(value.state(), &ValueState::Stale(Rc::new(2)));
+        assert_eq!(count.get(), 2);
+
+        assert!(!cache.update(&Key(2), |value| *value += 1));
+    }
+
+    #[test]
+    fn evict_expired_entries() {
+        let mut cache = BTreeCache::<()>::default();
+        let entry = |ttl| Entry {
+            last_updated: Some(0.0),
+            ttl,
+            ..Default::default()
+        };
+        cache.insert(Key(1), entry(Some(Duration::from_secs(1))));
+        cache.insert(Key(2), entry(Some(Duration::from_secs(3))));
+        cache.insert(Key(3), entry(None));
+
+        assert_eq!(cache.evict_expired(2000.0), 1);
+        assert!(cache.get(&Key(1)).is_none());
+        assert!(cache.get(&Key(2)).is_some());
+        assert!(cache.get(&Key(3)).is_some());
+    }
+
+    #[test]
+    fn evict_no_store_entries() {
+        let mut cache = BTreeCache::<()>::default();
+        let (subscriber, _) = counter();
+        let entry = |subscriptions| Entry {
+            no_store: true,
+            subscriptions,
+            ..Default::default()
+        };
+        cache.insert(Key(1), entry(vec![]));
+        cache.insert(Key(2), entry(vec![subscriber]));
+
+        assert_eq!(cache.evict_expired(0.0), 1);
+        assert!(cache.get(&Key(1)).is_none());
+        assert!(cache.get(&Key(2)).is_some());
+    }
+
+    /// Sensitive response, which must not be kept once it is no longer shown.
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Secret(u64);
+
+    impl Invalidatable<()> for Secret {}
+
+    #[async_trait::async_trait(?Send)]
+    impl CacheItem for Secret {
+        type Value = u64;
+        type Error = Failure;
+
+        async fn send(&self) -> Result<Self::Value, Self::Error> {
+            Ok(self.0)
+        }
+
+        fn cache_control(&self) -> CacheControlHints {
+            CacheControlHints {
+                no_store: true,
+                ..Default::default()
+            }
+        }
+    }
+
+    #[test]
+    fn no_store_removed_on_last_unsubscribe() {
+        let cache = Cache::<()>::default().with_spawn_fn(|future| {
+            futures::executor::block_on(future);
+        });
+        let (first, _) = counter();
+        let (second, _) = counter();
+        cache.subscribe_with(&Secret(1), &first, None, true);
+        cache.subscribe_with(&Secret(1), &second, None, true);
+        assert!(cache.contains_valid(&Secret(1)));
+
+        cache.unsubscribe(&Secret(1), &first);
+        assert!(cache.contains_valid(&Secret(1)));
+        cache.unsubscribe(&Secret(1), &second);
+        assert!(!cache.contains(&Secret(1)));
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn entry_expiry_stale_while_revalidate() {
+        let mut entry = Entry {
+            value: RcValue::new(Rc::new(1u64) as Rc<dyn Any>),
+            last_updated: Some(0.0),
+            ..Default::default()
+        };
+        entry.apply_hints(&CacheControlHints {
+            max_age: Some(Duration::from_secs(1)),
+            stale_while_revalidate: Some(Duration::from_secs(2)),
+            no_store: false,
+        });
+
+        // fresh value is kept
+        assert!(!entry.expire(500.0));
+        assert!(entry.value.valid());
+
+        // stale value is still served
+        assert!(entry.expire(2000.0));
+        assert!(!entry.value.valid());
+        assert!(entry.value.data().is_some());
+
+        // value is discarded after stale-while-revalidate window
+        assert!(entry.expire(4000.0));
+        assert!(entry.value.data().is_none());
+        assert!(!entry.expire(5000.0));
+    }
+
+    #[test]
+    fn statistics_entry_ages() {
+        let mut cache = BTreeCache::<()>::default();
+        assert_eq!(cache.statistics(0.0), CacheStatistics::default());
+
+        let entry = |created_at| Entry {
+            created_at: Some(created_at),
+            ..Default::default()
+        };
+        cache.insert(Key(1), entry(1000.0));
+        cache.insert(Key(2), entry(3000.0));
+
+        assert_eq!(
+            cache.statistics(4000.0),
+            CacheStatistics {
+                entries: 2,
+                oldest_entry_age: Some(Duration::from_secs(3)),
+                newest_entry_age: Some(Duration::from_secs(1)),
+            }
+        );
+    }
+
+    #[test]
+    fn failure_backoff_delays() {
+        let clock = ManualClock::new();
+        let cache = cache_with(&[1]).with_clock(clock.clone());
+        cache.cache.lock().unwrap().options.backoff.jitter = 0.0;
+        let delay = |cache: &Cache| cache.cache.lock().unwrap().get(&Key(1)).unwrap().delay;
+        assert_eq!(delay(&cache), None);
+
+        cache.failure(&Key(1), Failure);
+        assert_eq!(delay(&cache), Some(DELAY_INITIAL));
+        cache.failure(&Key(1), Failure);
+        assert_eq!(delay(&cache), Some(DELAY_INITIAL.mul_f64(DELAY_MULTIPLIER)));
+        cache.failure(&Key(1), Failure);
+        assert_eq!(delay(&cache), Some(DELAY_INITIAL.mul_f64(2.25)));
+
+        // a successful fetch resets the backoff
+        cache.cache(&Key(1), Rc::new(1));
+        assert_eq!(delay(&cache), None);
+    }
+
+    /// Item whose fetches always fail, counting its attempts.
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Flaky;
+
+    thread_local! {
+        static ATTEMPTS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    impl Invalidatable<()> for Flaky {}
+
+    #[async_trait::async_trait(?Send)]
+    impl CacheItem for Flaky {
+        type Value = u64;
+        type Error = Failure;
+
+        async fn send(&self) -> Result<Self::Value, Self::Error> {
+            ATTEMPTS.with(|attempts| attempts.set(attempts.get() + 1));
+            Err(Failure)
+        }
+    }
+
+    #[test]
+    fn failed_fetches_sleep_for_backoff() {
+        run_local(async {
+            let clock = ManualClock::new();
+            let cache = Cache::<()>::default()
+                .with_clock(clock.clone())
+                .with_spawn_fn(|future| drop(tokio::task::spawn_local(future)));
+            cache.cache.lock().unwrap().options.backoff.jitter = 0.0;
+            let settle = || async {
+                for _ in 0..10 {
+                    tokio::task::yield_now().await;
+                }
+            };
+            let attempts = || ATTEMPTS.with(Cell::get);
+            let (subscriber, _) = counter();
+            cache.subscribe_with(&Flaky, &subscriber, None, true);
+            settle().await;
+            assert_eq!(attempts(), 1);
+            assert!(clock.sleeps().is_empty());
+
+            // every retry sleeps for the backoff delay before fetching
+            let delays = [100, 150, 225].map(Duration::from_millis);
+            for (retry, delay) in delays.into_iter().enumerate() {
+                cache.subscribe_with(&Flaky, &subscriber, None, true);
+                settle().await;
+                assert_eq!(clock.sleeps(), delays[..=retry]);
+                assert_eq!(attempts(), retry + 1);
+
+                clock.advance(delay);
+                settle().await;
+                assert_eq!(attempts(), retry + 2);
+            }
+        });
+    }
+
+    #[test]
+    fn invalidation_resets_backoff() {
+        let invalidations: [fn(&Cache); 4] = [
+            |cache| cache.invalidate(&()),
+            |cache| cache.invalidate_key(&Key(1)),
+            |cache| cache.invalidate_all(),
+            |cache| {
+                cache.refetch(&Key(1));
+            },
+        ];
+        for invalidate in invalidations {
+            let clock = ManualClock::new();
+            let cache = cache_with(&[1])
+                .with_clock(clock.clone())
+                .with_spawn_fn(|future| {
+                    let _ = futures::FutureExt::now_or_never(future);
+                });
+            cache.failure(&Key(1), Failure);
+            cache.failure(&Key(1), Failure);
+            assert!(cache.peek_entry(&Key(1)).unwrap().delay.is_some());
+
+            invalidate(&cache);
+            let (subscriber, _) = counter();
+            cache.subscribe_with(&Key(1), &subscriber, None, true);
+            assert!(clock.sleeps().is_empty());
+            assert!(cache.contains_valid(&Key(1)));
+            assert_eq!(cache.peek_entry(&Key(1)).unwrap().delay, None);
+        }
+    }
+
+    #[test]
+    fn backoff_capped_with_jitter() {
+        let backoff = Backoff {
+            max_delay: Duration::from_secs(1),
+            jitter: 0.2,
+        };
+        let mut entry = Entry {
+            backoff,
+            ..Default::default()
+        };
+        let mut capped = vec![];
+        for failures in 1..=30 {
+            entry.fetch_started();
+            entry.fetch_failed(0.0, None);
+            let base = (0.1 * 1.5f64.powi(failures - 1)).min(1.0);
+            let delay = entry.delay.unwrap().as_secs_f64();
+            assert!(delay >= base * 0.8 - 1e-9 && delay <= base * 1.2 + 1e-9);
+            if base == 1.0 {
+                capped.push(delay);
+            }
+        }
+        assert!(capped.len() > 20);
+        assert!(capped.iter().any(|delay| *delay != capped[0]));
+
+        // no jitter yields the exact sequence
+        let backoff = Backoff {
+            jitter: 0.0,
+            ..backoff
+        };
+        assert_eq!(backoff.delay(1), DELAY_INITIAL);
+        assert_eq!(backoff.delay(3), DELAY_INITIAL.mul_f64(2.25));
+        assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn cache_uses_clock() {
+        let clock = ManualClock::new();
+        let cache = Cache::<()>::default().with_clock(clock.clone());
+        cache.cache.lock().unwrap().insert(Key(1), Entry::default());
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(
+            cache.statistics().oldest_entry_age,
+            Some(Duration::from_secs(2))
+        );
+
+        cache.cache(&Key(1), Rc::new(1));
+        let cache = cache.cache.lock().unwrap();
+        assert_eq!(cache.get(&Key(1)).unwrap().last_updated, Some(2000.0));
+    }
+
+    #[test]
+    fn keep_previous_data_while_loading() {
+        let mut previous = None;
+        let value = |data: u64| RcValue::new(Rc::new(data) as Rc<dyn Any>);
+
+        let (current, flag) = keep_previous::<_, u64>(&Key(1), value(1), &mut previous, true);
+        assert_eq!(current.data().map(|data| **data), Some(1));
+        assert!(!flag);
+
+        // new key has no data yet, previous data is kept and flagged
+        let empty = RcValue::default;
+        let (current, flag) = keep_previous::<_, u64>(&Key(2), empty(), &mut previous, true);
+        assert_eq!(current.data().map(|data| **data), Some(1));
+        assert!(flag);
+
+        // without the option, the empty value is returned
+        let (current, flag) = keep_previous::<_, u64>(&Key(2), empty(), &mut previous, false);
+        assert_eq!(current.data(), None);
+        assert!(!flag);
+
+        // new data replaces the previous data
+        let (current, flag) = keep_previous::<_, u64>(&Key(2), value(2), &mut previous, true);
+        assert_eq!(current.data().map(|data| **data), Some(2));
+        assert!(!flag);
+        let (key, value) = previous.clone().unwrap();
+        assert_eq!(key, Key(2));
+        assert!(value.value_eq(&2u64));
+
+        // the same key losing its data does not show the removed data
+        let (current, flag) = keep_previous::<_, u64>(&Key(2), empty(), &mut previous, true);
+        assert_eq!(current.data(), None);
+        assert!(!flag);
+    }
+
+    #[test]
+    #[should_panic(expected = "Value is of wrong type")]
+    fn keep_previous_data_wrong_type() {
+        let mut previous = Some((Key(1), RcValue::new(Rc::new("text") as Rc<dyn Any>)));
+        keep_previous::<_, u64>(&Key(2), RcValue::default(), &mut previous, true);
+    }
+
+    #[test]
+    fn placeholder_until_loaded() {
+        let value = with_placeholder(RcValue::default(), 7);
+        assert_eq!(value.state(), &ValueState::Stale(Rc::new(7)));
+
+        let mut stale = RcValue::new(Rc::new(1));
+        stale.invalidate();
+        assert_eq!(with_placeholder(stale.clone(), 7), stale);
+        let loaded = RcValue::new(Rc::new(2));
+        assert_eq!(with_placeholder(loaded.clone(), 7), loaded);
+    }
+
+    /// Run this future on a tokio runtime, inside of a `LocalSet`.
+    pub(crate) fn run_local<F: Future>(future: F) -> F::Output {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        tokio::task::LocalSet::new().block_on(&runtime, future)
+    }
+
+    #[test]
+    fn native_fetch() {
+        run_local(async {
+            let cache = Cache::<()>::default();
+            let in_progress = || cache.cache.lock().unwrap().in_progress_count();
+            let (subscriber, count) = counter();
+            cache.subscribe_with(&Key(3), &subscriber, None, true);
+            assert_eq!(in_progress(), 1);
+
+            while in_progress() > 0 {
+                tokio::task::yield_now().await;
+            }
+            assert_eq!(count.get(), 1);
+            assert_eq!(cache.value_of(&Key(3)).data().map(|data| **data), Some(3));
+        });
+    }
+
+    #[test]
+    fn native_fetch_respects_delay() {
+        run_local(async {
+            let cache = cache_with(&[1]);
+            cache.invalidate_key(&Key(1));
+            cache.fetch(&Key(1), Some(Duration::from_millis(20)));
+
+            tokio::task::yield_now().await;
+            assert!(!valid(&cache, 1));
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert!(valid(&cache, 1));
+        });
+    }
+
+    /// Search query which is debounced by one second and counts its fetches.
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Search;
+
+    thread_local! {
+        static SEARCHES: Cell<usize> = const { Cell::new(0) };
+    }
+
+    impl Invalidatable<()> for Search {}
+
+    #[async_trait::async_trait(?Send)]
+    impl CacheItem for Search {
+        type Value = u64;
+        type Error = Failure;
+
+        async fn send(&self) -> Result<Self::Value, Self::Error> {
+            SEARCHES.with(|searches| searches.set(searches.get() + 1));
+            Ok(0)
+        }
+
+        fn debounce(&self) -> Option<Duration> {
+            Some(Duration::from_secs(1))
+        }
+    }
+
+    #[test]
+    fn debounced_fetch_restarts_timer() {
+        run_local(async {
+            let clock = ManualClock::new();
+            let cache = Cache::<()>::default()
+                .with_clock(clock.clone())
+                .with_spawn_fn(|future| drop(tokio::task::spawn_local(future)));
+            let settle = || async {
+                for _ in 0..10 {
+                    tokio::task::yield_now().await;
+                }
+            };
+            let (subscriber, _) = counter();
+            cache.subscribe_with(&Search, &subscriber, None, false);
+            settle().await;
+
+            // every fetch within the debounce time restarts the timer
+            for _ in 0..3 {
+                clock.advance(Duration::from_millis(600));
+                cache.fetch(&Search, None);
+                settle().await;
+            }
+            assert_eq!(SEARCHES.with(Cell::get), 0);
+            assert!(!cache.value_of(&Search).valid());
+
+            clock.advance(Duration::from_secs(1));
+            settle().await;
+            assert_eq!(SEARCHES.with(Cell::get), 1);
+            assert!(cache.value_of(&Search).valid());
+            let inner = cache.cache.lock().unwrap();
+            let entry = inner.get(&Search).unwrap();
+            assert_eq!(entry.debounce_duration, Some(Duration::from_secs(1)));
+            assert!(entry.debounce_cancel.is_none());
+        });
+    }
+
+    #[test]
+    fn failure_records_last_error() {
+        let cache = cache_with(&[1]);
+        let errors = |cache: &Cache| {
+            let cache = cache.cache.lock().unwrap();
+            cache.get(&Key(1)).unwrap().last_error_count
+        };
+        assert_eq!(cache.last_error(&Key(1)), None);
+
+        cache.failure(&Key(1), Failure);
+        cache.failure(&Key(1), Failure);
+        assert_eq!(cache.last_error(&Key(1)), Some("Failure".into()));
+        assert_eq!(errors(&cache), 2);
+
+        cache.cache(&Key(1), Rc::new(1));
+        assert_eq!(cache.last_error(&Key(1)), None);
+        assert_eq!(errors(&cache), 0);
+    }
+
+    #[test]
+    fn select_only_notifies_changes() {
+        let cache = cache_with(&[]);
+        let selected = Rc::new(RefCell::new(vec![]));
+        let setter = {
+            let selected = selected.clone();
+            Callback::from(move |value| selected.borrow_mut().push(value))
+        };
+        let select: Selector<u64, bool> = Rc::new(|value: &u64| *value > 4);
+        let subscriber = selecting_subscriber(Rc::new(RefCell::new(select)), None, setter);
+        cache.subscribe_with(&Key(1), &subscriber, None, false);
+
+        for value in [1, 2, 5, 7, 3] {
+            cache.cache(&Key(1), Rc::new(value));
+        }
+        let expected = vec![Some(false), Some(true), Some(false)];
+        assert_eq!(*selected.borrow(), expected);
+
+        // selection of the empty value is `None`
+        let mut inner = cache.cache.lock().unwrap();
+        inner
+            .mutate(&Key(1), |entry| {
+                entry.value = RcValue::default();
+                entry.broadcast();
+            })
+            .unwrap();
+        drop(inner);
+        assert_eq!(selected.borrow().last(), Some(&None));
+    }
+
+    #[test]
+    fn fallback_to_global_cache() {
+        struct Mutation;
+        assert!(cache_or_global::<Mutation>(None) == Cache::global());
+        assert!(cache_or_global::<Mutation>(None) == Cache::global());
+
+        // a provider mounted later takes precedence
+        let provided = Cache::<Mutation>::default();
+        assert!(cache_or_global(Some(provided.clone())) == provided);
+        assert!(provided != Cache::global());
+    }
+
+    #[test]
+    fn missing_provider_names_mutation_type() {
+        struct Mutation;
+        let message = missing_provider::<Mutation>();
+        assert!(message.starts_with("Cache not present, wrap this component in a CacheProvider<"));
+        assert!(message.ends_with("::Mutation>"));
+    }
+
+    #[test]
+    fn global_cache_per_mutation_type() {
+        assert!(Cache::<()>::global() == Cache::<()>::global());
+
+        struct Mutation;
+        let cache = Cache::<()>::global();
+        cache.cache.lock().unwrap().insert(Key(1), Entry::default());
+        let other = Cache::<Mutation>::global();
+        assert!(other.cache.lock().unwrap().entries.is_empty());
+        let global = Cache::<()>::global();
+        assert!(global.cache.lock().unwrap().get(&Key(1)).is_some());
+    }
+
+    #[test]
+    fn apply_mutation_invalidates_affected() {
+        #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+        struct User(u64);
+
+        impl Invalidatable<u64> for User {
+            fn invalidated_by(&self, mutation: &u64) -> bool {
+                self.0 == *mutation
+            }
+        }
+
+        #[async_trait::async_trait(?Send)]
+        impl CacheItem<u64> for User {
+            type Value = u64;
+            type Error = Failure;
+
+            async fn send(&self) -> Result<Self::Value, Self::Error> {
+                Ok(self.0)
+            }
+        }
+
+        struct Update(Vec<u64>);
+
+        impl Invalidator<u64> for Update {
+            fn mutations(&self) -> Vec<u64> {
+                self.0.clone()
+            }
+        }
+
+        let cache = Cache::<u64>::default();
+        for user in 1..=3 {
+            cache.cache.lock().unwrap().insert(
+                User(user),
+                Entry {
+                    value: RcValue::new(Rc::new(user) as Rc<dyn Any>),
+                    ..Default::default()
+                },
+            );
+        }
+        cache.apply_mutation(&Update(vec![1, 3]));
+
+        let cache = cache.cache.lock().unwrap();
+        let valid = |user| cache.get(&User(user)).unwrap().value.valid();
+        assert_eq!([valid(1), valid(2), valid(3)], [false, true, false]);
+    }
+
+    #[test]
+    fn insert_entry_if_absent_fetches() {
+        let cache = Cache::<()>::default().with_spawn_fn(|_future| {});
+        assert!(cache.insert_entry_if_absent(Key(1)));
+        assert!(!cache.insert_entry_if_absent(Key(1)));
+
+        let inner = cache.cache.lock().unwrap();
+        let entry = inner.get(&Key(1)).unwrap();
+        assert!(entry.progress);
+        assert!(entry.subscriptions.is_empty());
+        assert_eq!(inner.in_flight, 1);
+    }
+
+    #[test]
+    fn peek_has_no_side_effects() {
+        let clock = ManualClock::new();
+        let cache = Cache::<()>::default()
+            .with_clock(clock.clone())
+            .with_spawn_fn(|_future| {});
+        assert_eq!(cache.peek(&Key(1)), None);
+        assert_eq!(cache.peek_entry(&Key(1)), None);
+        assert!(cache.is_empty());
+
+        let (subscriber, _) = counter();
+        cache.subscribe_with(&Key(1), &subscriber, None, true);
+        assert_eq!(cache.peek(&Key(1)), Some(RcValue::default()));
+        assert_eq!(
+            cache.peek_entry(&Key(1)),
+            Some(EntryMetadata {
+                valid: false,
+                progress: true,
+                delay: None,
+                subscribers: 1,
+                failures: 0,
+                terminal: false,
+                value_type: None,
+            })
+        );
+
+        cache.failure(&Key(1), Failure);
+        cache.set_value(&Key(1), 5);
+        cache.invalidate_key(&Key(1));
+        clock.advance(Duration::from_secs(1));
+        let value = cache.peek(&Key(1)).unwrap();
+        assert_eq!(value.state(), &ValueState::Stale(Rc::new(5)));
+        let inner = cache.cache.lock().unwrap();
+        let entry = inner.entries.values().next().unwrap();
+        assert_eq!(entry.last_used.get(), Some(0.0));
+    }
+
+    #[test]
+    fn debug_cache_and_entry() {
+        let cache = cache_with(&[1, 2]);
+        let (subscriber, _) = counter();
+        cache.subscribe_with(&Key(1), &subscriber, None, false);
+        assert_eq!(
+            format!("{cache:?}"),
+            "Cache { entries: 2, keys: [Key(1), Key(2)] }"
+        );
+
+        let inner = cache.cache.lock().unwrap();
+        assert_eq!(format!("{cache:?}"), "Cache { <locked> }");
+        assert_eq!(
+            format!("{:?}", inner.get(&Key(1)).unwrap()),
+            "Entry { progress: false, delay: None, valid: true, subscriber_count: 1, .. }"
+        );
+    }
+
+    #[test]
+    fn seed_first_wins() {
+        let cache = cache_with(&[]);
+        assert!(cache.seed(&Key(1), 10));
+        assert!(!cache.seed(&Key(1), 20));
+
+        let value = cache.value_of(&Key(1));
+        assert!(!value.valid());
+        assert_eq!(value.data().map(|data| **data), Some(10));
+    }
+
+    #[test]
+    fn seed_never_overwrites() {
+        let cache = cache_with(&[1]);
+        cache.invalidate_key(&Key(1));
+        assert!(!cache.seed(&Key(1), 10));
+        assert_eq!(cache.value_of(&Key(1)).data().map(|data| **data), Some(1));
+    }
+
+    #[test]
+    fn seed_broadcasts_to_subscribers() {
+        let cache = cache_with(&[]);
+        let (subscriber, count) = counter();
+        cache.subscribe_with(&Key(1), &subscriber, None, false);
+        assert!(cache.seed(&Key(1), 10));
+        assert_eq!(count.get(), 1);
+    }
+
+    #[test]
+    fn set_creates_entry() {
+        let cache = cache_with(&[]);
+        cache.set(Key(1), 10);
+        assert!(cache.contains_valid(&Key(1)));
+        assert_eq!(cache.value_of(&Key(1)).data().map(|data| **data), Some(10));
+
+        let (subscriber, count) = counter();
+        cache.subscribe_with(&Key(1), &subscriber, None, false);
+        cache.invalidate_key(&Key(1));
+        cache.set(Key(1), 20);
+        assert_eq!(count.get(), 2);
+        assert!(cache.contains_valid(&Key(1)));
+        assert_eq!(cache.value_of(&Key(1)).data().map(|data| **data), Some(20));
+    }
+
+    #[test]
+    fn set_value_discards_in_flight_fetch() {
+        let cache = Cache::<()>::default().with_spawn_fn(|_future| {});
+        let (subscriber, count) = counter();
+        cache.subscribe_with(&Key(1), &subscriber, None, true);
+        assert_eq!(cache.in_flight(), 1);
+
+        cache.set_value(&Key(1), Rc::new(10));
+        assert_eq!(cache.in_flight(), 0);
+        assert_eq!(count.get(), 1);
+        assert_eq!(cache.generation_of(&Key(1)), Some(1));
+
+        // plain values are accepted as well
+        cache.set_value(&Key(1), 20);
+        assert_eq!(cache.generation_of(&Key(1)), Some(2));
+        assert_eq!(cache.value_of(&Key(1)).data().map(|data| **data), Some(20));
+    }
+
+    #[test]
+    fn set_value_wins_over_older_fetch() {
+        run_local(async {
+            let cache = Cache::<()>::default();
+            let (subscriber, _) = counter();
+            cache.subscribe_with(&Key(1), &subscriber, None, true);
+            cache.set_value(&Key(1), 10);
+            while cache.cache.lock().unwrap().fetching > 0 {
+                tokio::task::yield_now().await;
+            }
+            assert_eq!(cache.value_of(&Key(1)).data().map(|data| **data), Some(10));
+        });
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Tagged;
+
+    impl Invalidatable<()> for Tagged {}
+
+    #[async_trait::async_trait(?Send)]
+    impl CacheItem for Tagged {
+        type Value = u64;
+        type Error = Failure;
+
+        async fn send(&self) -> Result<Self::Value, Self::Error> {
+            Ok(1)
+        }
+
+        async fn send_conditional(
+            &self,
+            validator: Option<&str>,
+        ) -> Result<Conditional<Self::Value>, Self::Error> {
+            match validator {
+                Some("v1") => Ok(Conditional::NotModified),
+                _ => Ok(Conditional::Modified {
+                    value: 1,
+                    validator: Some("v1".into()),
+                }),
+            }
+        }
+    }
+
+    #[test]
+    fn conditional_fetch_revalidates() {
+        let cache = Cache::<()>::default().with_spawn_fn(|future| {
+            futures::executor::block_on(future);
+        });
+        let (subscriber, count) = counter();
+        cache.subscribe_with(&Tagged, &subscriber, None, true);
+        assert_eq!(cache.validator_of(&Tagged), Some("v1".into()));
+        assert_eq!(count.get(), 1);
+
+        // not modified, the value is valid again without being replaced
+        let before = cache.raw_value_of(&Tagged).unwrap();
+        cache.invalidate_key(&Tagged);
+        cache.subscribe_with(&Tagged, &subscriber, None, true);
+        let after = cache.raw_value_of(&Tagged).unwrap();
+        assert!(after.valid());
+        assert!(Rc::ptr_eq(before.data().unwrap(), after.data().unwrap()));
+        assert_eq!(count.get(), 3);
+        assert_eq!(cache.in_flight(), 0);
+    }
+
+    #[test]
+    fn not_modified_without_value_drops_validator() {
+        let cache = cache_with(&[]);
+        let (subscriber, _) = counter();
+        cache.subscribe_with(&Tagged, &subscriber, None, false);
+        cache.set_validator(&Tagged, Some("v1".into()));
+        cache.not_modified(&Tagged);
+        assert_eq!(cache.validator_of(&Tagged), None);
+        assert!(!cache.contains_valid(&Tagged));
+    }
+
+    #[test]
+    fn cache_transforms_response() {
+        #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+        struct Name;
+
+        impl Invalidatable<()> for Name {}
+
+        #[async_trait::async_trait(?Send)]
+        impl CacheItem for Name {
+            type Value = String;
+            type Error = Failure;
+
+            async fn send(&self) -> Result<Self::Value, Self::Error> {
+                Ok("  name ".into())
+            }
+
+            fn transform_response(&self, raw: Self::Value) -> Self::Value {
+                raw.trim().into()
+            }
+        }
+
+        let cache = cache_with(&[]);
+        cache.cache.lock().unwrap().insert(Name, Entry::default());
+        cache.cache(&Name, Rc::new("  name ".into()));
+        let value = cache.value_of(&Name);
+        assert_eq!(value.data().map(|data| data.as_str()), Some("name"));
+    }
+
+    #[test]
+    fn cache_contains() {
+        let cache = cache_with(&[]);
+        assert!(cache.is_empty());
+
+        let cache = cache_with(&[1, 2]);
+        cache.invalidate_key(&Key(2));
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.is_empty());
+        assert!(cache.contains(&Key(2)));
+        assert!(!cache.contains(&Key(3)));
+        assert!(cache.contains_valid(&Key(1)));
+        assert!(!cache.contains_valid(&Key(2)));
+        assert!(!cache.contains_valid(&Key(3)));
+    }
+
+    #[test]
+    fn in_flight_counts_fetches() {
+        run_local(async {
+            let cache = Cache::<()>::default();
+            let notified = Rc::new(Cell::new(0));
+            let activity = {
+                let notified = notified.clone();
+                Callback::from(move |_| notified.set(notified.get() + 1))
+            };
+            cache.subscribe_activity(&activity);
+
+            let (subscriber, _) = counter();
+            cache.subscribe_with(&Key(1), &subscriber, None, true);
+            cache.subscribe_with(&Key(2), &subscriber, None, true);
+            assert_eq!(cache.in_flight(), 2);
+            let filtered = cache
+                .cache
+                .lock()
+                .unwrap()
+                .in_flight_where(|key| format!("{key:?}") == "Key(1)");
+            assert_eq!(filtered, 1);
+
+            while cache.in_flight() > 0 {
+                tokio::task::yield_now().await;
+            }
+            assert_eq!(notified.get(), 4);
+
+            cache.unsubscribe_activity(&activity);
+            cache.cache.lock().unwrap().fetch_begun();
+            assert_eq!(notified.get(), 4);
+        });
+    }
+
+    #[test]
+    fn in_flight_never_negative() {
+        let cache = cache_with(&[1]);
+        cache.cache.lock().unwrap().fetch_done();
+        cache.failure(&Key(1), Failure);
+        cache.cache(&Key(1), Rc::new(1));
+        assert_eq!(cache.in_flight(), 0);
+    }
+
+    #[test]
+    fn remove_entry() {
+        let cache = cache_with(&[1, 2]);
+        let (subscriber, count) = counter();
+        cache.subscribe_with(&Key(1), &subscriber, None, false);
+
+        assert!(cache.remove(&Key(1)));
+        assert_eq!(count.get(), 1);
+        assert!(!cache.contains(&Key(1)));
+        assert!(cache.contains(&Key(2)));
+        assert!(!cache.remove(&Key(1)));
+    }
+
+    #[test]
+    fn removed_entry_not_resurrected() {
+        run_local(async {
+            let cache = Cache::<()>::default();
+            let (subscriber, _) = counter();
+            cache.subscribe_with(&Key(1), &subscriber, None, true);
+            assert!(cache.remove(&Key(1)));
+            assert_eq!(cache.in_flight(), 0);
+
+            // a new entry for the same key ignores the result of the old fetch
+            cache.subscribe_with(&Key(1), &subscriber, None, false);
+            while cache.cache.lock().unwrap().fetching > 0 {
+                tokio::task::yield_now().await;
+            }
+            assert_eq!(cache.peek(&Key(1)), Some(RcValue::default()));
+            assert_eq!(cache.in_flight(), 0);
+        });
+    }
+
+    #[test]
+    fn clear_discards_values_and_fetches() {
+        run_local(async {
+            let cache = Cache::<()>::default();
+            let (subscriber, count) = counter();
+            cache.subscribe_with(&Key(1), &subscriber, None, true);
+            cache.seed(&Key(2), 2);
+            cache.subscribe_with(&Key(2), &subscriber, None, false);
+            count.set(0);
+
+            cache.clear();
+            assert_eq!(count.get(), 2);
+            assert!(cache.is_empty());
+            assert_eq!(cache.in_flight(), 0);
+
+            // the fetch which was in flight is ignored by the new entry
+            cache.subscribe_with(&Key(1), &subscriber, None, false);
+            while cache.cache.lock().unwrap().fetching > 0 {
+                tokio::task::yield_now().await;
+            }
+            assert_eq!(cache.peek(&Key(1)), Some(RcValue::default()));
+        });
+    }
+
+    #[test]
+    fn spawn_fn_runs_fetches() {
+        let cache = Cache::<()>::default().with_spawn_fn(|future| {
+            futures::executor::block_on(future);
+        });
+        let (subscriber, count) = counter();
+        cache.subscribe_with(&Key(4), &subscriber, None, true);
+
+        assert_eq!(count.get(), 1);
+        assert_eq!(cache.in_flight(), 0);
+        assert_eq!(cache.value_of(&Key(4)).data().map(|data| **data), Some(4));
+    }
+
+    thread_local! {
+        static SPAWNED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    #[test]
+    fn concurrent_subscribers_fetch_once() {
+        // fetches are spawned but never run, so every subscriber sees the first one in progress
+        let cache = Cache::<()>::default().with_spawn_fn(|_future| {
+            SPAWNED.with(|spawned| spawned.set(spawned.get() + 1));
+        });
+        let (first, _) = counter();
+        let (second, _) = counter();
+        cache.subscribe_with(&Key(1), &first, None, true);
+        cache.subscribe_with(&Key(1), &second, None, true);
+        cache.prefetch(Key(1));
+        cache.subscribe_with(&Key(1), &first, None, true);
+
+        assert_eq!(SPAWNED.with(Cell::get), 1);
+        assert_eq!(cache.in_flight(), 1);
+        assert_eq!(subscribers(&cache, 1), 2);
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Eager(u64);
+
+    impl Invalidatable<()> for Eager {}
+
+    #[async_trait::async_trait(?Send)]
+    impl CacheItem for Eager {
+        type Value = u64;
+        type Error = Failure;
+
+        async fn send(&self) -> Result<Self::Value, Self::Error> {
+            Ok(self.0)
+        }
+
+        fn should_refetch(&self, entry: &Entry, _now: f64) -> bool {
+            entry.failures == 0
+        }
+    }
+
+    #[test]
+    fn custom_should_refetch() {
+        let cache = Cache::<()>::default().with_spawn_fn(|_future| {
+            SPAWNED.with(|spawned| spawned.set(spawned.get() + 1));
+        });
+        let (subscriber, _) = counter();
+        cache.subscribe_with(&Eager(1), &subscriber, None, true);
+        cache.cache(&Eager(1), Rc::new(1));
+
+        // valid values are refetched, but never while a fetch is in progress
+        cache.subscribe_with(&Eager(1), &subscriber, None, true);
+        cache.subscribe_with(&Eager(1), &subscriber, None, true);
+        assert_eq!(SPAWNED.with(Cell::get), 2);
+
+        // failed entries are not refetched
+        cache.failure(&Eager(1), Failure);
+        cache.subscribe_with(&Eager(1), &subscriber, None, true);
+        assert_eq!(SPAWNED.with(Cell::get), 2);
+    }
+
+    #[test]
+    fn subscribe_many_only_diffs_keys() {
+        let cache = Cache::<()>::default().with_spawn_fn(|_future| {
+            SPAWNED.with(|spawned| spawned.set(spawned.get() + 1));
+        });
+        let (subscriber, _) = counter();
+        let mut subscribed = vec![];
+        cache.subscribe_many(&subscriber, &mut subscribed, &[Key(1), Key(2)]);
+        assert_eq!(SPAWNED.with(Cell::get), 2);
+        cache.cache(&Key(1), Rc::new(1));
+        cache.cache(&Key(2), Rc::new(2));
+
+        // rerendering with the same keys neither subscribes nor fetches
+        cache.subscribe_many(&subscriber, &mut subscribed, &[Key(1), Key(2)]);
+        assert_eq!(SPAWNED.with(Cell::get), 2);
+        assert_eq!(subscribers(&cache, 1), 1);
+
+        cache.subscribe_many(&subscriber, &mut subscribed, &[Key(2), Key(3)]);
+        assert_eq!(subscribed, [Key(2), Key(3)]);
+        assert_eq!(subscribers(&cache, 1), 0);
+        assert_eq!(subscribers(&cache, 3), 1);
+        assert_eq!(SPAWNED.with(Cell::get), 3);
+
+        // invalidated keys are refetched without subscribing again
+        cache.invalidate_key(&Key(2));
+        cache.subscribe_many(&subscriber, &mut subscribed, &[Key(2), Key(3)]);
+        assert_eq!(SPAWNED.with(Cell::get), 4);
+        assert_eq!(subscribers(&cache, 2), 1);
+
+        // removed entries are subscribed to again
+        cache.cache(&Key(2), Rc::new(2));
+        cache.remove(&Key(2));
+        cache.subscribe_many(&subscriber, &mut subscribed, &[Key(2), Key(3)]);
+        assert_eq!(subscribers(&cache, 2), 1);
+        assert_eq!(SPAWNED.with(Cell::get), 5);
+    }
+
+    #[test]
+    fn entry_is_stale() {
+        let mut entry = Entry {
+            value: RcValue::new(Rc::new(1u64) as Rc<dyn Any>),
+            last_updated: Some(0.0),
+            ..Default::default()
+        };
+
+        // never stale by time without a ttl
+        assert!(!entry.is_stale(1e9));
+        assert!(!entry.needs_fetch(1e9));
+
+        entry.ttl = Some(Duration::from_secs(1));
+        assert!(!entry.is_stale(1000.0));
+        assert!(entry.is_stale(1001.0));
+        assert!(entry.needs_fetch(1001.0));
+
+        // empty values are not stale
+        entry.value = RcValue::default();
+        assert!(!entry.is_stale(1001.0));
+        assert!(entry.needs_fetch(1001.0));
+    }
+
+    #[test]
+    fn evict_least_recently_used() {
+        let clock = ManualClock::new();
+        let cache = cache_with(&[1, 2, 3, 4]).with_clock(clock.clone());
+        clock.advance(Duration::from_secs(1));
+        cache.contains(&Key(2));
+        clock.advance(Duration::from_secs(1));
+        cache.contains(&Key(1));
+        clock.advance(Duration::from_secs(1));
+        cache.contains(&Key(3));
+        let (subscriber, _) = counter();
+        cache.subscribe_with(&Key(4), &subscriber, None, false);
+
+        // entry 4 is subscribed, entry 2 is the least recently used
+        assert_eq!(cache.evict_lru(2), 2);
+        assert!(!cache.contains(&Key(2)));
+        assert!(!cache.contains(&Key(1)));
+        assert!(cache.contains(&Key(3)));
+        assert!(cache.contains(&Key(4)));
+
+        assert_eq!(cache.evict_lru(0), 1);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn evict_expired_finishes_fetch() {
+        let cache = Cache::<()>::default().with_spawn_fn(|_future| {});
+        cache.cache.lock().unwrap().insert(
+            Key(1),
+            Entry {
+                no_store: true,
+                ..Default::default()
+            },
+        );
+        assert!(cache.force_fetch(&Key(1)));
+        assert_eq!(cache.in_flight(), 1);
+
+        assert_eq!(cache.evict_expired(), 1);
+        assert_eq!(cache.in_flight(), 0);
+        cache.cache(&Key(1), Rc::new(1));
+        assert!(!cache.contains(&Key(1)));
+    }
+
+    #[test]
+    fn evict_lru_finishes_fetch() {
+        let cache = Cache::<()>::default().with_spawn_fn(|_future| {});
+        cache.prefetch(Key(1));
+        assert_eq!(cache.in_flight(), 1);
+
+        assert_eq!(cache.evict_lru(0), 1);
+        assert_eq!(cache.in_flight(), 0);
+
+        // the result of the fetch is discarded
+        cache.cache(&Key(1), Rc::new(1));
+        assert_eq!(cache.in_flight(), 0);
+        assert!(!cache.contains(&Key(1)));
+    }
 }
@@ -0,0 +1,153 @@
+//! Building caches with initial contents.
+use super::{BTreeCache, Cache, CacheOptions, Clock, Entry};
+use crate::{CacheItem, CacheKey, RcValue};
+use std::{any::Any, rc::Rc, sync::Mutex};
+
+/// Builder for a [`Cache`] with initial contents.
+///
+/// Entries inserted here are treated as freshly fetched, so they are not refetched when a
+/// component first subscribes to them. This allows rendering components against a seeded cache
+/// without any network access, for example in tests:
+///
+/// ```rust
+/// # use wasm_cache::{CacheItem, Invalidatable, yew::*};
+/// # use yew::prelude::*;
+/// # #[derive(Debug)]
+/// # struct Offline;
+/// # impl std::fmt::Display for Offline {
+/// #     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+/// #         write!(f, "offline")
+/// #     }
+/// # }
+/// # impl std::error::Error for Offline {}
+/// #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// struct UserName(u64);
+///
+/// impl Invalidatable<()> for UserName {}
+///
+/// #[async_trait::async_trait(?Send)]
+/// impl CacheItem for UserName {
+///     type Value = String;
+///     type Error = Offline;
+///
+///     async fn send(&self) -> Result<String, Offline> {
+///         Err(Offline)
+///     }
+/// }
+///
+/// #[function_component]
+/// fn Profile() -> Html {
+///     let name = use_cached(UserName(1));
+///     html! { <p>{ name.data().map(|name| name.to_string()) }</p> }
+/// }
+///
+/// let cache = CacheBuilder::new()
+///     .insert(UserName(1), "Alice".to_string())
+///     .build();
+/// let app = html! {
+///     <CacheProvider {cache}>
+///         <Profile />
+///     </CacheProvider>
+/// };
+/// ```
+pub struct CacheBuilder<M: 'static = ()> {
+    cache: BTreeCache<M>,
+}
+
+impl<M: 'static> Default for CacheBuilder<M> {
+    fn default() -> Self {
+        Self {
+            cache: Default::default(),
+        }
+    }
+}
+
+impl<M: 'static> CacheBuilder<M> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use these options for the cache.
+    pub fn options(mut self, options: CacheOptions) -> Self {
+        self.cache.options = options;
+        self
+    }
+
+    /// Use this clock for the cache, see [`Cache::with_clock`].
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.cache.clock = Rc::new(clock);
+        self
+    }
+
+    /// Insert a fresh value for this key.
+    pub fn insert<T: CacheItem<M>>(self, key: T, value: T::Value) -> Self {
+        let value = RcValue::new(Rc::new(value) as Rc<dyn Any>);
+        self.insert_raw(Box::new(key.cache_key().into_owned()), value)
+    }
+
+    /// Insert a fresh type-erased value for this key.
+    pub fn insert_raw(mut self, key: Box<dyn CacheKey<M>>, value: RcValue) -> Self {
+        let now = self.cache.clock.now();
+        let entry = Entry {
+            value,
+            created_at: Some(now),
+            last_updated: Some(now),
+            ..Default::default()
+        };
+        self.cache.entries.insert(key, entry);
+        self
+    }
+
+    /// Build the cache.
+    pub fn build(self) -> Cache<M> {
+        Cache {
+            cache: Rc::new(Mutex::new(self.cache)),
+        }
+    }
+}
+
+impl<M: 'static> Cache<M> {
+    /// Create a new cache with these initial entries.
+    ///
+    /// The entries are treated as freshly fetched, see [`CacheBuilder`].
+    pub fn with_entries(
+        entries: impl IntoIterator<Item = (Box<dyn CacheKey<M>>, RcValue)>,
+    ) -> Self {
+        entries
+            .into_iter()
+            .fold(CacheBuilder::new(), |builder, (key, value)| {
+                builder.insert_raw(key, value)
+            })
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yew::tests::{counter, Key};
+
+    #[test]
+    fn built_entries_are_fresh() {
+        let cache = CacheBuilder::<()>::new().insert(Key(1), 10).build();
+        let (subscriber, count) = counter();
+        cache.subscribe_with(&Key(1), &subscriber, None, true);
+
+        assert_eq!(cache.in_flight(), 0);
+        assert_eq!(count.get(), 0);
+        assert_eq!(cache.value_of(&Key(1)).data().map(|data| **data), Some(10));
+    }
+
+    #[test]
+    fn cache_with_entries() {
+        let cache = Cache::<()>::with_entries([
+            (
+                Box::new(Key(1)) as Box<dyn CacheKey>,
+                RcValue::new(Rc::new(1u64) as Rc<dyn Any>),
+            ),
+            (Box::new(Key(2)), RcValue::new(Rc::new(2u64))),
+        ]);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.contains_valid(&Key(2)));
+    }
+}
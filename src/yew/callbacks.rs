@@ -0,0 +1,323 @@
+//! Callbacks of individual subscribers for completed fetches.
+
+use super::{use_cached_with_options, Cache, CacheItem, QueryOptions, Subscriber};
+use crate::RcValue;
+use std::{
+    any::{Any, TypeId},
+    error::Error,
+    rc::Rc,
+};
+use yew::prelude::*;
+
+/// Callbacks which are invoked when a fetch of the subscribed data completes.
+pub struct FetchCallbacks<V> {
+    /// Called with the new value after a successful fetch.
+    pub on_success: Option<Callback<Rc<V>>>,
+    /// Called with the error after a failed fetch.
+    pub on_error: Option<Callback<Rc<dyn Error>>>,
+}
+
+impl<V> Clone for FetchCallbacks<V> {
+    fn clone(&self) -> Self {
+        Self {
+            on_success: self.on_success.clone(),
+            on_error: self.on_error.clone(),
+        }
+    }
+}
+
+impl<V> Default for FetchCallbacks<V> {
+    fn default() -> Self {
+        Self {
+            on_success: None,
+            on_error: None,
+        }
+    }
+}
+
+impl<V> PartialEq for FetchCallbacks<V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.on_success == other.on_success && self.on_error == other.on_error
+    }
+}
+
+impl<V: 'static> FetchCallbacks<V> {
+    /// Type-erased listener for these callbacks, to store in an [`Entry`](super::Entry).
+    pub fn into_listener(self) -> FetchListener {
+        let listener = FetchListener {
+            on_error: self.on_error,
+            ..Default::default()
+        };
+        match self.on_success {
+            Some(callback) => listener.with_success(callback),
+            None => listener,
+        }
+    }
+}
+
+/// Type-erased [`FetchCallbacks`] of a subscriber.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FetchListener {
+    pub on_success: Option<Callback<Rc<dyn Any>>>,
+    pub on_error: Option<Callback<Rc<dyn Error>>>,
+    /// Called with the new value after every write of a valid value, not only after fetches.
+    pub on_value: Option<Callback<Rc<dyn Any>>>,
+    /// Type of the values `on_success` accepts, see [`FetchListener::check_value_type`].
+    pub value_type: Option<(TypeId, &'static str)>,
+}
+
+impl FetchListener {
+    /// Determine if this listener has no callbacks.
+    pub fn is_empty(&self) -> bool {
+        self.on_success.is_none() && self.on_error.is_none() && self.on_value.is_none()
+    }
+
+    /// Call the callbacks for this new value, `on_success` only if it has been `fetched`.
+    pub fn emit_value(&self, value: &Rc<dyn Any>, fetched: bool) {
+        if let Some(on_success) = self.on_success.as_ref().filter(|_| fetched) {
+            on_success.emit(value.clone());
+        }
+        if let Some(on_value) = &self.on_value {
+            on_value.emit(value.clone());
+        }
+    }
+
+    /// Set the callback for successful fetches, which accepts values of type `V`.
+    fn with_success<V: 'static>(self, callback: Callback<Rc<V>>) -> Self {
+        Self {
+            on_success: Some(Callback::from(move |value: Rc<dyn Any>| {
+                callback.emit(value.downcast().expect("Value is of wrong type"));
+            })),
+            value_type: Some((TypeId::of::<V>(), std::any::type_name::<V>())),
+            ..self
+        }
+    }
+
+    /// Check that `on_success` accepts the values of the data it is set for.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the callback accepts values of another type, since it could never be called.
+    pub fn check_value_type<V: 'static>(&self) {
+        if let Some((type_id, name)) = self.value_type {
+            assert!(
+                type_id == TypeId::of::<V>(),
+                "on_success callback accepts {name}, but the value is {}",
+                std::any::type_name::<V>()
+            );
+        }
+    }
+}
+
+impl QueryOptions {
+    /// Call this callback with the new value whenever a fetch succeeds while subscribed.
+    ///
+    /// Like all options, this is per component: every component subscribing with a callback has
+    /// it called. It is not called for data which was already cached when subscribing, nor after
+    /// unsubscribing. The callback must accept the [`Value`](CacheItem::Value) of the data these
+    /// options are used for, subscribing panics otherwise.
+    pub fn on_success<V: 'static>(mut self, callback: Callback<Rc<V>>) -> Self {
+        self.listener = self.listener.with_success(callback);
+        self
+    }
+
+    /// Call this callback with the error whenever a fetch fails while subscribed.
+    ///
+    /// See [`QueryOptions::on_success`] for when it is called. The concrete error of the data can
+    /// be obtained by downcasting it, or with [`Cache::error_of`].
+    pub fn on_error(mut self, callback: Callback<Rc<dyn Error>>) -> Self {
+        self.listener.on_error = Some(callback);
+        self
+    }
+}
+
+impl<M: 'static> Cache<M> {
+    /// Set the fetch listener of this subscriber of the data.
+    ///
+    /// The listener is removed when the subscriber unsubscribes. Does nothing if there is no
+    /// entry for this data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the success callback of the listener does not accept the value of the data, see
+    /// [`FetchListener::check_value_type`].
+    pub fn set_fetch_listener<T: CacheItem<M>>(
+        &self,
+        data: &T,
+        subscriber: &Subscriber,
+        listener: FetchListener,
+    ) {
+        listener.check_value_type::<T::Value>();
+        let _ = self
+            .cache
+            .lock()
+            .expect("Failure to lock cache")
+            .mutate(data, |entry| entry.set_listener(subscriber, listener));
+    }
+}
+
+/// Use cached data, invoking callbacks whenever a fetch of it completes.
+///
+/// Behaves like [`use_cached_with_options`] with the callbacks set as
+/// [`QueryOptions::on_success`] and [`QueryOptions::on_error`]. The callbacks are only invoked for
+/// fetches which complete while this component is subscribed, not for data which was already
+/// cached. This is useful for side effects, such as showing a notification when a fetch fails.
+#[hook]
+pub fn use_cached_with_callbacks<M: 'static, R: CacheItem<M>>(
+    data: R,
+    options: QueryOptions,
+    callbacks: FetchCallbacks<R::Value>,
+) -> RcValue<R::Value> {
+    let options = QueryOptions {
+        listener: callbacks.into_listener(),
+        ..options
+    };
+    use_cached_with_options::<M, R>(data, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yew::tests::{cache_with, counter, Failure, Key};
+    use std::cell::RefCell;
+
+    type Events = Rc<RefCell<Vec<String>>>;
+
+    /// Listener which records the events under this name.
+    fn recording(name: &'static str, events: &Events) -> FetchListener {
+        let success = events.clone();
+        let error = events.clone();
+        FetchCallbacks::<u64> {
+            on_success: Some(Callback::from(move |value: Rc<u64>| {
+                success.borrow_mut().push(format!("{name}: {value}"));
+            })),
+            on_error: Some(Callback::from(move |failure: Rc<dyn Error>| {
+                error.borrow_mut().push(format!("{name}: {failure}"));
+            })),
+        }
+        .into_listener()
+    }
+
+    #[test]
+    fn listeners_per_subscriber() {
+        let cache = cache_with(&[1]);
+        let events = Events::default();
+        let (first, _) = counter();
+        let (second, _) = counter();
+        cache.subscribe_with(&Key(1), &first, None, false);
+        cache.subscribe_with(&Key(1), &second, None, false);
+        cache.set_fetch_listener(&Key(1), &first, recording("first", &events));
+        cache.set_fetch_listener(&Key(1), &second, recording("second", &events));
+        cache.set_fetch_listener(&Key(1), &second, recording("replaced", &events));
+
+        cache.cache_fetched(&Key(1), Rc::new(2));
+        cache.failure(&Key(1), Failure);
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                "first: 2",
+                "replaced: 2",
+                "first: failure",
+                "replaced: failure"
+            ]
+        );
+
+        // no callbacks after unsubscribing
+        events.borrow_mut().clear();
+        cache.unsubscribe(&Key(1), &first);
+        cache.cache_fetched(&Key(1), Rc::new(3));
+        assert_eq!(*events.borrow(), vec!["replaced: 3"]);
+    }
+
+    /// Options with callbacks which record the events under this name.
+    fn options(name: &'static str, events: &Events) -> QueryOptions {
+        let success = events.clone();
+        let error = events.clone();
+        QueryOptions {
+            enabled: false,
+            ..Default::default()
+        }
+        .on_success(Callback::from(move |value: Rc<u64>| {
+            success.borrow_mut().push(format!("{name}: {value}"));
+        }))
+        .on_error(Callback::from(move |failure: Rc<dyn Error>| {
+            error.borrow_mut().push(format!("{name}: {failure}"));
+        }))
+    }
+
+    #[test]
+    fn option_callbacks_per_subscriber() {
+        let cache = cache_with(&[1]);
+        let events = Events::default();
+        let (first, _) = counter();
+        let (second, _) = counter();
+        let (plain, _) = counter();
+        cache.subscribe_with_options(&Key(1), &first, None, &options("first", &events), false);
+        cache.subscribe_with_options(&Key(1), &second, None, &options("second", &events), false);
+        cache.subscribe_with(&Key(1), &plain, None, false);
+
+        // already cached data does not trigger the callbacks
+        assert!(events.borrow().is_empty());
+
+        // subscribing again without callbacks removes the previous ones
+        cache.subscribe_with(&Key(1), &second, None, false);
+        cache.cache_fetched(&Key(1), Rc::new(2));
+        cache.failure(&Key(1), Failure);
+        cache.unsubscribe(&Key(1), &first);
+        cache.failure(&Key(1), Failure);
+        assert_eq!(*events.borrow(), vec!["first: 2", "first: failure"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "on_success callback accepts alloc::string::String")]
+    fn option_callback_of_wrong_type() {
+        let cache = cache_with(&[1]);
+        let (subscriber, _) = counter();
+        let options = QueryOptions::default().on_success(Callback::from(|_: Rc<String>| {}));
+        cache.subscribe_with_options(&Key(1), &subscriber, None, &options, false);
+    }
+
+    #[test]
+    fn listeners_only_called_for_fetches() {
+        let cache = cache_with(&[1]);
+        let events = Events::default();
+        let sinks = Rc::new(RefCell::new(vec![]));
+        let recorded = sinks.clone();
+        cache.attach_source(&Key(1), move |sink| recorded.borrow_mut().push(sink));
+        let (subscriber, _) = counter();
+        cache.subscribe_with(&Key(1), &subscriber, None, false);
+        cache.set_fetch_listener(&Key(1), &subscriber, recording("first", &events));
+
+        // values written or pushed do not come from a fetch
+        cache.set_value(&Key(1), 2);
+        cache.cache(&Key(1), Rc::new(3));
+        assert!(sinks.borrow()[0].push(4));
+        assert!(events.borrow().is_empty());
+
+        cache.cache_fetched(&Key(1), Rc::new(5));
+        assert_eq!(*events.borrow(), vec!["first: 5"]);
+    }
+
+    #[test]
+    fn listeners_called_when_not_modified() {
+        let cache = cache_with(&[1]);
+        let events = Events::default();
+        let (subscriber, _) = counter();
+        cache.subscribe_with(&Key(1), &subscriber, None, false);
+        cache.set_fetch_listener(&Key(1), &subscriber, recording("first", &events));
+        cache.invalidate_key(&Key(1));
+        cache.not_modified(&Key(1));
+        assert_eq!(*events.borrow(), vec!["first: 1"]);
+    }
+
+    #[test]
+    fn listeners_not_called_for_seeded_data() {
+        let cache = cache_with(&[]);
+        let events = Events::default();
+        let (subscriber, _) = counter();
+        cache.subscribe_with(&Key(1), &subscriber, None, false);
+        cache.set_fetch_listener(&Key(1), &subscriber, recording("first", &events));
+        cache.seed(&Key(1), 5);
+        assert!(events.borrow().is_empty());
+    }
+}
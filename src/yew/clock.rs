@@ -0,0 +1,152 @@
+//! Time source of the cache.
+//!
+//! All time-based logic of the [`Cache`](super::Cache) (backoff delays, stale times, expiry and
+//! eviction) goes through a [`Clock`]. By default this is the [`SystemClock`], tests can use a
+//! [`ManualClock`] instead to advance time deterministically.
+
+use super::now;
+use std::{
+    cell::RefCell,
+    fmt::Debug,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+/// Source of timestamps and timers.
+pub trait Clock: Debug {
+    /// Current timestamp in milliseconds.
+    fn now(&self) -> f64;
+
+    /// Future which resolves once `duration` has passed.
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()>>>;
+}
+
+/// Clock using the real time and the timers of the runtime.
+///
+/// On native targets, this uses the timers of tokio.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> f64 {
+        now()
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(prokio::time::sleep(duration))
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+#[derive(Debug, Default)]
+struct ManualState {
+    now: f64,
+    sleeps: Vec<Duration>,
+    sleepers: Vec<(f64, Waker)>,
+}
+
+/// Clock which only advances when told to.
+///
+/// Starts at timestamp zero. Sleeps resolve once the clock has been advanced past their deadline,
+/// and every requested sleep duration is recorded so tests can assert on scheduled delays.
+#[derive(Clone, Debug, Default)]
+pub struct ManualClock {
+    state: Rc<RefCell<ManualState>>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Advance the clock, waking all sleeps whose deadline has passed.
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.state.borrow_mut();
+        state.now += duration.as_secs_f64() * 1000.0;
+        let now = state.now;
+        let (expired, waiting) = std::mem::take(&mut state.sleepers)
+            .into_iter()
+            .partition(|(deadline, _)| *deadline <= now);
+        state.sleepers = waiting;
+        drop(state);
+        for (_, waker) in expired {
+            waker.wake();
+        }
+    }
+
+    /// Durations of all sleeps requested so far, in order.
+    pub fn sleeps(&self) -> Vec<Duration> {
+        self.state.borrow().sleeps.clone()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> f64 {
+        self.state.borrow().now
+    }
+
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()>>> {
+        let mut state = self.state.borrow_mut();
+        state.sleeps.push(duration);
+        let deadline = state.now + duration.as_secs_f64() * 1000.0;
+        Box::pin(ManualSleep {
+            state: self.state.clone(),
+            deadline,
+        })
+    }
+}
+
+/// Sleep of a [`ManualClock`].
+struct ManualSleep {
+    state: Rc<RefCell<ManualState>>,
+    deadline: f64,
+}
+
+impl Future for ManualSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.state.borrow_mut();
+        if state.now >= self.deadline {
+            return Poll::Ready(());
+        }
+        state.sleepers.push((self.deadline, cx.waker().clone()));
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::block_on, poll};
+
+    #[test]
+    fn manual_clock_advances() {
+        let clock = ManualClock::new();
+        assert_eq!(clock.now(), 0.0);
+        clock.advance(Duration::from_millis(150));
+        assert_eq!(clock.now(), 150.0);
+    }
+
+    #[test]
+    fn manual_sleep_resolves_after_advance() {
+        let clock = ManualClock::new();
+        let mut sleep = clock.sleep(Duration::from_millis(100));
+        block_on(async {
+            assert!(poll!(&mut sleep).is_pending());
+            clock.advance(Duration::from_millis(99));
+            assert!(poll!(&mut sleep).is_pending());
+            clock.advance(Duration::from_millis(1));
+            assert!(poll!(&mut sleep).is_ready());
+        });
+        assert_eq!(clock.sleeps(), vec![Duration::from_millis(100)]);
+    }
+}
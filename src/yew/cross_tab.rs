@@ -0,0 +1,255 @@
+//! Synchronization of invalidations between browser tabs.
+//!
+//! Once enabled, every mutation passed to [`Cache::invalidate`] is serialized as JSON and posted
+//! to a [`SyncChannel`], and mutations received from it are invalidated locally. In the browser,
+//! [`Cache::enable_cross_tab_sync`] uses a `BroadcastChannel`, which reaches every other tab of
+//! the same origin.
+
+use super::Cache;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    fmt::{Debug, Formatter, Result},
+    rc::Rc,
+};
+
+/// Channel connecting the caches of several tabs, see [`Cache::enable_sync`].
+pub trait SyncChannel {
+    /// Post this message to the other tabs, but not to this one.
+    fn post(&self, message: &str);
+
+    /// Call this function with every message posted by the other tabs.
+    fn on_message(&self, receiver: Box<dyn Fn(String)>);
+}
+
+/// Connection of a cache to a [`SyncChannel`].
+pub struct CrossTabSync<M: 'static> {
+    post: Rc<dyn Fn(&M)>,
+}
+
+impl<M: 'static> Clone for CrossTabSync<M> {
+    fn clone(&self) -> Self {
+        Self {
+            post: self.post.clone(),
+        }
+    }
+}
+
+impl<M: 'static> Debug for CrossTabSync<M> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_struct("CrossTabSync").finish_non_exhaustive()
+    }
+}
+
+impl<M: Serialize + DeserializeOwned + Debug + 'static> Cache<M> {
+    /// Synchronize invalidations with the other tabs connected to this channel.
+    ///
+    /// Replaces any previous channel. Messages which cannot be deserialized are logged and
+    /// ignored. Invalidations received from the channel are not posted back to it.
+    pub fn enable_sync(&self, channel: impl SyncChannel + 'static) {
+        let cache = Rc::downgrade(&self.cache);
+        channel.on_message(Box::new(move |message| {
+            let Some(cache) = cache.upgrade() else {
+                return;
+            };
+            match serde_json::from_str::<M>(&message) {
+                Ok(mutation) => {
+                    log::debug!("Cross-tab mutation: {mutation:?}");
+                    Cache { cache }.invalidate_local(&mutation);
+                }
+                Err(error) => log::error!("Error in cross-tab message: {error}"),
+            }
+        }));
+        let post = move |mutation: &M| match serde_json::to_string(mutation) {
+            Ok(message) => channel.post(&message),
+            Err(error) => log::error!("Error serializing {mutation:?}: {error}"),
+        };
+        self.cache.lock().expect("Failure to lock cache").sync = Some(CrossTabSync {
+            post: Rc::new(post),
+        });
+    }
+
+    /// Synchronize invalidations with the other tabs using the `BroadcastChannel` of this name.
+    ///
+    /// Every tab which enables this with the same name receives the invalidations of the others.
+    /// Errors creating the channel are logged.
+    #[cfg(target_arch = "wasm32")]
+    pub fn enable_cross_tab_sync(&self, channel_name: &str) {
+        match broadcast::BroadcastSync::new(channel_name) {
+            Ok(channel) => self.enable_sync(channel),
+            Err(error) => log::error!("Error opening BroadcastChannel: {error:?}"),
+        }
+    }
+}
+
+impl<M: 'static> Cache<M> {
+    /// Stop synchronizing invalidations with other tabs, closing the channel.
+    pub fn disable_cross_tab_sync(&self) {
+        let sync = self
+            .cache
+            .lock()
+            .expect("Failure to lock cache")
+            .sync
+            .take();
+        drop(sync);
+    }
+
+    /// Post this mutation to the other tabs, if synchronization is enabled.
+    pub(super) fn post_sync(&self, mutation: &M) {
+        let sync = self
+            .cache
+            .lock()
+            .expect("Failure to lock cache")
+            .sync
+            .clone();
+        if let Some(sync) = sync {
+            (sync.post)(mutation);
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod broadcast {
+    use super::SyncChannel;
+    use std::cell::RefCell;
+    use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+    use web_sys::{BroadcastChannel, MessageEvent};
+
+    /// [`SyncChannel`] using a `BroadcastChannel`, which is closed when this is dropped.
+    pub(super) struct BroadcastSync {
+        channel: BroadcastChannel,
+        listener: RefCell<Option<Closure<dyn Fn(MessageEvent)>>>,
+    }
+
+    impl BroadcastSync {
+        pub(super) fn new(name: &str) -> Result<Self, JsValue> {
+            Ok(Self {
+                channel: BroadcastChannel::new(name)?,
+                listener: RefCell::new(None),
+            })
+        }
+    }
+
+    impl SyncChannel for BroadcastSync {
+        fn post(&self, message: &str) {
+            if let Err(error) = self.channel.post_message(&JsValue::from_str(message)) {
+                log::error!("Error posting to BroadcastChannel: {error:?}");
+            }
+        }
+
+        fn on_message(&self, receiver: Box<dyn Fn(String)>) {
+            let listener = Closure::<dyn Fn(MessageEvent)>::new(move |event: MessageEvent| {
+                if let Some(message) = event.data().as_string() {
+                    receiver(message);
+                }
+            });
+            self.channel
+                .set_onmessage(Some(listener.as_ref().unchecked_ref()));
+            self.listener.replace(Some(listener));
+        }
+    }
+
+    impl Drop for BroadcastSync {
+        fn drop(&mut self) {
+            self.channel.set_onmessage(None);
+            self.channel.close();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{yew::tests::Failure, CacheItem, Invalidatable};
+    use std::cell::RefCell;
+
+    #[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+    struct UserChanged(u64);
+
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct User(u64);
+
+    impl Invalidatable<UserChanged> for User {
+        fn invalidated_by(&self, mutation: &UserChanged) -> bool {
+            self.0 == mutation.0
+        }
+    }
+
+    #[async_trait::async_trait(?Send)]
+    impl CacheItem<UserChanged> for User {
+        type Value = u64;
+        type Error = Failure;
+
+        async fn send(&self) -> std::result::Result<Self::Value, Self::Error> {
+            Ok(self.0)
+        }
+    }
+
+    type Receivers = Rc<RefCell<Vec<(usize, Box<dyn Fn(String)>)>>>;
+
+    /// Simulated channel, delivering messages to every other endpoint of the same hub.
+    struct Endpoint {
+        id: usize,
+        hub: Receivers,
+        posted: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl SyncChannel for Endpoint {
+        fn post(&self, message: &str) {
+            self.posted.borrow_mut().push(message.into());
+            for (id, receiver) in self.hub.borrow().iter() {
+                if *id != self.id {
+                    receiver(message.into());
+                }
+            }
+        }
+
+        fn on_message(&self, receiver: Box<dyn Fn(String)>) {
+            self.hub.borrow_mut().push((self.id, receiver));
+        }
+    }
+
+    /// Cache of a tab with a valid value for the first two users.
+    fn tab(hub: &Receivers, id: usize) -> (Cache<UserChanged>, Rc<RefCell<Vec<String>>>) {
+        let cache = Cache::<UserChanged>::default().with_spawn_fn(|_future| {});
+        cache.set(User(1), 1);
+        cache.set(User(2), 2);
+        let posted = Rc::<RefCell<Vec<String>>>::default();
+        cache.enable_sync(Endpoint {
+            id,
+            hub: hub.clone(),
+            posted: posted.clone(),
+        });
+        (cache, posted)
+    }
+
+    #[test]
+    fn invalidations_synced_between_tabs() {
+        let hub = Receivers::default();
+        let (first, posted) = tab(&hub, 0);
+        let (second, received) = tab(&hub, 1);
+
+        first.invalidate(&UserChanged(1));
+        assert_eq!(*posted.borrow(), ["1"]);
+        assert!(!first.contains_valid(&User(1)));
+        assert!(!second.contains_valid(&User(1)));
+        assert!(second.contains_valid(&User(2)));
+
+        // received invalidations are not posted back
+        assert!(received.borrow().is_empty());
+
+        first.disable_cross_tab_sync();
+        first.invalidate(&UserChanged(2));
+        assert!(second.contains_valid(&User(2)));
+        assert_eq!(posted.borrow().len(), 1);
+    }
+
+    #[test]
+    fn invalid_messages_ignored() {
+        let hub = Receivers::default();
+        let (cache, _) = tab(&hub, 0);
+        for (_, receiver) in hub.borrow().iter() {
+            receiver("not json".into());
+        }
+        assert!(cache.contains_valid(&User(1)));
+    }
+}
@@ -0,0 +1,222 @@
+//! Panel listing the entries of the cache, for debugging.
+
+use super::{use_cache, Cache, CacheKey};
+use std::{cell::Cell, rc::Rc, time::Duration};
+use yew::prelude::*;
+
+/// State of a cache entry as shown by [`CacheDebugView`], see [`Cache::debug_entries`].
+#[derive(Clone, Debug)]
+pub struct DebugEntry<M: 'static = ()> {
+    /// Key of the entry.
+    pub key: Box<dyn CacheKey<M>>,
+    /// Type name of the cached value, if known.
+    pub value_type: Option<&'static str>,
+    /// Whether the value is valid.
+    pub valid: bool,
+    /// Whether the entry holds data, even if it is not valid.
+    pub has_data: bool,
+    /// Time since the value was last updated.
+    pub since_updated: Option<Duration>,
+    /// Whether a fetch is in progress.
+    pub progress: bool,
+    /// Delay to use for the next fetch.
+    pub delay: Option<Duration>,
+    /// Number of subscribers.
+    pub subscribers: usize,
+}
+
+impl<M: 'static> DebugEntry<M> {
+    /// Short description of the state of the value.
+    pub fn state(&self) -> &'static str {
+        match (self.valid, self.has_data) {
+            (true, _) => "valid",
+            (false, true) => "stale",
+            (false, false) => "empty",
+        }
+    }
+}
+
+impl<M: 'static> Cache<M> {
+    /// State of every entry, in key order, see [`CacheDebugView`].
+    pub fn debug_entries(&self) -> Vec<DebugEntry<M>> {
+        let cache = self.cache.lock().expect("Failure to lock cache");
+        let now = cache.clock.now();
+        cache
+            .entries
+            .iter()
+            .map(|(key, entry)| DebugEntry {
+                key: key.clone(),
+                value_type: entry.value_type,
+                valid: entry.value.valid(),
+                has_data: entry.value.data().is_some(),
+                since_updated: entry.since_updated(now),
+                progress: entry.progress,
+                delay: entry.delay,
+                subscribers: entry.subscriptions.len(),
+            })
+            .collect()
+    }
+}
+
+/// Properties of [`CacheDebugView`].
+#[derive(Properties, PartialEq)]
+pub struct CacheDebugViewProps {
+    /// Show the entries initially, rather than only the summary.
+    #[prop_or_default]
+    pub open: bool,
+    /// Interval at which the panel is refreshed, in addition to whenever fetches start or finish.
+    #[prop_or(Duration::from_secs(1))]
+    pub refresh_interval: Duration,
+}
+
+/// Collapsible panel listing every entry of the cache of the surrounding [`CacheProvider`].
+///
+/// Shows the key, value type, state, age, fetch progress, backoff delay and subscriber count of
+/// each entry, with buttons to invalidate or remove it, and to invalidate the whole cache. The
+/// panel refreshes whenever a fetch starts or finishes, and at the refresh interval to pick up
+/// other changes. It renders plain, unstyled HTML, so that it can stay mounted in production
+/// builds, for example behind a keyboard shortcut.
+///
+/// [`CacheProvider`]: super::CacheProvider
+#[function_component]
+pub fn CacheDebugView<M: 'static = ()>(props: &CacheDebugViewProps) -> Html {
+    let cache = use_cache::<M>();
+    let refresh = use_force_update();
+    use_effect_with_deps(
+        |(cache, interval)| {
+            let activity = Callback::from({
+                let refresh = refresh.clone();
+                move |_| refresh.force_update()
+            });
+            cache.subscribe_activity(&activity);
+
+            let cancel = Rc::new(Cell::new(false));
+            let (cancelled, interval, handle) = (cancel.clone(), *interval, cache.clone());
+            cache.spawn(async move {
+                loop {
+                    handle.sleep(interval).await;
+                    if cancelled.get() {
+                        break;
+                    }
+                    refresh.force_update();
+                }
+            });
+
+            let cache = cache.clone();
+            move || {
+                cancel.set(true);
+                cache.unsubscribe_activity(&activity);
+            }
+        },
+        (cache.clone(), props.refresh_interval),
+    );
+
+    let entries = cache.debug_entries();
+    let invalidate_all = {
+        let cache = cache.clone();
+        Callback::from(move |_| cache.invalidate_all())
+    };
+    let rows = entries.into_iter().map(|entry| {
+        let invalidate = {
+            let (cache, key) = (cache.clone(), entry.key.clone());
+            Callback::from(move |_| {
+                cache.invalidate_erased(key.as_ref());
+            })
+        };
+        let remove = {
+            let (cache, key) = (cache.clone(), entry.key.clone());
+            Callback::from(move |_| {
+                cache.remove_erased(key.as_ref());
+            })
+        };
+        html! {
+            <tr>
+                <td><code>{ format!("{:?}", entry.key) }</code></td>
+                <td>{ entry.value_type.unwrap_or("-") }</td>
+                <td>{ entry.state() }</td>
+                <td>{ format_duration(entry.since_updated) }</td>
+                <td>{ if entry.progress { "fetching" } else { "-" } }</td>
+                <td>{ format_duration(entry.delay) }</td>
+                <td>{ entry.subscribers }</td>
+                <td>
+                    <button onclick={invalidate}>{ "Invalidate" }</button>
+                    <button onclick={remove}>{ "Remove" }</button>
+                </td>
+            </tr>
+        }
+    });
+
+    html! {
+        <details class="wasm-cache-debug" open={props.open}>
+            <summary>{ format!("Cache ({} entries, {} in flight)", cache.len(), cache.in_flight()) }</summary>
+            <button onclick={invalidate_all}>{ "Invalidate all" }</button>
+            <table>
+                <thead>
+                    <tr>
+                        <th>{ "Key" }</th>
+                        <th>{ "Type" }</th>
+                        <th>{ "State" }</th>
+                        <th>{ "Age" }</th>
+                        <th>{ "Progress" }</th>
+                        <th>{ "Backoff" }</th>
+                        <th>{ "Subscribers" }</th>
+                        <th></th>
+                    </tr>
+                </thead>
+                <tbody>{ for rows }</tbody>
+            </table>
+        </details>
+    }
+}
+
+/// Format a duration for display, with one decimal.
+fn format_duration(duration: Option<Duration>) -> String {
+    match duration {
+        Some(duration) => format!("{:.1}s", duration.as_secs_f64()),
+        None => "-".into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yew::{
+        tests::{cache_with, counter, Key},
+        ManualClock,
+    };
+
+    #[test]
+    fn debug_entries_of_cache() {
+        let clock = ManualClock::new();
+        let cache = cache_with(&[1, 2])
+            .with_clock(clock.clone())
+            .with_spawn_fn(|_future| {});
+        cache.cache(&Key(1), Rc::new(5));
+        clock.advance(Duration::from_millis(2500));
+        let (subscriber, _) = counter();
+        cache.subscribe_with(&Key(3), &subscriber, None, true);
+
+        let entries = cache.debug_entries();
+        assert_eq!(entries.len(), 3);
+        assert!(entries[0].key.any_eq(&Key(1)));
+        assert_eq!(entries[0].value_type, Some("u64"));
+        assert_eq!(entries[0].state(), "valid");
+        assert_eq!(format_duration(entries[0].since_updated), "2.5s");
+        assert_eq!(entries[1].since_updated, None);
+        assert_eq!(entries[2].state(), "empty");
+        assert!(entries[2].progress);
+        assert_eq!(entries[2].subscribers, 1);
+    }
+
+    #[test]
+    fn erased_invalidate_and_remove() {
+        let cache = cache_with(&[1, 2]);
+        let key: Box<dyn CacheKey> = Box::new(Key(1));
+        assert!(cache.invalidate_erased(key.as_ref()));
+        assert_eq!(cache.debug_entries()[0].state(), "stale");
+        assert!(cache.remove_erased(key.as_ref()));
+        assert!(!cache.remove_erased(key.as_ref()));
+        assert!(!cache.invalidate_erased(key.as_ref()));
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -0,0 +1,105 @@
+//! Read-only iteration over the entries of the cache.
+//!
+//! Every method takes a snapshot of the entries while holding the lock, so the cache can be used
+//! freely while looking at the results. Changes made in the meantime are not reflected. None of
+//! these count as a use of the entries for least-recently-used eviction.
+
+use super::{Cache, CacheItem, CacheKey, EntryMetadata, RcValue};
+
+impl<M: 'static> Cache<M> {
+    /// Keys of all entries, in order.
+    pub fn keys(&self) -> Vec<Box<dyn CacheKey<M>>> {
+        let cache = self.cache.lock().expect("Failure to lock cache");
+        cache.entries.keys().cloned().collect()
+    }
+
+    /// Call the visitor with the key and metadata of every entry, in order.
+    ///
+    /// The visitor is called with a snapshot after the lock is released, so it may use the cache.
+    pub fn for_each_entry<F: FnMut(&dyn CacheKey<M>, &EntryMetadata)>(&self, mut visitor: F) {
+        let entries: Vec<_> = {
+            let cache = self.cache.lock().expect("Failure to lock cache");
+            cache
+                .entries
+                .iter()
+                .map(|(key, entry)| (key.clone(), EntryMetadata::from(entry)))
+                .collect()
+        };
+        for (key, metadata) in &entries {
+            visitor(&**key, metadata);
+        }
+    }
+
+    /// Keys and values of all entries of this type, in order.
+    ///
+    /// Entries whose value has the wrong type are skipped.
+    pub fn entries_of<T: CacheItem<M>>(&self) -> Vec<(T, RcValue<T::Value>)> {
+        let cache = self.cache.lock().expect("Failure to lock cache");
+        cache
+            .entries
+            .iter()
+            .filter_map(|(key, entry)| {
+                let key = key.any().downcast_ref::<T>()?;
+                Some((key.clone(), entry.value.clone().downcast()?))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        yew::tests::{cache_with, counter, Key},
+        Invalidatable,
+    };
+    use std::rc::Rc;
+
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Other;
+
+    impl Invalidatable<()> for Other {}
+
+    #[async_trait::async_trait(?Send)]
+    impl CacheItem for Other {
+        type Value = String;
+        type Error = crate::yew::tests::Failure;
+
+        async fn send(&self) -> Result<Self::Value, Self::Error> {
+            Ok(String::new())
+        }
+    }
+
+    #[test]
+    fn iterate_entries() {
+        let cache = cache_with(&[2, 1]).with_spawn_fn(|_future| {});
+        cache.seed(&Other, "other".into());
+        cache.invalidate_key(&Key(2));
+        let (subscriber, _) = counter();
+        cache.subscribe_with(&Key(1), &subscriber, None, false);
+
+        let keys: Vec<String> = cache.keys().iter().map(|key| format!("{key:?}")).collect();
+        assert_eq!(keys.len(), 3);
+        assert!(keys.contains(&"Other".to_string()));
+
+        let mut visited = vec![];
+        cache.for_each_entry(|key, metadata| {
+            // the cache is not locked while visiting
+            assert_eq!(cache.len(), 3);
+            visited.push((format!("{key:?}"), metadata.valid, metadata.subscribers));
+        });
+        assert!(visited.contains(&("Key(1)".into(), true, 1)));
+        assert!(visited.contains(&("Key(2)".into(), false, 0)));
+
+        let entries = cache.entries_of::<Key>();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].0, Key(1));
+        assert_eq!(entries[0].1.data(), Some(&Rc::new(1)));
+        assert!(!entries[1].1.valid());
+
+        let other = cache.entries_of::<Other>();
+        assert_eq!(other[0].1.data().map(|data| data.as_str()), Some("other"));
+        let metadata = cache.peek_entry(&Other).unwrap();
+        assert_eq!(metadata.value_type, Some(std::any::type_name::<String>()));
+    }
+}
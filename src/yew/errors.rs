@@ -0,0 +1,187 @@
+//! Errors of failed fetches, for rendering and reporting them.
+
+use super::{use_cache, use_cached_in, Cache, CacheItem};
+use crate::RcValue;
+use std::rc::Rc;
+use yew::prelude::*;
+
+impl<M: 'static> Cache<M> {
+    /// Error of the last failed fetch of this data.
+    ///
+    /// The error is replaced when another fetch fails, and cleared once a fetch succeeds or a
+    /// value is written. Unlike [`Cache::last_error`], it keeps its concrete type.
+    pub fn error_of<T: CacheItem<M>>(&self, data: &T) -> Option<Rc<T::Error>> {
+        let cache = self.cache.lock().expect("Failure to lock cache");
+        let error = cache.get(data)?.error.clone()?;
+        error.downcast().ok()
+    }
+
+    /// Collect the errors of all entries whose last fetch failed, and clear them.
+    ///
+    /// Returns the debug representation of the key and of the error of every entry, in key order,
+    /// and resets [`Cache::last_error`] and the error count of these entries, so that every error
+    /// is only returned once. This is useful for reporting errors to an error tracking service
+    /// periodically. The typed errors of [`Cache::error_of`] are kept, so that components can
+    /// still render them.
+    pub fn drain_errors(&self) -> Vec<(String, String)> {
+        let mut cache = self.cache.lock().expect("Failure to lock cache");
+        cache
+            .iter_mut()
+            .filter_map(|(key, entry)| {
+                let error = entry.last_error.take()?;
+                entry.last_error_count = 0;
+                Some((format!("{key:?}"), error))
+            })
+            .collect()
+    }
+}
+
+/// Use cached data, along with the error of the last failed fetch.
+///
+/// Behaves like [`use_cached`](super::use_cached), but additionally returns the error of the last
+/// failed fetch, see [`Cache::error_of`]. Subscribers are notified when a fetch fails, so the
+/// component renders the error as soon as it occurs. Any stale data is still returned alongside
+/// the error.
+///
+/// ```rust
+/// # use wasm_cache::{yew::*, CacheItem, Invalidatable};
+/// # use yew::prelude::*;
+/// # #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// # struct Document(u64);
+/// # impl Invalidatable<()> for Document {}
+/// # #[async_trait::async_trait(?Send)]
+/// # impl CacheItem for Document {
+/// #     type Value = String;
+/// #     type Error = std::fmt::Error;
+/// #     async fn send(&self) -> Result<Self::Value, Self::Error> {
+/// #         Err(std::fmt::Error)
+/// #     }
+/// # }
+/// #[function_component]
+/// fn DocumentView() -> Html {
+///     let (document, error) = use_cached_with_error::<(), _>(Document(1));
+///     match (document.data(), error) {
+///         (Some(document), _) => html! { <p>{ document.to_string() }</p> },
+///         (None, Some(error)) => html! { <p>{ format!("failed to load: {error}") }</p> },
+///         (None, None) => html! { <p>{ "loading" }</p> },
+///     }
+/// }
+/// ```
+#[hook]
+pub fn use_cached_with_error<M: 'static, R: CacheItem<M>>(
+    data: R,
+) -> (RcValue<R::Value>, Option<Rc<R::Error>>) {
+    log::debug!("use_cached_with_error({data:?})");
+    let cache = use_cache::<M>();
+    let error = cache.error_of(&data);
+    (use_cached_in(cache, data), error)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        yew::{
+            tests::{cache_with, counter, Failure, Key},
+            Cache,
+        },
+        CacheItem, Invalidatable,
+    };
+    use std::{
+        cell::Cell,
+        fmt::{Display, Formatter, Result},
+        rc::Rc,
+    };
+
+    thread_local! {
+        static ALLOWED: Cell<bool> = const { Cell::new(false) };
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    enum DocumentError {
+        PermissionDenied(u64),
+    }
+
+    impl Display for DocumentError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+            match self {
+                Self::PermissionDenied(id) => write!(f, "permission denied for {id}"),
+            }
+        }
+    }
+
+    impl std::error::Error for DocumentError {}
+
+    /// Document which can only be loaded while it is allowed.
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Document(u64);
+
+    impl Invalidatable<()> for Document {}
+
+    #[async_trait::async_trait(?Send)]
+    impl CacheItem for Document {
+        type Value = String;
+        type Error = DocumentError;
+
+        async fn send(&self) -> std::result::Result<Self::Value, Self::Error> {
+            match ALLOWED.with(Cell::get) {
+                true => Ok(format!("document {}", self.0)),
+                false => Err(DocumentError::PermissionDenied(self.0)),
+            }
+        }
+    }
+
+    #[test]
+    fn typed_error_until_fetch_succeeds() {
+        let cache = Cache::<()>::default().with_spawn_fn(|future| {
+            futures::executor::block_on(future);
+        });
+        let (subscriber, count) = counter();
+        cache.subscribe_with(&Document(1), &subscriber, None, true);
+        assert_eq!(count.get(), 1);
+        assert_eq!(
+            cache.error_of(&Document(1)),
+            Some(Rc::new(DocumentError::PermissionDenied(1)))
+        );
+        assert_eq!(
+            cache.last_error(&Document(1)).as_deref(),
+            Some("PermissionDenied(1)")
+        );
+        assert_eq!(cache.error_of(&Document(2)), None);
+
+        // a newer failure replaces the error
+        cache.failure(&Document(1), DocumentError::PermissionDenied(2));
+        assert_eq!(
+            cache.error_of(&Document(1)),
+            Some(Rc::new(DocumentError::PermissionDenied(2)))
+        );
+
+        ALLOWED.with(|allowed| allowed.set(true));
+        cache.fetch(&Document(1), None);
+        assert_eq!(cache.error_of(&Document(1)), None);
+        assert_eq!(
+            cache.peek(&Document(1)).unwrap().data(),
+            Some(&Rc::new("document 1".to_string()))
+        );
+    }
+
+    #[test]
+    fn drain_errors_once() {
+        let cache = cache_with(&[1, 2, 3]);
+        cache.failure(&Key(3), Failure);
+        cache.failure(&Key(1), Failure);
+        cache.failure(&Key(1), Failure);
+        assert_eq!(
+            cache.drain_errors(),
+            [
+                ("Key(1)".to_string(), "Failure".to_string()),
+                ("Key(3)".to_string(), "Failure".to_string()),
+            ]
+        );
+        assert_eq!(cache.last_error(&Key(1)), None);
+        assert!(cache.error_of(&Key(1)).is_some());
+        assert!(cache.drain_errors().is_empty());
+
+        cache.failure(&Key(2), Failure);
+        assert_eq!(cache.drain_errors().len(), 1);
+    }
+}
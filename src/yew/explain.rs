@@ -0,0 +1,165 @@
+//! Human-readable explanations of cache entries, for debugging.
+
+use super::{Cache, CacheItem};
+use std::fmt::{Debug, Display, Formatter, Result};
+
+/// Explanation of the state of a cache entry, see [`Cache::explain`].
+///
+/// [`Display`] renders it on a single line, [`Debug`] renders it on multiple lines.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct EntryExplanation {
+    /// Whether an entry exists for the key.
+    pub exists: bool,
+    /// Whether the value is valid.
+    pub valid: bool,
+    /// Whether the entry holds data, even if it is not valid.
+    pub has_data: bool,
+    /// Whether a fetch is in progress.
+    pub in_progress: bool,
+    /// Number of subscribers.
+    pub subscriber_count: usize,
+    /// Number of consecutive failed fetches.
+    pub retry_count: u32,
+    /// Delay before the next fetch, in milliseconds.
+    pub delay_ms: Option<u64>,
+    /// Time since the value was last updated.
+    pub last_updated: Option<String>,
+    /// Error of the last failed fetch.
+    pub last_error: Option<String>,
+}
+
+impl Display for EntryExplanation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        if !self.exists {
+            return write!(f, "no entry");
+        }
+        let state = match (self.valid, self.has_data) {
+            (true, _) => "valid",
+            (false, true) => "stale",
+            (false, false) => "empty",
+        };
+        write!(f, "{state}, {} subscribers", self.subscriber_count)?;
+        if self.in_progress {
+            write!(f, ", fetching")?;
+        }
+        if let Some(last_updated) = &self.last_updated {
+            write!(f, ", updated {last_updated}")?;
+        }
+        if self.retry_count > 0 {
+            write!(f, ", {} failures", self.retry_count)?;
+        }
+        if let Some(delay) = self.delay_ms {
+            write!(f, ", retry in {delay}ms")?;
+        }
+        if let Some(error) = &self.last_error {
+            write!(f, ", last error: {error}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Fields of an [`EntryExplanation`], for its [`Debug`] implementation.
+struct Fields<'a>(&'a EntryExplanation);
+
+impl Debug for Fields<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let Fields(explanation) = self;
+        f.debug_struct("EntryExplanation")
+            .field("exists", &explanation.exists)
+            .field("valid", &explanation.valid)
+            .field("has_data", &explanation.has_data)
+            .field("in_progress", &explanation.in_progress)
+            .field("subscriber_count", &explanation.subscriber_count)
+            .field("retry_count", &explanation.retry_count)
+            .field("delay_ms", &explanation.delay_ms)
+            .field("last_updated", &explanation.last_updated)
+            .field("last_error", &explanation.last_error)
+            .finish()
+    }
+}
+
+impl Debug for EntryExplanation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{:#?}", Fields(self))
+    }
+}
+
+impl<M: 'static> Cache<M> {
+    /// Explain the state of the entry of this key.
+    pub fn explain<T: CacheItem<M>>(&self, key: &T) -> EntryExplanation {
+        let cache = self.cache.lock().expect("Failure to lock cache");
+        let now = cache.clock.now();
+        let Some(entry) = cache.get(key) else {
+            return EntryExplanation::default();
+        };
+        EntryExplanation {
+            exists: true,
+            valid: entry.value.valid(),
+            has_data: entry.value.data().is_some(),
+            in_progress: entry.progress,
+            subscriber_count: entry.subscriptions.len(),
+            retry_count: entry.failures,
+            delay_ms: entry.delay.map(|delay| delay.as_millis() as u64),
+            last_updated: entry
+                .since_updated(now)
+                .map(|since| format!("{}ms ago", since.as_millis())),
+            last_error: entry.last_error.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yew::{
+        tests::{cache_with, counter, Failure, Key},
+        ManualClock,
+    };
+    use std::{rc::Rc, time::Duration};
+
+    #[test]
+    fn explain_missing_entry() {
+        let cache = cache_with(&[]);
+        let explanation = cache.explain(&Key(1));
+        assert!(!explanation.exists);
+        assert_eq!(explanation.to_string(), "no entry");
+    }
+
+    #[test]
+    fn explain_entry() {
+        let clock = ManualClock::new();
+        let cache = cache_with(&[1]).with_clock(clock.clone());
+        cache.cache.lock().unwrap().options.backoff.jitter = 0.0;
+        let (subscriber, _) = counter();
+        cache.subscribe_with(&Key(1), &subscriber, None, false);
+        cache.cache(&Key(1), Rc::new(2));
+        clock.advance(Duration::from_millis(1500));
+        assert_eq!(
+            cache.explain(&Key(1)).to_string(),
+            "valid, 1 subscribers, updated 1500ms ago"
+        );
+
+        cache.invalidate_key(&Key(1));
+        cache.failure(&Key(1), Failure);
+        let explanation = cache.explain(&Key(1));
+        assert_eq!(
+            explanation,
+            EntryExplanation {
+                exists: true,
+                valid: false,
+                has_data: true,
+                in_progress: false,
+                subscriber_count: 1,
+                retry_count: 1,
+                delay_ms: Some(100),
+                last_updated: Some("1500ms ago".into()),
+                last_error: Some("Failure".into()),
+            }
+        );
+        assert_eq!(
+            explanation.to_string(),
+            "stale, 1 subscribers, updated 1500ms ago, 1 failures, retry in 100ms, last error: Failure"
+        );
+        assert!(format!("{explanation:?}").contains("\n    retry_count: 1,\n"));
+    }
+}
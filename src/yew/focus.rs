@@ -0,0 +1,227 @@
+//! Refetching of stale data when the window regains focus.
+//!
+//! Once enabled with [`Cache::enable_focus_refetch`], the cache listens for the `focus` and
+//! `visibilitychange` events of the window. When the page becomes visible again, every entry which
+//! has subscribers and is invalid or stale is invalidated and broadcast, so that the subscribed
+//! components refetch it. Items can opt out with [`CacheItem::refetch_on_focus`].
+//!
+//! [`CacheItem::refetch_on_focus`]: crate::CacheItem::refetch_on_focus
+
+use super::{BTreeCache, Cache};
+use std::{
+    rc::{Rc, Weak},
+    sync::Mutex,
+};
+
+/// Registered focus listeners of a cache, see [`Cache::enable_focus_refetch`].
+///
+/// The listeners are removed when this is dropped.
+#[derive(Debug, Default)]
+pub struct FocusListeners {
+    #[cfg(target_arch = "wasm32")]
+    listeners: Vec<gloo_events::EventListener>,
+}
+
+impl FocusListeners {
+    /// Register the focus and visibility listeners for this cache.
+    ///
+    /// The listeners only hold a weak reference, so they do not keep the cache alive.
+    #[cfg(target_arch = "wasm32")]
+    fn register<M: 'static>(cache: Weak<Mutex<BTreeCache<M>>>) -> Self {
+        use gloo_events::EventListener;
+
+        let Some(window) = web_sys::window() else {
+            return Self::default();
+        };
+        let Some(document) = window.document() else {
+            return Self::default();
+        };
+        let refetch = Rc::new(move || {
+            if let (true, Some(cache)) = (page_visible(), cache.upgrade()) {
+                Cache { cache }.refetch_stale();
+            }
+        });
+        let on_focus = refetch.clone();
+        Self {
+            listeners: vec![
+                EventListener::new(&window, "focus", move |_| on_focus()),
+                EventListener::new(&document, "visibilitychange", move |_| refetch()),
+            ],
+        }
+    }
+
+    /// There is no window on native targets, so no listeners are registered.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn register<M: 'static>(_cache: Weak<Mutex<BTreeCache<M>>>) -> Self {
+        Self::default()
+    }
+}
+
+/// Determine if the page is currently visible. Always true on native targets.
+pub(super) fn page_visible() -> bool {
+    #[cfg(target_arch = "wasm32")]
+    {
+        web_sys::window()
+            .and_then(|window| window.document())
+            .map(|document| !document.hidden())
+            .unwrap_or(true)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        true
+    }
+}
+
+impl<M: 'static> Cache<M> {
+    /// Refetch stale data whenever the window regains focus.
+    ///
+    /// The listeners are removed by [`Cache::disable_focus_refetch`], or once the cache is dropped.
+    /// Enabling this again has no effect. On native targets there is no window, so this does
+    /// nothing, use [`Cache::refetch_stale`] instead.
+    pub fn enable_focus_refetch(&self) {
+        let weak = Rc::downgrade(&self.cache);
+        let mut cache = self.cache.lock().expect("Failure to lock cache");
+        if cache.focus.is_none() {
+            cache.focus = Some(FocusListeners::register(weak));
+        }
+    }
+
+    /// Stop refetching stale data when the window regains focus.
+    pub fn disable_focus_refetch(&self) {
+        self.cache.lock().expect("Failure to lock cache").focus = None;
+    }
+
+    /// Determine if stale data is refetched when the window regains focus.
+    pub fn focus_refetch_enabled(&self) -> bool {
+        self.cache
+            .lock()
+            .expect("Failure to lock cache")
+            .focus
+            .is_some()
+    }
+
+    /// Invalidate and broadcast all subscribed entries which need to be refetched.
+    ///
+    /// This covers entries which are invalid, have outlived their TTL or are older than the stale
+    /// time of a subscriber, unless they are being fetched, have failed terminally or their item
+    /// opted out with [`CacheItem::refetch_on_focus`](crate::CacheItem::refetch_on_focus). The
+    /// subscribed components then refetch them. Returns the number of entries.
+    pub fn refetch_stale(&self) -> usize {
+        let mut cache = self.cache.lock().expect("Failure to lock cache");
+        let now = cache.clock.now();
+        let mut count = 0;
+        for entry in cache.entries.values_mut() {
+            if entry.subscriptions.is_empty()
+                || entry.no_focus_refetch
+                || entry.progress
+                || entry.terminal
+            {
+                continue;
+            }
+            let stale = entry.is_stale(now) || entry.is_stale_for_subscribers(now);
+            if entry.value.valid() && !stale {
+                continue;
+            }
+            entry.value.invalidate();
+            entry.broadcast();
+            count += 1;
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        yew::{
+            tests::{cache_with, counter, Failure, Key},
+            ManualClock, QueryOptions,
+        },
+        CacheItem, Invalidatable,
+    };
+    use std::time::Duration;
+
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Expensive;
+
+    impl Invalidatable<()> for Expensive {}
+
+    #[async_trait::async_trait(?Send)]
+    impl CacheItem for Expensive {
+        type Value = u64;
+        type Error = Failure;
+
+        async fn send(&self) -> Result<Self::Value, Self::Error> {
+            Ok(0)
+        }
+
+        fn refetch_on_focus(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn refetch_stale_entries() {
+        let clock = ManualClock::new();
+        let cache = cache_with(&[1, 2, 3, 4]).with_clock(clock.clone());
+        let (subscriber, count) = counter();
+        for key in [1, 2, 3] {
+            cache.subscribe_with(&Key(key), &subscriber, None, false);
+        }
+        let options = QueryOptions {
+            enabled: false,
+            stale_time: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        cache.subscribe_with_options(&Key(2), &subscriber, None, &options, false);
+        for key in [2, 3] {
+            let mut inner = cache.cache.lock().unwrap();
+            inner
+                .mutate(&Key(key), |entry| entry.last_updated = Some(0.0))
+                .unwrap();
+        }
+        cache.invalidate_key(&Key(1));
+        count.set(0);
+
+        // only the invalid entry, fresh entries are left alone
+        assert_eq!(cache.refetch_stale(), 1);
+        assert_eq!(count.get(), 1);
+
+        // older than the stale time of a subscriber, entries without subscribers are ignored
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(cache.refetch_stale(), 2);
+        let inner = cache.cache.lock().unwrap();
+        assert!(!inner.get(&Key(2)).unwrap().value.valid());
+        assert!(inner.get(&Key(3)).unwrap().value.valid());
+        assert!(inner.get(&Key(4)).unwrap().value.valid());
+    }
+
+    #[test]
+    fn refetch_stale_skips_opted_out_and_in_progress() {
+        let cache = cache_with(&[1]);
+        let (subscriber, count) = counter();
+        cache.subscribe_with(&Expensive, &subscriber, None, false);
+        cache.subscribe_with(&Key(1), &subscriber, None, false);
+        let mut inner = cache.cache.lock().unwrap();
+        inner
+            .mutate(&Key(1), |entry| entry.fetch_started())
+            .unwrap();
+        drop(inner);
+        cache.invalidate_all();
+        count.set(0);
+
+        assert_eq!(cache.refetch_stale(), 0);
+        assert_eq!(count.get(), 0);
+    }
+
+    #[test]
+    fn focus_refetch_toggles() {
+        let cache = cache_with(&[]);
+        assert!(!cache.focus_refetch_enabled());
+        cache.enable_focus_refetch();
+        cache.enable_focus_refetch();
+        assert!(cache.focus_refetch_enabled());
+        cache.disable_focus_refetch();
+        assert!(!cache.focus_refetch_enabled());
+    }
+}
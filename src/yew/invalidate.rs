@@ -0,0 +1,225 @@
+//! Handles for invalidating cache entries from components.
+use super::{use_cache, Cache};
+use crate::CacheItem;
+use yew::prelude::*;
+
+/// Handle to invalidate entries of the cache, returned by [`use_invalidate`].
+///
+/// Each method returns a callback which can be passed directly to event handlers, such as
+/// `onclick`. Handles compare equal if they refer to the same cache, so they do not break the
+/// memoization of components they are passed to.
+pub struct InvalidateHandle<M: 'static = ()> {
+    cache: Cache<M>,
+}
+
+impl<M: 'static> Clone for InvalidateHandle<M> {
+    fn clone(&self) -> Self {
+        Self {
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<M: 'static> PartialEq for InvalidateHandle<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cache == other.cache
+    }
+}
+
+impl<M: 'static> InvalidateHandle<M> {
+    /// Handle which invalidates entries of this cache.
+    pub fn new(cache: Cache<M>) -> Self {
+        Self { cache }
+    }
+
+    /// Callback which invalidates all entries affected by this mutation.
+    pub fn mutation<E>(&self, mutation: M) -> Callback<E> {
+        let cache = self.cache.clone();
+        Callback::from(move |_| cache.invalidate(&mutation))
+    }
+
+    /// Callback which invalidates the entry of this key.
+    pub fn key<T: CacheItem<M>, E>(&self, key: T) -> Callback<E> {
+        let cache = self.cache.clone();
+        Callback::from(move |_| cache.invalidate_key(&key))
+    }
+
+    /// Callback which invalidates all entries.
+    pub fn all<E>(&self) -> Callback<E> {
+        let cache = self.cache.clone();
+        Callback::from(move |_| cache.invalidate_all())
+    }
+
+    /// Callback which removes all entries, see [`Cache::clear`].
+    pub fn clear<E>(&self) -> Callback<E> {
+        let cache = self.cache.clone();
+        Callback::from(move |_| cache.clear())
+    }
+}
+
+/// Get a handle to invalidate entries of the cache of the surrounding [`CacheProvider`].
+///
+/// ```rust
+/// # use wasm_cache::yew::*;
+/// # use yew::prelude::*;
+/// #[function_component]
+/// fn Refresh() -> Html {
+///     let invalidate = use_invalidate::<()>();
+///     html! {
+///         <button onclick={invalidate.all()}>{"Refresh"}</button>
+///     }
+/// }
+/// ```
+///
+/// [`CacheProvider`]: super::CacheProvider
+#[hook]
+pub fn use_invalidate<M: 'static>() -> InvalidateHandle<M> {
+    InvalidateHandle::new(use_cache::<M>())
+}
+
+/// Get a callback which invalidates the whole cache of the surrounding [`CacheProvider`].
+///
+/// Subscribed components keep showing their values while they refetch, which is useful for
+/// refreshing everything after a network interruption. The callback stays the same across
+/// renders, as long as the cache does not change.
+///
+/// ```rust
+/// # use wasm_cache::yew::*;
+/// # use yew::prelude::*;
+/// #[function_component]
+/// fn Reconnect() -> Html {
+///     let invalidate_all = use_invalidate_all::<()>();
+///     let onclick = move |_| invalidate_all.emit(());
+///     html! {
+///         <button {onclick}>{"Reconnect"}</button>
+///     }
+/// }
+/// ```
+///
+/// [`CacheProvider`]: super::CacheProvider
+#[hook]
+pub fn use_invalidate_all<M: 'static>() -> Callback<()> {
+    let cache = use_cache::<M>();
+    use_callback(|_, cache| cache.invalidate_all(), cache)
+}
+
+/// Get a callback which removes all entries of the cache of the surrounding [`CacheProvider`].
+///
+/// Unlike [`use_invalidate_all`], the cached values are discarded, see [`Cache::clear`]. This is
+/// useful for logging out, or resetting to defaults. The callback stays the same across renders,
+/// as long as the cache does not change.
+///
+/// ```rust
+/// # use wasm_cache::yew::*;
+/// # use yew::prelude::*;
+/// #[function_component]
+/// fn Logout() -> Html {
+///     let clear = use_clear_cache::<()>();
+///     let onclick = move |_| clear.emit(());
+///     html! {
+///         <button {onclick}>{"Log out"}</button>
+///     }
+/// }
+/// ```
+///
+/// [`CacheProvider`]: super::CacheProvider
+#[hook]
+pub fn use_clear_cache<M: 'static>() -> Callback<()> {
+    let cache = use_cache::<M>();
+    use_callback(|_, cache| cache.clear(), cache)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        yew::{tests::Failure, CacheProviderProps, Entry},
+        Invalidatable, RcValue,
+    };
+    use std::{any::Any, rc::Rc};
+
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct User(u64);
+
+    impl Invalidatable<u64> for User {
+        fn invalidated_by(&self, mutation: &u64) -> bool {
+            self.0 == *mutation
+        }
+    }
+
+    #[async_trait::async_trait(?Send)]
+    impl CacheItem<u64> for User {
+        type Value = u64;
+        type Error = Failure;
+
+        async fn send(&self) -> Result<Self::Value, Self::Error> {
+            Ok(self.0)
+        }
+    }
+
+    fn cache_with(users: &[u64]) -> Cache<u64> {
+        let cache = Cache::default();
+        for user in users {
+            cache.cache.lock().unwrap().insert(
+                User(*user),
+                Entry {
+                    value: RcValue::new(Rc::new(*user) as Rc<dyn Any>),
+                    ..Default::default()
+                },
+            );
+        }
+        cache
+    }
+
+    #[test]
+    fn handles_of_same_cache_are_equal() {
+        let cache = cache_with(&[]);
+        let handle = InvalidateHandle::new(cache.clone());
+        assert!(handle == InvalidateHandle::new(cache));
+        assert!(handle != InvalidateHandle::new(cache_with(&[])));
+    }
+
+    #[test]
+    fn callbacks_invalidate_entries() {
+        // the provider passes a clone of the cache of its props as context on every render
+        let props = |cache: &Cache<u64>| CacheProviderProps {
+            children: Default::default(),
+            cache: cache.clone(),
+            eviction_interval: None,
+            refetch_on_focus: false,
+            scope: Default::default(),
+        };
+        let cache = cache_with(&[1, 2, 3]);
+        let first = props(&cache);
+        let handle = InvalidateHandle::new(first.cache.clone());
+        let mutation = handle.mutation::<()>(1);
+        let key = handle.key::<_, ()>(User(2));
+
+        // re-rendering with the same cache keeps the handle, and the callbacks of the first
+        // render target the cache seen by the second one
+        let second = props(&cache);
+        assert!(first == second);
+        let rendered = InvalidateHandle::new(second.cache.clone());
+        assert!(handle == rendered);
+        mutation.emit(());
+        key.emit(());
+        assert!(!second.cache.contains_valid(&User(1)));
+        assert!(!second.cache.contains_valid(&User(2)));
+        assert!(second.cache.contains_valid(&User(3)));
+
+        // callbacks of another cache do not touch it
+        let other = cache_with(&[3]);
+        let other_handle = InvalidateHandle::new(props(&other).cache);
+        assert!(handle != other_handle);
+        other_handle.all::<()>().emit(());
+        assert!(second.cache.contains_valid(&User(3)));
+        assert!(!other.contains_valid(&User(3)));
+
+        rendered.all::<()>().emit(());
+        assert!(!cache.contains_valid(&User(3)));
+        assert_eq!(cache.len(), 3);
+
+        handle.clear::<()>().emit(());
+        assert!(cache.is_empty());
+    }
+}
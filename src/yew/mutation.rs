@@ -0,0 +1,323 @@
+//! Mutations with optimistic updates.
+use super::{use_cache, Cache};
+use crate::{CacheItem, RcValue};
+use std::{future::Future, rc::Rc};
+use yew::prelude::*;
+
+/// Handle to a mutation, returned by [`use_mutation_with_optimistic`].
+pub struct MutationHandle<Args: 'static, E: 'static> {
+    run: Callback<Args>,
+    reset: Callback<()>,
+    loading: bool,
+    error: Option<Rc<E>>,
+}
+
+impl<Args: 'static, E: 'static> Clone for MutationHandle<Args, E> {
+    fn clone(&self) -> Self {
+        Self {
+            run: self.run.clone(),
+            reset: self.reset.clone(),
+            loading: self.loading,
+            error: self.error.clone(),
+        }
+    }
+}
+
+impl<Args: 'static, E: 'static> MutationHandle<Args, E> {
+    /// Run the mutation with these arguments.
+    pub fn run(&self, args: Args) {
+        self.run.emit(args);
+    }
+
+    /// Determine if the mutation is currently running.
+    pub fn is_loading(&self) -> bool {
+        self.loading
+    }
+
+    /// Error of the last run of the mutation, if it failed.
+    pub fn error(&self) -> Option<Rc<E>> {
+        self.error.clone()
+    }
+
+    /// Reset the error of the last run.
+    pub fn reset(&self) {
+        self.reset.emit(());
+    }
+}
+
+/// Setters of the state of a mutation, which [`use_mutation_with_optimistic`] keeps in the state
+/// of the component.
+struct MutationState<E> {
+    loading: Callback<bool>,
+    error: Callback<Option<Rc<E>>>,
+}
+
+impl<E> Clone for MutationState<E> {
+    fn clone(&self) -> Self {
+        Self {
+            loading: self.loading.clone(),
+            error: self.error.clone(),
+        }
+    }
+}
+
+impl<E> MutationState<E> {
+    /// Reset the error of the last run.
+    fn reset(&self) {
+        self.error.emit(None);
+    }
+}
+
+impl<M: 'static> Cache<M> {
+    /// Restore a previous value of this data and broadcast it, unless the optimistic value has
+    /// been replaced in the meantime.
+    ///
+    /// The optimistic value is replaced by any write to the entry, such as a fetch or
+    /// [`Cache::set_value`], which is more recent than the previous value and is therefore kept.
+    /// If the optimistic value has been invalidated, the restored value is invalidated as well.
+    fn restore_value<T: CacheItem<M>>(&self, key: &T, mut previous: RcValue, optimistic: &RcValue) {
+        let _ = self
+            .cache
+            .lock()
+            .expect("Failure to lock cache")
+            .mutate(key, |entry| {
+                let (Some(current), Some(optimistic)) = (entry.value.data(), optimistic.data())
+                else {
+                    return;
+                };
+                if !Rc::ptr_eq(current, optimistic) {
+                    return;
+                }
+                if !entry.value.valid() {
+                    previous.invalidate();
+                }
+                entry.value = previous;
+                entry.broadcast();
+            });
+    }
+
+    /// Run a mutation of this data, see [`use_mutation_with_optimistic`].
+    ///
+    /// The optimistic value is applied right away, and the result of the request is stored once it
+    /// completes. If it fails, the previous value is restored.
+    fn run_mutation<T, Args, E, Fut>(
+        &self,
+        key: T,
+        args: Args,
+        optimistic_fn: impl FnOnce(&T::Value, &Args) -> T::Value,
+        send_fn: impl FnOnce(Args) -> Fut,
+        state: MutationState<E>,
+    ) where
+        T: CacheItem<M>,
+        E: 'static,
+        Fut: Future<Output = Result<T::Value, E>> + 'static,
+    {
+        let previous = self.raw_value_of(&key);
+        self.apply(&key, |value| optimistic_fn(value, &args));
+        let optimistic = self.raw_value_of(&key);
+        state.loading.emit(true);
+        state.error.emit(None);
+
+        let request = send_fn(args);
+        let cache = self.clone();
+        self.spawn(async move {
+            match request.await {
+                Ok(value) => cache.cache(&key, Rc::new(value)),
+                Err(failure) => {
+                    if let (Some(previous), Some(optimistic)) = (previous, optimistic) {
+                        cache.restore_value(&key, previous, &optimistic);
+                    }
+                    state.error.emit(Some(Rc::new(failure)));
+                }
+            }
+            state.loading.emit(false);
+        });
+    }
+}
+
+/// Mutation which optimistically updates a cached value.
+///
+/// When run, the cached value of `cache_key` is immediately replaced with the result of
+/// `optimistic_fn`, and `send_fn` is called to perform the real request. If it succeeds, the value
+/// it returns is stored in the cache. If it fails, the previous value is restored and the error
+/// is reported by the returned [`MutationHandle`]. A value written to the cache while the request
+/// is running, for example by a fetch, is not overwritten by restoring the previous one.
+#[hook]
+pub fn use_mutation_with_optimistic<M, R, Args, E, MutFn, SendFn, Fut>(
+    cache_key: R,
+    optimistic_fn: MutFn,
+    send_fn: SendFn,
+) -> MutationHandle<Args, E>
+where
+    M: 'static,
+    R: CacheItem<M>,
+    Args: 'static,
+    E: 'static,
+    MutFn: Fn(&R::Value, &Args) -> R::Value + 'static,
+    SendFn: Fn(Args) -> Fut + 'static,
+    Fut: Future<Output = Result<R::Value, E>> + 'static,
+{
+    let cache = use_cache::<M>();
+    let loading = use_state(|| false);
+    let error = use_state(|| None::<Rc<E>>);
+    let state = MutationState {
+        loading: {
+            let loading = loading.clone();
+            Callback::from(move |value| loading.set(value))
+        },
+        error: {
+            let error = error.clone();
+            Callback::from(move |value| error.set(value))
+        },
+    };
+
+    let run = {
+        let state = state.clone();
+        Callback::from(move |args: Args| {
+            cache.run_mutation(
+                cache_key.clone(),
+                args,
+                &optimistic_fn,
+                &send_fn,
+                state.clone(),
+            );
+        })
+    };
+
+    let reset = Callback::from(move |_| state.reset());
+
+    MutationHandle {
+        run,
+        reset,
+        loading: *loading,
+        error: (*error).clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yew::tests::{cache_with, counter, run_local, Failure, Key};
+    use futures::channel::oneshot;
+    use std::cell::RefCell;
+
+    /// Let all woken tasks run.
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    /// Events of the state of a mutation.
+    type Events = Rc<RefCell<Vec<String>>>;
+
+    /// State recording its changes.
+    fn recording(events: &Events) -> MutationState<Failure> {
+        let (loading, error) = (events.clone(), events.clone());
+        MutationState {
+            loading: Callback::from(move |value| {
+                loading.borrow_mut().push(format!("loading {value}"))
+            }),
+            error: Callback::from(move |value: Option<Rc<Failure>>| {
+                error
+                    .borrow_mut()
+                    .push(format!("error {}", value.is_some()))
+            }),
+        }
+    }
+
+    /// Cache running spawned tasks on the `LocalSet`, with a value of 1 for the first key.
+    fn cache() -> Cache {
+        cache_with(&[1]).with_spawn_fn(|future| {
+            tokio::task::spawn_local(future);
+        })
+    }
+
+    /// Run a mutation adding the argument to the value, with a response sent through a channel.
+    fn run(cache: &Cache, events: &Events, add: u64) -> oneshot::Sender<Result<u64, Failure>> {
+        let (sender, receiver) = oneshot::channel();
+        cache.run_mutation(
+            Key(1),
+            add,
+            |value, add| value + add,
+            |_| async move { receiver.await.unwrap() },
+            recording(events),
+        );
+        sender
+    }
+
+    fn value(cache: &Cache) -> Option<u64> {
+        cache.value_of(&Key(1)).data().map(|data| **data)
+    }
+
+    #[test]
+    fn mutation_applies_optimistic_value_and_commits() {
+        run_local(async {
+            let cache = cache();
+            let events = Events::default();
+            let (subscriber, count) = counter();
+            cache.subscribe_with(&Key(1), &subscriber, None, false);
+
+            let sender = run(&cache, &events, 1);
+            assert_eq!(value(&cache), Some(2));
+            assert_eq!(count.get(), 1);
+            assert_eq!(*events.borrow(), ["loading true", "error false"]);
+
+            sender.send(Ok(5)).unwrap();
+            settle().await;
+            assert_eq!(value(&cache), Some(5));
+            assert!(cache.contains_valid(&Key(1)));
+            assert_eq!(
+                *events.borrow(),
+                ["loading true", "error false", "loading false"]
+            );
+        });
+    }
+
+    #[test]
+    fn mutation_failure_rolls_back() {
+        run_local(async {
+            let cache = cache();
+            let events = Events::default();
+            let sender = run(&cache, &events, 1);
+            sender.send(Err(Failure)).unwrap();
+            settle().await;
+            assert_eq!(value(&cache), Some(1));
+            assert_eq!(
+                *events.borrow(),
+                ["loading true", "error false", "error true", "loading false"]
+            );
+
+            recording(&events).reset();
+            assert_eq!(events.borrow().last().unwrap(), "error false");
+        });
+    }
+
+    #[test]
+    fn mutation_failure_keeps_concurrent_write() {
+        run_local(async {
+            let cache = cache();
+            let events = Events::default();
+            let sender = run(&cache, &events, 1);
+            cache.set_value(&Key(1), 7);
+            sender.send(Err(Failure)).unwrap();
+            settle().await;
+            assert_eq!(value(&cache), Some(7));
+            assert_eq!(events.borrow()[2], "error true");
+        });
+    }
+
+    #[test]
+    fn restore_keeps_invalidation() {
+        let cache = cache_with(&[1]);
+        let previous = cache.raw_value_of(&Key(1)).unwrap();
+        cache.apply(&Key(1), |value| value + 1);
+        let optimistic = cache.raw_value_of(&Key(1)).unwrap();
+        cache.invalidate_key(&Key(1));
+
+        cache.restore_value(&Key(1), previous, &optimistic);
+        assert_eq!(value(&cache), Some(1));
+        assert!(!cache.contains_valid(&Key(1)));
+        assert!(cache.raw_value_of(&Key(2)).is_none());
+    }
+}
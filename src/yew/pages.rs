@@ -0,0 +1,204 @@
+//! Paginated queries, where each page is fetched after the previous one.
+
+use super::{use_cache, use_cached_many, Cache, CacheItem};
+use std::rc::Rc;
+use yew::prelude::*;
+
+/// Cache item which is one page of a paginated query.
+///
+/// Each page is cached under its own key, the key of the next page is derived from the value of
+/// the previous page.
+pub trait PaginatedCacheItem<M = ()>: CacheItem<M> {
+    /// Key of the page after this one, or `None` if this is the last page.
+    fn next(&self, last_value: &Self::Value) -> Option<Self>;
+}
+
+/// Pages of a paginated query, see [`use_cached_pages`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct CachedPages<T> {
+    /// Values of the pages which have loaded so far, in order.
+    pub pages: Vec<Rc<T>>,
+    /// Request the next page. Does nothing while a page is loading or if there is none.
+    pub fetch_next: Callback<()>,
+    /// Whether there is another page which can be fetched.
+    pub has_next: bool,
+    /// Whether a page after the first one is loading.
+    pub is_fetching_next: bool,
+}
+
+/// Keys and values of the requested pages, following the pages from the first one.
+#[derive(Debug)]
+struct PageWalk<R, T> {
+    /// Keys of the requested pages.
+    keys: Vec<R>,
+    /// Values of the pages which have data.
+    pages: Vec<Rc<T>>,
+    /// Key of the page after the requested ones, if any.
+    next: Option<R>,
+}
+
+impl<R, T> PageWalk<R, T> {
+    /// Follow up to `requested` pages, stopping at the first page without data.
+    fn new<M: 'static>(cache: &Cache<M>, first: &R, requested: usize) -> Self
+    where
+        R: PaginatedCacheItem<M, Value = T>,
+    {
+        let mut walk = Self {
+            keys: vec![first.clone()],
+            pages: vec![],
+            next: None,
+        };
+        loop {
+            let last = walk.keys.last().expect("First page always requested");
+            let Some(value) = cache.value_of(last).data().cloned() else {
+                break;
+            };
+            let next = last.next(&value);
+            walk.pages.push(value);
+            match next {
+                Some(next) if walk.keys.len() < requested => walk.keys.push(next),
+                next => {
+                    walk.next = next;
+                    break;
+                }
+            }
+        }
+        walk
+    }
+
+    /// Determine if a page after the first one is requested, but has no data yet.
+    fn is_fetching_next(&self) -> bool {
+        self.keys.len() > 1 && self.pages.len() < self.keys.len()
+    }
+}
+
+/// Number of pages to request, resetting it if the first page changed or was invalidated.
+fn requested_pages<R: PartialEq + Clone>(
+    state: &mut (R, usize),
+    first: &R,
+    first_valid: bool,
+) -> usize {
+    if state.0 != *first || !first_valid {
+        *state = (first.clone(), 1);
+    }
+    state.1
+}
+
+/// Use a paginated query, starting at the `first` page.
+///
+/// Initially only the first page is fetched, every call of [`CachedPages::fetch_next`] requests
+/// one more page. Each page is cached under its own key, so pages can be invalidated individually.
+/// When the first page is invalidated or the `first` key changes, the sequence is reset to only
+/// the first page.
+#[hook]
+pub fn use_cached_pages<M: 'static, R: PaginatedCacheItem<M>>(first: R) -> CachedPages<R::Value> {
+    log::debug!("use_cached_pages({first:?})");
+    let cache = use_cache::<M>();
+    let update = use_force_update();
+    let requested = use_mut_ref(|| (first.clone(), 1));
+
+    let first_valid = cache.value_of(&first).valid();
+    let count = requested_pages(&mut requested.borrow_mut(), &first, first_valid);
+    let walk = PageWalk::new(&cache, &first, count);
+    use_cached_many::<M, R>(walk.keys.clone());
+
+    let has_next = walk.next.is_some();
+    let is_fetching_next = walk.is_fetching_next();
+    let fetch_next = Callback::from(move |()| {
+        if has_next && !is_fetching_next {
+            requested.borrow_mut().1 = count + 1;
+            update.force_update();
+        }
+    });
+    CachedPages {
+        pages: walk.pages,
+        fetch_next,
+        has_next,
+        is_fetching_next,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        yew::tests::{counter, Failure},
+        Invalidatable,
+    };
+
+    /// Page of a query with three pages, whose value is the page number.
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Page(u64);
+
+    impl Invalidatable<()> for Page {}
+
+    #[async_trait::async_trait(?Send)]
+    impl CacheItem for Page {
+        type Value = u64;
+        type Error = Failure;
+
+        async fn send(&self) -> Result<Self::Value, Self::Error> {
+            Ok(self.0)
+        }
+    }
+
+    impl PaginatedCacheItem for Page {
+        fn next(&self, last_value: &u64) -> Option<Self> {
+            (*last_value < 3).then(|| Page(last_value + 1))
+        }
+    }
+
+    fn pages(walk: &PageWalk<Page, u64>) -> Vec<u64> {
+        walk.pages.iter().map(|page| **page).collect()
+    }
+
+    #[test]
+    fn walk_pages() {
+        let cache = Cache::<()>::default().with_spawn_fn(|_future| {});
+        let (subscriber, _) = counter();
+
+        // nothing loaded yet
+        let walk = PageWalk::new(&cache, &Page(1), 1);
+        assert_eq!(walk.keys, vec![Page(1)]);
+        assert!(walk.pages.is_empty() && walk.next.is_none());
+        assert!(!walk.is_fetching_next());
+
+        cache.subscribe_with(&Page(1), &subscriber, None, false);
+        cache.cache(&Page(1), Rc::new(1));
+        let walk = PageWalk::new(&cache, &Page(1), 1);
+        assert_eq!(pages(&walk), vec![1]);
+        assert_eq!(walk.next, Some(Page(2)));
+
+        // the next page is requested, but still loading
+        let walk = PageWalk::new(&cache, &Page(1), 2);
+        assert_eq!(walk.keys, vec![Page(1), Page(2)]);
+        assert!(walk.is_fetching_next());
+        assert!(walk.next.is_none());
+
+        // end of pagination
+        for page in [2, 3] {
+            cache.subscribe_with(&Page(page), &subscriber, None, false);
+            cache.cache(&Page(page), Rc::new(page));
+        }
+        let walk = PageWalk::new(&cache, &Page(1), 5);
+        assert_eq!(pages(&walk), vec![1, 2, 3]);
+        assert!(walk.next.is_none());
+        assert!(!walk.is_fetching_next());
+    }
+
+    #[test]
+    fn requested_pages_reset() {
+        let mut state = (Page(1), 1);
+        assert_eq!(requested_pages(&mut state, &Page(1), true), 1);
+        state.1 = 3;
+        assert_eq!(requested_pages(&mut state, &Page(1), true), 3);
+
+        // invalidating the first page resets the sequence
+        assert_eq!(requested_pages(&mut state, &Page(1), false), 1);
+
+        // as does changing the first page
+        state.1 = 3;
+        assert_eq!(requested_pages(&mut state, &Page(10), true), 1);
+        assert_eq!(state.0, Page(10));
+    }
+}
@@ -0,0 +1,291 @@
+//! Polling of cached data by individual components.
+
+use super::{focus::page_visible, use_cache, use_cached_in, Cache, CacheItem};
+use crate::RcValue;
+use std::{
+    cell::Cell,
+    fmt::{Debug, Formatter, Result},
+    rc::Rc,
+    time::Duration,
+};
+use yew::prelude::*;
+
+/// Running polling task, see [`Cache::start_polling`].
+///
+/// The task is cancelled when this is dropped.
+pub struct PollingTask {
+    cancel: Rc<Cell<bool>>,
+    /// Removes the subscription waiting for the first value, see [`Cache::start_interval`].
+    waiting: Option<Box<dyn FnOnce()>>,
+}
+
+impl Debug for PollingTask {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_struct("PollingTask")
+            .field("cancel", &self.cancel)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for PollingTask {
+    fn drop(&mut self) {
+        self.cancel.set(true);
+        if let Some(waiting) = self.waiting.take() {
+            waiting();
+        }
+    }
+}
+
+impl<M: 'static> Cache<M> {
+    /// Start a background task which refetches this data every `interval`.
+    ///
+    /// A refetch is skipped while the data is being fetched, while its circuit is open, while the
+    /// page is hidden, or if the value has been updated within the interval. When several tasks
+    /// poll the same data, the shortest interval therefore wins. The task runs until the returned
+    /// [`PollingTask`] is dropped.
+    pub fn start_polling<T: CacheItem<M>>(&self, data: T, interval: Duration) -> PollingTask {
+        let cancel = Rc::new(Cell::new(false));
+        let cache = self.clone();
+        let cancelled = cancel.clone();
+        self.spawn(async move {
+            loop {
+                cache.sleep(interval).await;
+                if cancelled.get() {
+                    break;
+                }
+                if page_visible() {
+                    cache.poll(&data, interval);
+                }
+            }
+        });
+        PollingTask {
+            cancel,
+            waiting: None,
+        }
+    }
+
+    /// Start a background task which force fetches this data every `interval`.
+    ///
+    /// Unlike [`Cache::start_polling`], every tick fetches the data with [`Cache::force_fetch`],
+    /// regardless of when it was last updated. The timer only starts once the data has a valid
+    /// value, so that no ticks happen while it is first being loaded; until then the data is
+    /// subscribed to and fetched if needed. The task runs until the returned [`PollingTask`] is
+    /// dropped.
+    pub fn start_interval<T: CacheItem<M>>(&self, data: T, interval: Duration) -> PollingTask {
+        let cancel = Rc::new(Cell::new(false));
+        let cache = self.clone();
+        let cancelled = cancel.clone();
+        let key = data.clone();
+        let subscriber = self.subscribe_until_valid(data.clone(), move |_| {
+            if cancelled.get() {
+                return;
+            }
+            cache.clone().spawn(async move {
+                loop {
+                    cache.sleep(interval).await;
+                    if cancelled.get() {
+                        break;
+                    }
+                    cache.force_fetch(&key);
+                }
+            });
+        });
+        let cache = self.clone();
+        PollingTask {
+            cancel,
+            waiting: subscriber.map(|subscriber| {
+                Box::new(move || cache.unsubscribe(&data, &subscriber)) as Box<dyn FnOnce()>
+            }),
+        }
+    }
+
+    /// Refetch this data if it has not been updated within the interval.
+    ///
+    /// Returns whether a fetch was started.
+    fn poll<T: CacheItem<M>>(&self, data: &T, interval: Duration) -> bool {
+        let cache = self.cache.lock().expect("Failure to lock cache");
+        let now = cache.clock.now();
+        let Some(entry) = cache.get(data) else {
+            return false;
+        };
+        let due = entry
+            .since_updated(now)
+            .map(|since| since >= interval)
+            .unwrap_or(true);
+        if !due || entry.progress || entry.circuit_open(now) {
+            return false;
+        }
+        self.fetch_locked(cache, data, None);
+        true
+    }
+}
+
+/// Use cached data, refetching it every `interval` while the component is mounted.
+///
+/// Behaves like [`use_cached`](super::use_cached), see [`Cache::start_polling`] for when polls are
+/// skipped. Polling stops once the component is unmounted.
+#[hook]
+pub fn use_cached_polling<M: 'static, R: CacheItem<M>>(
+    data: R,
+    interval: Duration,
+) -> RcValue<R::Value> {
+    log::debug!("use_cached_polling({data:?}, {interval:?})");
+    let cache = use_cache::<M>();
+    use_effect_with_deps(
+        |(cache, data, interval)| {
+            let task = cache.start_polling(data.clone(), *interval);
+            move || drop(task)
+        },
+        (cache.clone(), data.clone(), interval),
+    );
+    use_cached_in(cache, data)
+}
+
+/// Use cached data, force fetching it every `interval` while the component is mounted.
+///
+/// Behaves like [`use_cached`](super::use_cached), see [`Cache::start_interval`] for when the
+/// timer starts. Unlike [`use_cached_polling`], the data is fetched on every tick even if it was
+/// updated recently. The timer is cancelled once the component is unmounted.
+#[hook]
+pub fn use_interval_cached<M: 'static, R: CacheItem<M>>(
+    data: R,
+    interval: Duration,
+) -> RcValue<R::Value> {
+    log::debug!("use_interval_cached({data:?}, {interval:?})");
+    let cache = use_cache::<M>();
+    use_effect_with_deps(
+        |(cache, data, interval)| {
+            let task = cache.start_interval(data.clone(), *interval);
+            move || drop(task)
+        },
+        (cache.clone(), data.clone(), interval),
+    );
+    use_cached_in(cache, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::yew::{
+        tests::{cache_with, run_local, subscribers, Key},
+        Cache, ManualClock,
+    };
+    use std::{cell::Cell, rc::Rc, time::Duration};
+
+    thread_local! {
+        static SPAWNED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Let all woken tasks run.
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    /// Advance the clock by ten seconds and let all woken tasks run.
+    async fn tick(clock: &ManualClock) {
+        clock.advance(Duration::from_secs(10));
+        settle().await;
+    }
+
+    #[test]
+    fn polling_shortest_interval_wins() {
+        run_local(async {
+            let clock = ManualClock::new();
+            let cache = cache_with(&[1])
+                .with_clock(clock.clone())
+                .with_spawn_fn(|future| {
+                    SPAWNED.with(|spawned| spawned.set(spawned.get() + 1));
+                    tokio::task::spawn_local(future);
+                });
+            cache.cache(&Key(1), Rc::new(1));
+            let fast = cache.start_polling(Key(1), Duration::from_secs(10));
+            let slow = cache.start_polling(Key(1), Duration::from_secs(30));
+            let fetches = || SPAWNED.with(Cell::get) - 2;
+            settle().await;
+
+            for _ in 0..3 {
+                tick(&clock).await;
+            }
+            assert_eq!(fetches(), 3);
+
+            // no fetches once the tasks are dropped
+            drop(fast);
+            drop(slow);
+            for _ in 0..3 {
+                tick(&clock).await;
+            }
+            assert_eq!(fetches(), 3);
+            assert_eq!(cache.in_flight(), 0);
+        });
+    }
+
+    #[test]
+    fn poll_skips_fresh_and_in_progress() {
+        let clock = ManualClock::new();
+        let cache = cache_with(&[1])
+            .with_clock(clock.clone())
+            .with_spawn_fn(|_future| {});
+        cache.cache(&Key(1), Rc::new(1));
+        assert!(!cache.poll(&Key(1), Duration::from_secs(10)));
+
+        clock.advance(Duration::from_secs(10));
+        assert!(cache.poll(&Key(1), Duration::from_secs(10)));
+        assert!(!cache.poll(&Key(1), Duration::from_secs(10)));
+        assert!(!cache.poll(&Key(2), Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn interval_starts_after_first_value() {
+        run_local(async {
+            let clock = ManualClock::new();
+            let cache = Cache::<()>::default()
+                .with_clock(clock.clone())
+                .with_spawn_fn(|future| {
+                    SPAWNED.with(|spawned| spawned.set(spawned.get() + 1));
+                    tokio::task::spawn_local(future);
+                });
+            let task = cache.start_interval(Key(1), Duration::from_secs(10));
+            assert_eq!(SPAWNED.with(Cell::get), 1);
+
+            // no ticks while the data is loading
+            clock.advance(Duration::from_secs(30));
+            settle().await;
+            assert_eq!(cache.peek(&Key(1)).unwrap().data(), Some(&Rc::new(1)));
+            assert_eq!(subscribers(&cache, 1), 0);
+            let fetches = || SPAWNED.with(Cell::get) - 2;
+            assert_eq!(fetches(), 0);
+
+            // every tick fetches, even though the value is fresh
+            for _ in 0..3 {
+                tick(&clock).await;
+            }
+            assert_eq!(fetches(), 3);
+
+            drop(task);
+            tick(&clock).await;
+            assert_eq!(fetches(), 3);
+        });
+    }
+
+    #[test]
+    fn interval_dropped_while_loading() {
+        let cache = Cache::<()>::default().with_spawn_fn(|_future| {});
+        let task = cache.start_interval(Key(1), Duration::from_secs(10));
+        assert_eq!(subscribers(&cache, 1), 1);
+        drop(task);
+        assert_eq!(subscribers(&cache, 1), 0);
+
+        // the timer is not started once the value arrives
+        cache.cache(&Key(1), Rc::new(1));
+    }
+
+    #[test]
+    fn force_fetch_valid_value() {
+        let cache = cache_with(&[1]).with_spawn_fn(|_future| {});
+        assert!(cache.force_fetch(&Key(1)));
+        assert!(cache.peek_entry(&Key(1)).unwrap().progress);
+        assert!(!cache.force_fetch(&Key(1)));
+        assert!(!cache.force_fetch(&Key(2)));
+    }
+}
@@ -0,0 +1,251 @@
+//! Values pushed into the cache by long-lived sources, such as WebSockets.
+//!
+//! A source is attached to the entry of a key with [`Cache::attach_source`]. It is connected while
+//! the entry has subscribers: the connect function is called with a [`PushSink`] when the first
+//! component subscribes, and the connection it returns is dropped once the last one unsubscribes.
+//! Every value pushed into the sink is stored like the result of a fetch and broadcast to the
+//! subscribers. The source is removed with the entry, or with [`Cache::detach_source`].
+
+use super::{BTreeCache, Cache, CacheItem, Entry};
+use std::{
+    any::Any,
+    fmt::{Debug, Formatter, Result},
+    rc::{Rc, Weak},
+    sync::Mutex,
+};
+
+/// Connection of a source, which disconnects it when dropped.
+type Connection = Rc<dyn Any>;
+
+/// Source attached to an entry, see [`Cache::attach_source`].
+#[derive(Clone)]
+pub struct EntrySource {
+    connect: Rc<dyn Fn() -> Connection>,
+    connection: Option<Connection>,
+}
+
+impl EntrySource {
+    /// Determine if the source is currently connected.
+    pub fn connected(&self) -> bool {
+        self.connection.is_some()
+    }
+}
+
+impl Debug for EntrySource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_struct("EntrySource")
+            .field("connected", &self.connected())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Entry {
+    /// Take the connection of the source if there are no subscribers left, to drop it.
+    pub(super) fn disconnect_if_unsubscribed(&mut self) -> Option<Connection> {
+        if !self.subscriptions.is_empty() {
+            return None;
+        }
+        self.source.as_mut()?.connection.take()
+    }
+}
+
+/// Handle for a source to push values of a key into the cache.
+///
+/// This only holds a weak reference, so it does not keep the cache alive.
+pub struct PushSink<T: CacheItem<M>, M: 'static = ()> {
+    cache: Weak<Mutex<BTreeCache<M>>>,
+    key: T,
+}
+
+impl<T: CacheItem<M>, M: 'static> Clone for PushSink<T, M> {
+    fn clone(&self) -> Self {
+        Self {
+            cache: self.cache.clone(),
+            key: self.key.clone(),
+        }
+    }
+}
+
+impl<T: CacheItem<M>, M: 'static> PushSink<T, M> {
+    /// Key whose values are pushed.
+    pub fn key(&self) -> &T {
+        &self.key
+    }
+
+    /// Store this value and broadcast it to the subscribers, see [`Cache::set_value`].
+    ///
+    /// Returns false, doing nothing, if the cache or the entry no longer exists.
+    pub fn push(&self, value: T::Value) -> bool {
+        let Some(cache) = self.cache.upgrade() else {
+            return false;
+        };
+        let cache = Cache { cache };
+        if !cache.contains(&self.key) {
+            return false;
+        }
+        cache.set_value(&self.key, value);
+        true
+    }
+}
+
+impl<M: 'static> Cache<M> {
+    /// Attach a source which pushes values of this data, replacing any previous one.
+    ///
+    /// The entry is created if it does not exist. The `connect` function is called with a sink
+    /// whenever the entry gains its first subscriber, and the connection it returns is dropped once
+    /// the last subscriber is gone, which should close it. If the entry already has subscribers,
+    /// the source is connected right away. Fetches of the data keep working as usual, so the
+    /// initial value can be fetched while updates are pushed.
+    pub fn attach_source<T, F, C>(&self, key: &T, connect: F)
+    where
+        T: CacheItem<M>,
+        F: Fn(PushSink<T, M>) -> C + 'static,
+        C: 'static,
+    {
+        let sink = PushSink {
+            cache: Rc::downgrade(&self.cache),
+            key: key.clone(),
+        };
+        let source = EntrySource {
+            connect: Rc::new(move || Rc::new(connect(sink.clone())) as Connection),
+            connection: None,
+        };
+        let previous = {
+            let mut cache = self.cache.lock().expect("Failure to lock cache");
+            if cache.get(key).is_none() {
+                cache.insert(key.clone(), Entry::default());
+            }
+            cache.mutate(key, |entry| entry.source.replace(source))
+        };
+        drop(previous);
+        self.connect_source(key);
+    }
+
+    /// Detach the source of this data, disconnecting it. Returns whether there was one.
+    pub fn detach_source<T: CacheItem<M>>(&self, key: &T) -> bool {
+        let source = self
+            .cache
+            .lock()
+            .expect("Failure to lock cache")
+            .mutate(key, |entry| entry.source.take())
+            .flatten();
+        source.is_some()
+    }
+
+    /// Connect the source of this data, if it has subscribers and is not connected yet.
+    ///
+    /// The connect function is called without holding the lock, so it may push values right away.
+    pub(super) fn connect_source<T: CacheItem<M>>(&self, key: &T) {
+        let connect = {
+            let cache = self.cache.lock().expect("Failure to lock cache");
+            let Some(entry) = cache.get(key) else {
+                return;
+            };
+            match &entry.source {
+                Some(source) if !source.connected() && !entry.subscriptions.is_empty() => {
+                    source.connect.clone()
+                }
+                _ => return,
+            }
+        };
+        let connection = connect();
+
+        // only keep the connection if nothing changed while connecting
+        let unused = {
+            let mut cache = self.cache.lock().expect("Failure to lock cache");
+            cache.mutate(key, |entry| {
+                let current = entry
+                    .source
+                    .as_mut()
+                    .filter(|source| Rc::ptr_eq(&source.connect, &connect) && !source.connected());
+                match current {
+                    Some(source) if !entry.subscriptions.is_empty() => {
+                        source.connection = Some(connection);
+                        None
+                    }
+                    _ => Some(connection),
+                }
+            })
+        };
+        drop(unused);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yew::tests::{cache_with, counter, Key};
+    use std::cell::{Cell, RefCell};
+
+    /// Connection which counts how often it was closed.
+    struct Counted(Rc<Cell<usize>>);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    type Sinks = Rc<RefCell<Vec<PushSink<Key>>>>;
+
+    /// Attach a source to the key, recording its sinks and counting closed connections.
+    fn attach(cache: &Cache, key: u64) -> (Sinks, Rc<Cell<usize>>) {
+        let sinks = Sinks::default();
+        let closed = Rc::new(Cell::new(0));
+        let (recorded, counted) = (sinks.clone(), closed.clone());
+        cache.attach_source(&Key(key), move |sink| {
+            recorded.borrow_mut().push(sink);
+            Counted(counted.clone())
+        });
+        (sinks, closed)
+    }
+
+    #[test]
+    fn source_connected_while_subscribed() {
+        let cache = cache_with(&[]).with_spawn_fn(|_future| {});
+        let (sinks, closed) = attach(&cache, 1);
+        assert!(sinks.borrow().is_empty());
+
+        let (first, count) = counter();
+        let (second, _) = counter();
+        cache.subscribe_with(&Key(1), &first, None, false);
+        cache.subscribe_with(&Key(1), &second, None, false);
+        assert_eq!(sinks.borrow().len(), 1);
+
+        // pushed values are broadcast
+        assert!(sinks.borrow()[0].push(5));
+        assert_eq!(count.get(), 1);
+        assert_eq!(cache.peek(&Key(1)).unwrap().data(), Some(&Rc::new(5)));
+
+        // disconnected once the last subscriber is gone, reconnected on the next one
+        cache.unsubscribe(&Key(1), &first);
+        assert_eq!(closed.get(), 0);
+        cache.unsubscribe(&Key(1), &second);
+        assert_eq!(closed.get(), 1);
+        cache.subscribe_with(&Key(1), &first, None, false);
+        assert_eq!(sinks.borrow().len(), 2);
+
+        assert!(cache.detach_source(&Key(1)));
+        assert_eq!(closed.get(), 2);
+        assert!(!cache.detach_source(&Key(1)));
+    }
+
+    #[test]
+    fn source_connected_when_attached_to_subscribed_entry() {
+        let cache = cache_with(&[1]);
+        let (subscriber, _) = counter();
+        cache.subscribe_with(&Key(1), &subscriber, None, false);
+        let (sinks, closed) = attach(&cache, 1);
+        assert_eq!(sinks.borrow().len(), 1);
+
+        // replacing the source closes the previous connection
+        let (replaced, _) = attach(&cache, 1);
+        assert_eq!(closed.get(), 1);
+        assert_eq!(replaced.borrow().len(), 1);
+
+        // pushes after the entry is removed are ignored
+        assert!(cache.remove(&Key(1)));
+        assert!(!replaced.borrow()[0].push(2));
+        assert!(!cache.contains(&Key(1)));
+    }
+}
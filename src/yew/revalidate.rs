@@ -0,0 +1,143 @@
+//! Stale-while-revalidate state of cached data, for rendering refresh indicators.
+
+use super::{use_cache, use_cached_in, Cache, CacheItem};
+use crate::RcValue;
+use yew::prelude::*;
+
+/// Whether cached data is stale and being revalidated, see [`Cache::revalidation`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Revalidation {
+    /// The entry holds data, but it has been invalidated or has outlived its TTL.
+    pub is_stale: bool,
+    /// The stale data is being fetched again, or will be as soon as it is subscribed to.
+    pub is_revalidating: bool,
+}
+
+impl<M: 'static> Cache<M> {
+    /// Stale-while-revalidate state of this data, both flags are unset if there is no entry or
+    /// no data for it.
+    ///
+    /// The data stays stale until a fetch succeeds or a value is written. Failed fetches keep
+    /// the stale data if [`CacheOptions::keep_stale_on_failure`](super::CacheOptions) is set,
+    /// which it is by default, and it is revalidated again after the backoff delay. Once fetching
+    /// has been given up, the data is still stale but no longer revalidating.
+    pub fn revalidation<T: CacheItem<M>>(&self, data: &T) -> Revalidation {
+        let cache = self.cache.lock().expect("Failure to lock cache");
+        let now = cache.clock.now();
+        let Some(entry) = cache.get(data) else {
+            return Revalidation::default();
+        };
+        let is_stale = entry.value.data().is_some()
+            && (!entry.value.valid() || entry.is_stale(now) || entry.is_stale_for_subscribers(now));
+        Revalidation {
+            is_stale,
+            is_revalidating: is_stale && (entry.progress || entry.needs_fetch(now)),
+        }
+    }
+}
+
+/// Use cached data, along with whether it is stale and being revalidated.
+///
+/// Behaves like [`use_cached`](super::use_cached): stale data is returned right away when the
+/// component mounts, it is fetched again in the background, and the component is rerendered once
+/// the fresh value arrives. The additional [`Revalidation`] allows showing a subtle refresh
+/// indicator alongside the stale data, rather than replacing it with a loading state.
+///
+/// ```rust
+/// # use wasm_cache::{yew::*, CacheItem, Invalidatable};
+/// # use yew::prelude::*;
+/// # #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// # struct Document(u64);
+/// # impl Invalidatable<()> for Document {}
+/// # #[async_trait::async_trait(?Send)]
+/// # impl CacheItem for Document {
+/// #     type Value = String;
+/// #     type Error = std::fmt::Error;
+/// #     async fn send(&self) -> Result<Self::Value, Self::Error> {
+/// #         Ok("document".into())
+/// #     }
+/// # }
+/// #[function_component]
+/// fn DocumentView() -> Html {
+///     let (document, revalidation) = use_cached_revalidation::<(), _>(Document(1));
+///     match document.data() {
+///         Some(document) => html! {
+///             <p class={classes!(revalidation.is_revalidating.then_some("refreshing"))}>
+///                 { document.to_string() }
+///             </p>
+///         },
+///         None => html! { <p>{ "loading" }</p> },
+///     }
+/// }
+/// ```
+#[hook]
+pub fn use_cached_revalidation<M: 'static, R: CacheItem<M>>(
+    data: R,
+) -> (RcValue<R::Value>, Revalidation) {
+    log::debug!("use_cached_revalidation({data:?})");
+    let cache = use_cache::<M>();
+    let revalidation = cache.revalidation(&data);
+    (use_cached_in(cache, data), revalidation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yew::{
+        tests::{cache_with, counter, Failure, Key},
+        CacheOptions,
+    };
+    use std::rc::Rc;
+
+    #[test]
+    fn stale_value_served_while_revalidating() {
+        let cache = cache_with(&[1]).with_spawn_fn(|_future| {});
+        assert_eq!(cache.revalidation(&Key(1)), Revalidation::default());
+        assert_eq!(cache.revalidation(&Key(2)), Revalidation::default());
+
+        cache.invalidate_key(&Key(1));
+        let stale = Revalidation {
+            is_stale: true,
+            is_revalidating: true,
+        };
+        assert_eq!(cache.revalidation(&Key(1)), stale);
+
+        // subscribing starts the refetch, and returns the stale value meanwhile
+        let (subscriber, count) = counter();
+        cache.subscribe_with(&Key(1), &subscriber, None, true);
+        assert!(cache.peek_entry(&Key(1)).unwrap().progress);
+        assert_eq!(cache.revalidation(&Key(1)), stale);
+        assert_eq!(cache.peek(&Key(1)).unwrap().data(), Some(&Rc::new(1)));
+
+        // the fresh value is broadcast once it lands
+        cache.cache(&Key(1), Rc::new(2));
+        assert_eq!(count.get(), 1);
+        assert_eq!(cache.revalidation(&Key(1)), Revalidation::default());
+    }
+
+    #[test]
+    fn failed_revalidation_keeps_stale_value() {
+        let cache = Cache::with_options(CacheOptions {
+            max_retries: Some(1),
+            ..Default::default()
+        })
+        .with_spawn_fn(|_future| {});
+        cache.set(Key(1), 1);
+        cache.invalidate_key(&Key(1));
+
+        cache.failure(&Key(1), Failure);
+        assert_eq!(cache.peek(&Key(1)).unwrap().data(), Some(&Rc::new(1)));
+        assert!(cache.revalidation(&Key(1)).is_revalidating);
+
+        // once fetching is given up, the data stays stale
+        cache.failure(&Key(1), Failure);
+        assert_eq!(cache.peek(&Key(1)).unwrap().data(), Some(&Rc::new(1)));
+        assert_eq!(
+            cache.revalidation(&Key(1)),
+            Revalidation {
+                is_stale: true,
+                is_revalidating: false,
+            }
+        );
+    }
+}
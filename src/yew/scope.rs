@@ -0,0 +1,163 @@
+//! Nested caches, which inherit the entries of a parent cache.
+
+use super::{Cache, CacheItem, CacheKey, Entry};
+use std::rc::Rc;
+
+/// How the cache of a [`CacheProvider`](super::CacheProvider) relates to other caches.
+#[derive(Debug, Default)]
+pub enum CacheScope<M: 'static = ()> {
+    /// The cache is independent of any other cache.
+    #[default]
+    Isolated,
+    /// The cache inherits valid entries from the parent cache.
+    ///
+    /// When a component subscribes to data which has no entry in this cache yet, the valid value
+    /// of the parent is copied into it, so that it is not fetched again. Everything else, such as
+    /// fetches, invalidations and mutations, stays in this cache and never affects the parent.
+    /// Components only ever subscribe to this cache, so dropping it leaves no subscriptions
+    /// behind in the parent. The parent is not kept alive by the child.
+    Layered {
+        /// Cache to inherit entries from, usually the one of the surrounding provider.
+        parent: Cache<M>,
+    },
+}
+
+impl<M: 'static> Clone for CacheScope<M> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Isolated => Self::Isolated,
+            Self::Layered { parent } => Self::Layered {
+                parent: parent.clone(),
+            },
+        }
+    }
+}
+
+impl<M: 'static> PartialEq for CacheScope<M> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Isolated, Self::Isolated) => true,
+            (Self::Layered { parent }, Self::Layered { parent: other }) => parent == other,
+            _ => false,
+        }
+    }
+}
+
+impl<M: 'static> Cache<M> {
+    /// Set the scope of this cache, linking it to or unlinking it from a parent.
+    ///
+    /// Entries which were already inherited are kept when unlinking.
+    ///
+    /// # Panics
+    ///
+    /// Panics when linking a cache to itself.
+    pub fn set_scope(&self, scope: &CacheScope<M>) {
+        let parent = match scope {
+            CacheScope::Isolated => None,
+            CacheScope::Layered { parent } => {
+                assert!(parent != self, "Cache cannot be layered on top of itself");
+                Some(Rc::downgrade(&parent.cache))
+            }
+        };
+        self.cache.lock().expect("Failure to lock cache").parent = parent;
+    }
+
+    /// Parent this cache inherits entries from, if it is layered and the parent still exists.
+    pub fn parent(&self) -> Option<Cache<M>> {
+        let cache = self.cache.lock().expect("Failure to lock cache");
+        let cache = cache.parent.as_ref()?.upgrade()?;
+        Some(Cache { cache })
+    }
+
+    /// Copy the entry of this data from the parent, if this cache has none and the parent has a
+    /// valid value. Returns whether it was inherited.
+    ///
+    /// If the parent is itself layered, only its own entries are looked up.
+    pub(super) fn inherit_from_parent<T: CacheItem<M>>(&self, key: &T) -> bool {
+        if self.contains(key) {
+            return false;
+        }
+        let Some(parent) = self.parent() else {
+            return false;
+        };
+        let Some(inherited) = parent.inheritable(key) else {
+            return false;
+        };
+        let mut cache = self.cache.lock().expect("Failure to lock cache");
+        if cache.get(key).is_some() {
+            return false;
+        }
+        cache.insert(key.clone(), inherited);
+        true
+    }
+
+    /// Entry to copy into a child cache, if this data has a valid value.
+    fn inheritable<T: CacheItem<M>>(&self, key: &T) -> Option<Entry> {
+        let cache = self.cache.lock().expect("Failure to lock cache");
+        let entry = cache.entries.get(&*key.cache_key() as &dyn CacheKey<M>)?;
+        entry.value.valid().then(|| Entry {
+            value: entry.value.clone(),
+            value_type: entry.value_type,
+            last_updated: entry.last_updated,
+            ttl: entry.ttl,
+            stale_while_revalidate: entry.stale_while_revalidate,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yew::tests::{cache_with, counter, subscribers, Key};
+
+    #[test]
+    fn layered_cache_inherits_valid_entries() {
+        let parent = cache_with(&[1, 2]).with_spawn_fn(|_future| {});
+        parent.invalidate_key(&Key(2));
+        let child = Cache::<()>::default().with_spawn_fn(|_future| {});
+        child.set_scope(&CacheScope::Layered {
+            parent: parent.clone(),
+        });
+        assert_eq!(child.parent(), Some(parent.clone()));
+
+        // valid entries are inherited without fetching, stale ones are fetched by the child
+        let (subscriber, _) = counter();
+        child.subscribe_with(&Key(1), &subscriber, None, true);
+        child.subscribe_with(&Key(2), &subscriber, None, true);
+        assert_eq!(child.peek(&Key(1)).unwrap().data(), Some(&Rc::new(1)));
+        assert!(!child.peek_entry(&Key(1)).unwrap().progress);
+        assert!(child.peek_entry(&Key(2)).unwrap().progress);
+        assert!(!parent.peek_entry(&Key(2)).unwrap().progress);
+        assert_eq!(subscribers(&parent, 1), 0);
+
+        // invalidations and writes stay in the child
+        child.invalidate_key(&Key(1));
+        child.cache(&Key(2), Rc::new(3));
+        assert!(parent.peek(&Key(1)).unwrap().valid());
+        assert_eq!(parent.peek(&Key(2)).unwrap().data(), Some(&Rc::new(2)));
+
+        // existing child entries are not replaced by the parent
+        child.subscribe_with(&Key(1), &subscriber, None, false);
+        assert!(!child.peek(&Key(1)).unwrap().valid());
+    }
+
+    #[test]
+    fn isolated_cache_does_not_inherit() {
+        let parent = cache_with(&[1]);
+        let child = Cache::<()>::default().with_spawn_fn(|_future| {});
+        child.set_scope(&CacheScope::Layered {
+            parent: parent.clone(),
+        });
+        child.set_scope(&CacheScope::Isolated);
+        assert_eq!(child.parent(), None);
+        let (subscriber, _) = counter();
+        child.subscribe_with(&Key(1), &subscriber, None, true);
+        assert!(child.peek_entry(&Key(1)).unwrap().progress);
+
+        // the child does not keep the parent alive
+        let child = Cache::<()>::default();
+        child.set_scope(&CacheScope::Layered { parent });
+        assert_eq!(child.parent(), None);
+    }
+}
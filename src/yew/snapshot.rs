@@ -0,0 +1,109 @@
+//! In-memory snapshots of the cache state.
+
+use super::{Cache, CacheKey, Entry, RcValue};
+use std::collections::BTreeMap;
+
+/// Snapshot of the valid values of a [`Cache`], see [`Cache::snapshot`].
+///
+/// Values are kept as reference-counted pointers, so taking a snapshot is cheap.
+pub struct CacheSnapshot<M: 'static = ()> {
+    pub entries: BTreeMap<Box<dyn CacheKey<M>>, RcValue>,
+}
+
+impl<M: 'static> Clone for CacheSnapshot<M> {
+    fn clone(&self) -> Self {
+        Self {
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+impl<M: 'static> CacheSnapshot<M> {
+    /// Number of values in this snapshot.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Determine if this snapshot has no values.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<M: 'static> Cache<M> {
+    /// Take a snapshot of all valid values.
+    ///
+    /// Entries which are invalid or currently being fetched are excluded.
+    pub fn snapshot(&self) -> CacheSnapshot<M> {
+        let cache = self.cache.lock().expect("Failure to lock cache");
+        let entries = cache
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.value.valid() && !entry.progress)
+            .map(|(key, entry)| (key.clone(), entry.value.clone()))
+            .collect();
+        CacheSnapshot { entries }
+    }
+
+    /// Restore the values of a snapshot.
+    ///
+    /// Values are stored in their entries, creating them if needed, and broadcast to current
+    /// subscribers. Entries which are not part of the snapshot are left untouched.
+    pub fn restore(&self, snapshot: CacheSnapshot<M>) {
+        let mut cache = self.cache.lock().expect("Failure to lock cache");
+        for (key, value) in snapshot.entries {
+            match cache.entries.get_mut(&key) {
+                Some(entry) => {
+                    entry.value = value;
+                    entry.broadcast();
+                }
+                None => {
+                    let entry = Entry {
+                        value,
+                        created_at: Some(cache.clock.now()),
+                        ..Default::default()
+                    };
+                    cache.entries.insert(key, entry);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::yew::tests::{cache_with, counter, Key};
+    use std::rc::Rc;
+
+    #[test]
+    fn snapshot_excludes_invalid_and_in_progress() {
+        let cache = cache_with(&[1, 2, 3]);
+        cache.invalidate_key(&Key(2));
+        let mut inner = cache.cache.lock().unwrap();
+        inner
+            .mutate(&Key(3), |entry| entry.fetch_started())
+            .unwrap();
+        drop(inner);
+        let snapshot = cache.snapshot();
+        assert_eq!(snapshot.len(), 1);
+    }
+
+    #[test]
+    fn restore_broadcasts_values() {
+        let cache = cache_with(&[1]);
+        let snapshot = cache.snapshot();
+        let (subscriber, count) = counter();
+        cache.subscribe_with(&Key(1), &subscriber, None, false);
+        cache.cache(&Key(1), Rc::new(5));
+        assert_eq!(count.get(), 1);
+
+        cache.restore(snapshot.clone());
+        assert_eq!(count.get(), 2);
+        assert_eq!(cache.value_of(&Key(1)).data().map(|data| **data), Some(1));
+
+        // entries are created in another cache
+        let other = cache_with(&[]);
+        other.restore(snapshot);
+        assert!(other.value_of(&Key(1)).valid());
+    }
+}
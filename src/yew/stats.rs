@@ -0,0 +1,135 @@
+//! Counts of the entries of the cache, in total and by key type.
+
+use super::{Cache, CacheItem, Entry};
+use std::collections::BTreeMap;
+
+/// Counts of cache entries, see [`Cache::stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct EntryCounts {
+    /// Number of entries.
+    pub entries: usize,
+    /// Number of entries with valid data.
+    pub valid: usize,
+    /// Number of entries which are being fetched.
+    pub fetching: usize,
+    /// Number of entries with at least one subscriber.
+    pub subscribed: usize,
+}
+
+impl EntryCounts {
+    /// Count this entry.
+    fn add(&mut self, entry: &Entry) {
+        self.entries += 1;
+        self.valid += usize::from(entry.value.valid());
+        self.fetching += usize::from(entry.progress);
+        self.subscribed += usize::from(!entry.subscriptions.is_empty());
+    }
+}
+
+/// Counts of the entries of a [`Cache`], see [`Cache::stats`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CacheStats {
+    /// Counts of all entries.
+    pub total: EntryCounts,
+    /// Counts of the entries by the type name of their key.
+    pub by_type: BTreeMap<&'static str, EntryCounts>,
+}
+
+impl<M: 'static> Cache<M> {
+    /// Count the entries, in total and by the type of their key.
+    ///
+    /// The counts are taken under a single lock, so they are consistent with each other.
+    pub fn stats(&self) -> CacheStats {
+        let cache = self.cache.lock().expect("Failure to lock cache");
+        let mut stats = CacheStats::default();
+        for (key, entry) in &cache.entries {
+            stats.total.add(entry);
+            stats.by_type.entry(key.type_name()).or_default().add(entry);
+        }
+        stats
+    }
+
+    /// Count the entries whose key has this type.
+    pub fn stats_for<T: CacheItem<M>>(&self) -> EntryCounts {
+        let cache = self.cache.lock().expect("Failure to lock cache");
+        let mut counts = EntryCounts::default();
+        for (_, entry) in cache.entries.iter().filter(|(key, _)| key.any().is::<T>()) {
+            counts.add(entry);
+        }
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        yew::tests::{cache_with, counter, Failure, Key},
+        Invalidatable,
+    };
+
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Other(u64);
+
+    impl Invalidatable<()> for Other {}
+
+    #[async_trait::async_trait(?Send)]
+    impl CacheItem for Other {
+        type Value = u64;
+        type Error = Failure;
+
+        async fn send(&self) -> Result<Self::Value, Self::Error> {
+            Ok(self.0)
+        }
+    }
+
+    #[test]
+    fn stats_count_known_mix() {
+        // four valid keys, one of which is invalidated and one subscribed
+        let cache = cache_with(&[1, 2, 3, 4]).with_spawn_fn(|_future| {});
+        cache.invalidate_key(&Key(2));
+        let (subscriber, _) = counter();
+        cache.subscribe_with(&Key(3), &subscriber, None, false);
+
+        // two other keys being fetched, one of which is subscribed
+        cache.prefetch(Other(1));
+        cache.subscribe_with(&Other(2), &subscriber, None, true);
+
+        let key = EntryCounts {
+            entries: 4,
+            valid: 3,
+            fetching: 0,
+            subscribed: 1,
+        };
+        let other = EntryCounts {
+            entries: 2,
+            valid: 0,
+            fetching: 2,
+            subscribed: 1,
+        };
+        let stats = cache.stats();
+        assert_eq!(
+            stats.total,
+            EntryCounts {
+                entries: 6,
+                valid: 3,
+                fetching: 2,
+                subscribed: 2,
+            }
+        );
+        assert_eq!(stats.by_type.len(), 2);
+        assert_eq!(stats.by_type[std::any::type_name::<Key>()], key);
+        assert_eq!(stats.by_type[std::any::type_name::<Other>()], other);
+        assert_eq!(cache.stats_for::<Key>(), key);
+        assert_eq!(cache.stats_for::<Other>(), other);
+    }
+
+    #[test]
+    fn stats_of_empty_cache() {
+        let cache = cache_with(&[]);
+        assert_eq!(cache.stats(), CacheStats::default());
+        assert_eq!(cache.stats_for::<Key>(), EntryCounts::default());
+    }
+}
@@ -0,0 +1,204 @@
+//! Fetch status of cached data, for rendering loading and error states.
+
+use super::{use_cache, use_cached_in, Cache, CacheItem};
+use crate::RcValue;
+use yew::prelude::*;
+
+/// Status of fetching the data of an entry, see [`Cache::fetch_status`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FetchStatus {
+    /// No fetch is running.
+    #[default]
+    Idle,
+    /// A fetch is running or queued.
+    Fetching,
+    /// Fetching has failed this many times in a row, and is being retried, see
+    /// [`Entry::failures`](super::Entry::failures).
+    Retrying {
+        /// Number of consecutive failed fetches.
+        failures: u32,
+    },
+    /// Fetching has been given up after too many failures, see
+    /// [`CacheOptions::max_retries`](super::CacheOptions::max_retries). The data is only fetched
+    /// again once it is invalidated or [refetched](Cache::refetch).
+    Failed,
+}
+
+impl<M: 'static> Cache<M> {
+    /// Status of fetching this data, [`FetchStatus::Idle`] if there is no entry for it.
+    ///
+    /// The status is [`FetchStatus::Retrying`] from the first failed fetch until a fetch succeeds
+    /// or a value is written, including while waiting for the backoff delay.
+    pub fn fetch_status<T: CacheItem<M>>(&self, data: &T) -> FetchStatus {
+        let cache = self.cache.lock().expect("Failure to lock cache");
+        match cache.get(data) {
+            Some(entry) if entry.terminal => FetchStatus::Failed,
+            Some(entry) if entry.failures > 0 => FetchStatus::Retrying {
+                failures: entry.failures,
+            },
+            Some(entry) if entry.progress => FetchStatus::Fetching,
+            _ => FetchStatus::Idle,
+        }
+    }
+}
+
+/// Use cached data, along with the status of fetching it.
+///
+/// Behaves like [`use_cached`](super::use_cached), but additionally returns the
+/// [`FetchStatus`] of the data. This allows showing retries as they happen, since subscribers are
+/// notified of every failed fetch, and rendering an error once fetching has been given up, rather
+/// than a loading indicator which never goes away.
+///
+/// ```rust
+/// # use wasm_cache::{yew::*, CacheItem, Invalidatable};
+/// # use yew::prelude::*;
+/// # #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// # struct Document(u64);
+/// # impl Invalidatable<()> for Document {}
+/// # #[async_trait::async_trait(?Send)]
+/// # impl CacheItem for Document {
+/// #     type Value = String;
+/// #     type Error = std::fmt::Error;
+/// #     async fn send(&self) -> Result<Self::Value, Self::Error> {
+/// #         Err(std::fmt::Error)
+/// #     }
+/// # }
+/// #[function_component]
+/// fn DocumentView() -> Html {
+///     let cache = use_cache::<()>();
+///     let (document, status) = use_cached_status::<(), _>(Document(1));
+///     match (document.data(), status) {
+///         (Some(document), _) => html! { <p>{ document.to_string() }</p> },
+///         (None, FetchStatus::Failed) => {
+///             let retry = Callback::from(move |_| {
+///                 cache.refetch(&Document(1));
+///             });
+///             html! { <button onclick={retry}>{ "Retry" }</button> }
+///         }
+///         (None, FetchStatus::Retrying { failures }) => {
+///             html! { <p>{ format!("retrying (attempt {})", failures + 1) }</p> }
+///         }
+///         (None, _) => html! { <p>{ "loading" }</p> },
+///     }
+/// }
+/// ```
+#[hook]
+pub fn use_cached_status<M: 'static, R: CacheItem<M>>(data: R) -> (RcValue<R::Value>, FetchStatus) {
+    log::debug!("use_cached_status({data:?})");
+    let cache = use_cache::<M>();
+    let status = cache.fetch_status(&data);
+    (use_cached_in(cache, data), status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        yew::{
+            tests::{cache_with, counter, Failure, Key},
+            CacheOptions,
+        },
+        Invalidatable,
+    };
+    use std::rc::Rc;
+
+    /// Document which does not exist, so every fetch fails.
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Missing(u64);
+
+    impl Invalidatable<()> for Missing {}
+
+    #[async_trait::async_trait(?Send)]
+    impl CacheItem for Missing {
+        type Value = u64;
+        type Error = Failure;
+
+        async fn send(&self) -> Result<Self::Value, Self::Error> {
+            Err(Failure)
+        }
+
+        fn max_retries(&self) -> Option<u32> {
+            (self.0 == 0).then_some(0)
+        }
+    }
+
+    fn cache() -> Cache {
+        Cache::with_options(CacheOptions {
+            max_retries: Some(2),
+            ..Default::default()
+        })
+        .with_spawn_fn(|future| {
+            futures::executor::block_on(future);
+        })
+    }
+
+    #[test]
+    fn fetching_given_up_after_max_retries() {
+        let cache = cache();
+        let (subscriber, _) = counter();
+        cache.subscribe_with(&Missing(1), &subscriber, None, true);
+        for failures in 1..=2 {
+            assert_eq!(
+                cache.fetch_status(&Missing(1)),
+                FetchStatus::Retrying { failures }
+            );
+            cache.fetch(&Missing(1), None);
+        }
+        assert_eq!(cache.fetch_status(&Missing(1)), FetchStatus::Failed);
+        assert_eq!(cache.peek_entry(&Missing(1)).unwrap().failures, 3);
+
+        // subscribers no longer start fetches
+        cache.subscribe_with(&Missing(1), &subscriber, None, true);
+        assert_eq!(cache.peek_entry(&Missing(1)).unwrap().failures, 3);
+
+        // refetching retries from scratch
+        assert!(cache.refetch(&Missing(1)));
+        assert_eq!(
+            cache.fetch_status(&Missing(1)),
+            FetchStatus::Retrying { failures: 1 }
+        );
+    }
+
+    #[test]
+    fn failures_broadcast_until_success() {
+        let cache = cache_with(&[1]);
+        let (subscriber, count) = counter();
+        cache.subscribe_with(&Key(1), &subscriber, None, false);
+        assert_eq!(cache.fetch_status(&Key(1)), FetchStatus::Idle);
+
+        cache.invalidate_key(&Key(1));
+        cache.failure(&Key(1), Failure);
+        cache.failure(&Key(1), Failure);
+        assert_eq!(count.get(), 3);
+        assert_eq!(
+            cache.fetch_status(&Key(1)),
+            FetchStatus::Retrying { failures: 2 }
+        );
+
+        cache.cache(&Key(1), Rc::new(1));
+        assert_eq!(count.get(), 4);
+        assert_eq!(cache.fetch_status(&Key(1)), FetchStatus::Idle);
+    }
+
+    #[test]
+    fn invalidate_clears_terminal_failure() {
+        let cache = cache();
+        let (subscriber, _) = counter();
+
+        // the item override applies instead of the cache-wide maximum
+        cache.subscribe_with(&Missing(0), &subscriber, None, true);
+        assert_eq!(cache.fetch_status(&Missing(0)), FetchStatus::Failed);
+        assert!(cache.peek_entry(&Missing(0)).unwrap().terminal);
+
+        cache.invalidate_key(&Missing(0));
+        assert_eq!(cache.peek_entry(&Missing(0)).unwrap().failures, 0);
+        assert_eq!(cache.fetch_status(&Missing(0)), FetchStatus::Idle);
+        cache.fetch(&Missing(0), None);
+        assert_eq!(cache.fetch_status(&Missing(0)), FetchStatus::Failed);
+
+        // a successful write clears it as well
+        cache.set_value(&Missing(0), 1);
+        assert_eq!(cache.fetch_status(&Missing(0)), FetchStatus::Idle);
+        assert_eq!(cache.peek_entry(&Missing(0)).unwrap().failures, 0);
+    }
+}
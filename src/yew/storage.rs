@@ -0,0 +1,499 @@
+//! Persistent storage of cached values, such as in IndexedDB.
+//!
+//! Values are persisted per key type: after [`Cache::persist`] has been called for a type, every
+//! value of it which is [cached](Cache::cache) is serialized as JSON and written to the
+//! [`CacheStorage`] in the background. When a component subscribes to a key which has no entry
+//! yet, the stored value is loaded in the background and [seeded](Cache::seed) into the entry, so
+//! that it is shown while the data is revalidated. Values of items marked as
+//! [`no_store`](crate::CacheControlHints::no_store) are never persisted, and stored values are
+//! removed along with their entry by [`Cache::remove`] and [`Cache::clear`].
+
+use super::{Cache, CacheItem, CacheKey};
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    any::{Any, TypeId},
+    cell::RefCell,
+    collections::BTreeMap,
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+    rc::Rc,
+};
+
+/// Error of a [`CacheStorage`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StorageError(pub String);
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "storage error: {}", self.0)
+    }
+}
+
+impl Error for StorageError {}
+
+/// Asynchronous storage of serialized values, see [`Cache::persist`].
+#[async_trait(?Send)]
+pub trait CacheStorage {
+    /// Load the value stored under this key, if any.
+    async fn load(&self, key: &str) -> Result<Option<String>, StorageError>;
+
+    /// Store this value under this key, replacing any previous one.
+    async fn store(&self, key: &str, value: String) -> Result<(), StorageError>;
+
+    /// Remove the value stored under this key.
+    async fn remove(&self, key: &str) -> Result<(), StorageError>;
+}
+
+/// Storage which keeps values in memory, for testing and as a fallback.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    values: RefCell<BTreeMap<String, String>>,
+}
+
+#[async_trait(?Send)]
+impl CacheStorage for MemoryStorage {
+    async fn load(&self, key: &str) -> Result<Option<String>, StorageError> {
+        Ok(self.values.borrow().get(key).cloned())
+    }
+
+    async fn store(&self, key: &str, value: String) -> Result<(), StorageError> {
+        self.values.borrow_mut().insert(key.into(), value);
+        Ok(())
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), StorageError> {
+        self.values.borrow_mut().remove(key);
+        Ok(())
+    }
+}
+
+/// Function writing a type-erased key and value to the storage.
+type StoreFn<M> = Rc<dyn Fn(&Cache<M>, &dyn Any, &dyn Any)>;
+
+/// Function loading the value of a type-erased key from the storage.
+type LoadFn<M> = Rc<dyn Fn(&Cache<M>, &dyn Any)>;
+
+/// Function removing the value of a type-erased key from the storage.
+type RemoveFn<M> = Rc<dyn Fn(&Cache<M>, &dyn Any)>;
+
+/// Type-erased functions persisting the values of one key type, see [`Cache::persist`].
+pub struct PersistedType<M: 'static> {
+    type_id: TypeId,
+    store: StoreFn<M>,
+    load: LoadFn<M>,
+    remove: RemoveFn<M>,
+}
+
+impl<M: 'static> Clone for PersistedType<M> {
+    fn clone(&self) -> Self {
+        Self {
+            type_id: self.type_id,
+            store: self.store.clone(),
+            load: self.load.clone(),
+            remove: self.remove.clone(),
+        }
+    }
+}
+
+impl<M: 'static> std::fmt::Debug for PersistedType<M> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("PersistedType")
+            .field("type_id", &self.type_id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Key under which the value of this data is stored: its type name and its JSON representation.
+fn storage_key<T: Serialize>(key: &T) -> Result<String, serde_json::Error> {
+    Ok(format!(
+        "{}:{}",
+        std::any::type_name::<T>(),
+        serde_json::to_string(key)?
+    ))
+}
+
+impl<M: 'static> Cache<M> {
+    /// Persist the values of this key type in the storage, replacing any previous storage for it.
+    ///
+    /// Every value which is cached for a key of this type is written to the storage in the
+    /// background, and values are loaded from it when a key without entry is subscribed to. Loaded
+    /// values are stale, so they are still revalidated. Errors of the storage are logged.
+    pub fn persist<T>(&self, storage: Rc<dyn CacheStorage>)
+    where
+        T: CacheItem<M> + Serialize,
+        T::Value: Serialize + DeserializeOwned,
+    {
+        let store = {
+            let storage = storage.clone();
+            move |cache: &Cache<M>, key: &dyn Any, value: &dyn Any| {
+                let (Some(key), Some(value)) =
+                    (key.downcast_ref::<T>(), value.downcast_ref::<T::Value>())
+                else {
+                    return;
+                };
+                let key = key.cache_key();
+                let (key, value) = match (storage_key(&*key), serde_json::to_string(value)) {
+                    (Ok(key), Ok(value)) => (key, value),
+                    (Err(error), _) | (_, Err(error)) => {
+                        log::error!("Error serializing {key:?}: {error}");
+                        return;
+                    }
+                };
+                let storage = storage.clone();
+                cache.spawn(async move {
+                    if let Err(error) = storage.store(&key, value).await {
+                        log::error!("Error storing {key}: {error}");
+                    }
+                });
+            }
+        };
+        let remove = {
+            let storage = storage.clone();
+            move |cache: &Cache<M>, key: &dyn Any| {
+                let Some(key) = key.downcast_ref::<T>() else {
+                    return;
+                };
+                let name = match storage_key(&*key.cache_key()) {
+                    Ok(name) => name,
+                    Err(error) => {
+                        log::error!("Error serializing {key:?}: {error}");
+                        return;
+                    }
+                };
+                let storage = storage.clone();
+                cache.spawn(async move {
+                    if let Err(error) = storage.remove(&name).await {
+                        log::error!("Error removing {name}: {error}");
+                    }
+                });
+            }
+        };
+        let load = move |cache: &Cache<M>, key: &dyn Any| {
+            let Some(key) = key.downcast_ref::<T>() else {
+                return;
+            };
+            let name = match storage_key(&*key.cache_key()) {
+                Ok(name) => name,
+                Err(error) => {
+                    log::error!("Error serializing {key:?}: {error}");
+                    return;
+                }
+            };
+            let (storage, handle, key) = (storage.clone(), cache.clone(), key.clone());
+            cache.spawn(async move {
+                let value = match storage.load(&name).await {
+                    Ok(Some(value)) => value,
+                    Ok(None) => return,
+                    Err(error) => {
+                        log::error!("Error loading {name}: {error}");
+                        return;
+                    }
+                };
+                match serde_json::from_str(&value) {
+                    Ok(value) => {
+                        handle.seed(&key, value);
+                    }
+                    Err(error) => log::error!("Error deserializing {name}: {error}"),
+                }
+            });
+        };
+        let persisted = PersistedType {
+            type_id: TypeId::of::<T>(),
+            store: Rc::new(store),
+            load: Rc::new(load),
+            remove: Rc::new(remove),
+        };
+        let mut cache = self.cache.lock().expect("Failure to lock cache");
+        cache
+            .storage
+            .retain(|other| other.type_id != persisted.type_id);
+        cache.storage.push(persisted);
+    }
+
+    /// Functions persisting the values of the key type with this id, if it is persisted.
+    fn persisted(&self, type_id: TypeId) -> Option<PersistedType<M>> {
+        let cache = self.cache.lock().expect("Failure to lock cache");
+        cache
+            .storage
+            .iter()
+            .find(|persisted| persisted.type_id == type_id)
+            .cloned()
+    }
+
+    /// Write this value of this data to its storage, if its type is persisted and it may be
+    /// stored, see [`CacheControlHints::no_store`](crate::CacheControlHints::no_store).
+    pub(super) fn store_persisted<T: CacheItem<M>>(&self, key: &T, value: &T::Value) {
+        if key.cache_control().no_store {
+            return;
+        }
+        if let Some(persisted) = self.persisted(TypeId::of::<T>()) {
+            (persisted.store)(self, key, value);
+        }
+    }
+
+    /// Load the value of this data from its storage, if its type is persisted.
+    pub(super) fn load_persisted<T: CacheItem<M>>(&self, key: &T) {
+        if let Some(persisted) = self.persisted(TypeId::of::<T>()) {
+            (persisted.load)(self, key);
+        }
+    }
+
+    /// Remove the value of this type-erased key from its storage, if its type is persisted.
+    pub(super) fn remove_persisted(&self, key: &dyn CacheKey<M>) {
+        let key = key.any();
+        if let Some(persisted) = self.persisted(key.type_id()) {
+            (persisted.remove)(self, key);
+        }
+    }
+}
+
+/// Storage in an IndexedDB database of the browser.
+///
+/// Values are kept in a single object store, keyed by strings. Unlike local storage, IndexedDB
+/// is not limited to a few megabytes, which makes it suitable for large caches.
+#[cfg(all(feature = "indexed-db", target_arch = "wasm32"))]
+#[derive(Clone, Debug)]
+pub struct IndexedDbStorage {
+    database: String,
+    store: String,
+}
+
+#[cfg(all(feature = "indexed-db", target_arch = "wasm32"))]
+mod indexed_db {
+    use super::*;
+    use js_sys::{Function, Promise};
+    use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+    use wasm_bindgen_futures::JsFuture;
+    use web_sys::{
+        Event, IdbDatabase, IdbObjectStore, IdbOpenDbRequest, IdbRequest, IdbTransactionMode,
+    };
+
+    /// Convert a JavaScript error.
+    fn js_error(error: JsValue) -> StorageError {
+        StorageError(format!("{error:?}"))
+    }
+
+    /// Wait for this request to complete, returning its result.
+    async fn complete(request: &IdbRequest) -> Result<JsValue, StorageError> {
+        let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+            let success = {
+                let request = request.clone();
+                Closure::once_into_js(move |_: Event| {
+                    let result = request.result().unwrap_or(JsValue::UNDEFINED);
+                    let _ = resolve.call1(&JsValue::UNDEFINED, &result);
+                })
+            };
+            let failure = {
+                let request = request.clone();
+                Closure::once_into_js(move |_: Event| {
+                    let error = request
+                        .error()
+                        .ok()
+                        .flatten()
+                        .map(JsValue::from)
+                        .unwrap_or(JsValue::UNDEFINED);
+                    let _ = reject.call1(&JsValue::UNDEFINED, &error);
+                })
+            };
+            request.set_onsuccess(Some(success.unchecked_ref()));
+            request.set_onerror(Some(failure.unchecked_ref()));
+        });
+        JsFuture::from(promise).await.map_err(js_error)
+    }
+
+    impl IndexedDbStorage {
+        /// Storage in the IndexedDB database with this name, which is created if needed.
+        pub fn new(database: impl Into<String>) -> Self {
+            Self {
+                database: database.into(),
+                store: "entries".into(),
+            }
+        }
+
+        /// Open the database, creating the object store if needed.
+        async fn open(&self) -> Result<IdbDatabase, StorageError> {
+            let factory = web_sys::window()
+                .ok_or_else(|| StorageError("no window".into()))?
+                .indexed_db()
+                .map_err(js_error)?
+                .ok_or_else(|| StorageError("IndexedDB is not supported".into()))?;
+            let request: IdbOpenDbRequest =
+                factory.open_with_u32(&self.database, 1).map_err(js_error)?;
+            let upgrade = {
+                let (request, store) = (request.clone(), self.store.clone());
+                Closure::once_into_js(move |_: Event| {
+                    let database = request.result().map(IdbDatabase::unchecked_from_js);
+                    if let Ok(database) = database {
+                        if let Err(error) = database.create_object_store(&store) {
+                            log::error!("Error creating object store: {error:?}");
+                        }
+                    }
+                })
+            };
+            request.set_onupgradeneeded(Some(upgrade.unchecked_ref()));
+            let database = complete(&request).await?;
+            Ok(database.unchecked_into())
+        }
+
+        /// Open the object store in a new transaction.
+        async fn object_store(
+            &self,
+            mode: IdbTransactionMode,
+        ) -> Result<IdbObjectStore, StorageError> {
+            let database = self.open().await?;
+            database
+                .transaction_with_str_and_mode(&self.store, mode)
+                .and_then(|transaction| transaction.object_store(&self.store))
+                .map_err(js_error)
+        }
+    }
+
+    #[async_trait(?Send)]
+    impl CacheStorage for IndexedDbStorage {
+        async fn load(&self, key: &str) -> Result<Option<String>, StorageError> {
+            let store = self.object_store(IdbTransactionMode::Readonly).await?;
+            let request = store.get(&JsValue::from_str(key)).map_err(js_error)?;
+            Ok(complete(&request).await?.as_string())
+        }
+
+        async fn store(&self, key: &str, value: String) -> Result<(), StorageError> {
+            let store = self.object_store(IdbTransactionMode::Readwrite).await?;
+            let request = store
+                .put_with_key(&JsValue::from_str(&value), &JsValue::from_str(key))
+                .map_err(js_error)?;
+            complete(&request).await.map(|_| ())
+        }
+
+        async fn remove(&self, key: &str) -> Result<(), StorageError> {
+            let store = self.object_store(IdbTransactionMode::Readwrite).await?;
+            let request = store.delete(&JsValue::from_str(key)).map_err(js_error)?;
+            complete(&request).await.map(|_| ())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        yew::tests::{counter, Failure},
+        Invalidatable,
+    };
+    use futures::executor::block_on;
+
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+    struct Profile(u64);
+
+    impl Invalidatable<()> for Profile {}
+
+    #[async_trait(?Send)]
+    impl CacheItem for Profile {
+        type Value = String;
+        type Error = Failure;
+
+        async fn send(&self) -> Result<Self::Value, Self::Error> {
+            Ok(format!("profile {}", self.0))
+        }
+    }
+
+    #[test]
+    fn persisted_values_are_stored_and_loaded() {
+        let storage = Rc::new(MemoryStorage::default());
+        let cache = Cache::<()>::default().with_spawn_fn(|future| {
+            block_on(future);
+        });
+        cache.persist::<Profile>(storage.clone());
+        cache.set_value(&Profile(1), "stored".to_string());
+        let name = storage_key(&Profile(1)).unwrap();
+        assert_eq!(block_on(storage.load(&name)), Ok(Some("\"stored\"".into())));
+
+        // a new cache loads the stored value, which is seeded until it is fetched
+        let cache = Cache::<()>::default().with_spawn_fn(|future| {
+            block_on(future);
+        });
+        cache.persist::<Profile>(storage);
+        let (subscriber, count) = counter();
+        cache.subscribe_with(&Profile(1), &subscriber, None, false);
+        let value = cache.peek(&Profile(1)).unwrap();
+        assert_eq!(value.data(), Some(&Rc::new("stored".to_string())));
+        assert!(!value.valid());
+        assert_eq!(count.get(), 1);
+
+        // keys without stored value stay empty
+        cache.subscribe_with(&Profile(2), &subscriber, None, false);
+        assert_eq!(cache.peek(&Profile(2)).unwrap().data(), None);
+    }
+
+    /// Session token, which must never be written to the storage.
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+    struct Token(u64);
+
+    impl Invalidatable<()> for Token {}
+
+    #[async_trait(?Send)]
+    impl CacheItem for Token {
+        type Value = String;
+        type Error = Failure;
+
+        async fn send(&self) -> Result<Self::Value, Self::Error> {
+            Ok(format!("token {}", self.0))
+        }
+
+        fn cache_control(&self) -> crate::CacheControlHints {
+            crate::CacheControlHints {
+                no_store: true,
+                ..Default::default()
+            }
+        }
+    }
+
+    fn persisting_cache(storage: &Rc<MemoryStorage>) -> Cache {
+        let cache = Cache::<()>::default().with_spawn_fn(|future| {
+            block_on(future);
+        });
+        cache.persist::<Profile>(storage.clone());
+        cache.persist::<Token>(storage.clone());
+        cache
+    }
+
+    #[test]
+    fn no_store_values_not_persisted() {
+        let storage = Rc::new(MemoryStorage::default());
+        let cache = persisting_cache(&storage);
+        cache.set_value(&Token(1), "secret".to_string());
+        assert!(cache.contains(&Token(1)));
+        let name = storage_key(&Token(1)).unwrap();
+        assert_eq!(block_on(storage.load(&name)), Ok(None));
+    }
+
+    #[test]
+    fn removed_values_not_reloaded() {
+        let storage = Rc::new(MemoryStorage::default());
+        let cache = persisting_cache(&storage);
+        for id in 1..=3 {
+            cache.set_value(&Profile(id), format!("profile {id}"));
+        }
+        assert!(cache.remove(&Profile(1)));
+        cache.clear();
+
+        let cache = persisting_cache(&storage);
+        let (subscriber, _) = counter();
+        for id in 1..=3 {
+            cache.subscribe_with(&Profile(id), &subscriber, None, false);
+            assert_eq!(cache.peek(&Profile(id)).unwrap().data(), None);
+            let name = storage_key(&Profile(id)).unwrap();
+            assert_eq!(block_on(storage.load(&name)), Ok(None));
+        }
+    }
+
+    #[test]
+    fn memory_storage_removes_values() {
+        let storage = MemoryStorage::default();
+        block_on(async {
+            storage.store("key", "value".into()).await.unwrap();
+            storage.remove("key").await.unwrap();
+            assert_eq!(storage.load("key").await, Ok(None));
+        });
+    }
+}
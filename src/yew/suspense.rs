@@ -0,0 +1,164 @@
+//! Integration with Yew's [`Suspense`](yew::suspense::Suspense).
+
+use super::{use_cache, use_cached, Cache, CacheItem, Entry};
+use std::{cell::RefCell, rc::Rc};
+use yew::{
+    prelude::*,
+    suspense::{Suspension, SuspensionHandle, SuspensionResult},
+};
+
+/// Suspension of the components waiting for the value of an entry.
+///
+/// All components suspending on the same key share it, it is resumed once a fetch of the entry
+/// completes.
+#[derive(Clone, Debug)]
+pub struct EntrySuspension {
+    suspension: Suspension,
+    handle: Rc<RefCell<Option<SuspensionHandle>>>,
+}
+
+impl Entry {
+    /// Suspension for components waiting for the value of this entry.
+    ///
+    /// Returns the current suspension if it has not been resumed yet, or creates a new one.
+    pub fn suspension(&mut self) -> Suspension {
+        match &self.suspension {
+            Some(current) if !current.suspension.resumed() => current.suspension.clone(),
+            _ => {
+                let (suspension, handle) = Suspension::new();
+                self.suspension = Some(EntrySuspension {
+                    suspension: suspension.clone(),
+                    handle: Rc::new(RefCell::new(Some(handle))),
+                });
+                suspension
+            }
+        }
+    }
+
+    /// Resume the components suspended on this entry, if any.
+    pub fn resume(&mut self) {
+        if let Some(current) = self.suspension.take() {
+            if let Some(handle) = current.handle.borrow_mut().take() {
+                handle.resume();
+            }
+        }
+    }
+}
+
+impl<M: 'static> Cache<M> {
+    /// Suspension which is resumed once this data has been fetched.
+    ///
+    /// Returns a resumed suspension if there is no entry for this data.
+    fn suspension<T: CacheItem<M>>(&self, data: &T) -> Suspension {
+        self.cache
+            .lock()
+            .expect("Failure to lock cache")
+            .mutate(data, Entry::suspension)
+            .unwrap_or_else(|| Suspension::new().0)
+    }
+}
+
+/// Use cached data, suspending the component while it is loading.
+///
+/// While the entry has no data, the data is fetched and the component suspends, so the loading
+/// state is handled by the surrounding [`Suspense`](yew::suspense::Suspense) boundary. Components
+/// waiting for the same key share the same suspension. Once the data is available, this behaves
+/// like [`use_cached`].
+///
+/// A failed fetch resumes the component, which then suspends again until a retry succeeds. Use
+/// [`use_cached_suspense_result`] to handle failures instead.
+#[hook]
+pub fn use_cached_suspense<M: 'static, R: CacheItem<M>>(data: R) -> SuspensionResult<Rc<R::Value>> {
+    let cache = use_cache::<M>();
+    match use_cached_suspense_result::<M, R>(data.clone())? {
+        Ok(value) => Ok(value),
+        Err(_) => Err(suspend(&cache, &data)),
+    }
+}
+
+/// Fetch this data if needed, returning the suspension of its entry.
+fn suspend<M: 'static, R: CacheItem<M>>(cache: &Cache<M>, data: &R) -> Suspension {
+    cache.prefetch(data.clone());
+    cache.suspension(data)
+}
+
+/// Cached data or the error of its last failed fetch, see [`use_cached_suspense_result`].
+pub type CachedResult<V, E> = Result<Rc<V>, Rc<E>>;
+
+/// Use cached data, suspending the component while it is loading and returning failures.
+///
+/// Behaves like [`use_cached_suspense`], but when a fetch fails and there is no data to show, the
+/// error of the last fetch is returned instead of suspending again, see [`Cache::error_of`].
+#[hook]
+pub fn use_cached_suspense_result<M: 'static, R: CacheItem<M>>(
+    data: R,
+) -> SuspensionResult<CachedResult<R::Value, R::Error>> {
+    log::debug!("use_cached_suspense_result({data:?})");
+    let cache = use_cache::<M>();
+    let value = use_cached::<M, R>(data.clone());
+    if let Some(value) = value.data() {
+        return Ok(Ok(value.clone()));
+    }
+    match cache.suspense_result(&data) {
+        Some(result) => Ok(result),
+        None => Err(suspend(&cache, &data)),
+    }
+}
+
+impl<M: 'static> Cache<M> {
+    /// Current data or error of the last failed fetch of this data, `None` if the component has
+    /// to suspend.
+    fn suspense_result<R: CacheItem<M>>(
+        &self,
+        data: &R,
+    ) -> Option<CachedResult<R::Value, R::Error>> {
+        if let Some(value) = self.value_of(data).data() {
+            return Some(Ok(value.clone()));
+        }
+        self.error_of(data).map(Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::yew::tests::{cache_with, Failure, Key};
+    use std::rc::Rc;
+
+    #[test]
+    fn suspension_shared_between_waiters() {
+        let cache = cache_with(&[1]);
+        let first = cache.suspension(&Key(1));
+        let second = cache.suspension(&Key(1));
+        assert_eq!(first, second);
+        assert!(!first.resumed());
+
+        cache.cache(&Key(1), Rc::new(2));
+        assert!(first.resumed());
+
+        // a new suspension is created once the previous one has been resumed
+        let third = cache.suspension(&Key(1));
+        assert_ne!(first, third);
+        cache.failure(&Key(1), Failure);
+        assert!(third.resumed());
+    }
+
+    #[test]
+    fn suspense_result_after_errors_drained() {
+        let cache = cache_with(&[]).with_spawn_fn(|_future| {});
+        assert!(cache.suspense_result(&Key(1)).is_none());
+
+        cache.prefetch(Key(1));
+        cache.failure(&Key(1), Failure);
+        assert_eq!(cache.drain_errors().len(), 1);
+        assert!(matches!(cache.suspense_result(&Key(1)), Some(Err(_))));
+
+        cache.cache(&Key(1), Rc::new(1));
+        assert!(matches!(cache.suspense_result(&Key(1)), Some(Ok(value)) if *value == 1));
+    }
+
+    #[test]
+    fn suspension_without_entry_is_resumed() {
+        let cache = cache_with(&[]);
+        assert!(cache.suspension(&Key(1)).resumed());
+    }
+}
@@ -0,0 +1,370 @@
+//! Utilities for testing code which uses the [`Cache`].
+//!
+//! The [`MockItem`] resolves from a queue of canned results, and the [`TestCache`] drives its
+//! fetches synchronously, so that retry, invalidation and deduplication logic can be tested
+//! natively without a browser. Broadcasts to subscribers are captured with a [`Recorder`].
+//!
+//! ```
+//! use wasm_cache::yew::{MockItem, TestCache};
+//!
+//! let cache = TestCache::<()>::new();
+//! let item: MockItem = MockItem::new(1).with_ok(42);
+//! let recorder = cache.subscribe(&item);
+//!
+//! assert!(cache.resolve(&item));
+//! assert_eq!(recorder.values::<u64>(), vec![Some(42)]);
+//! ```
+
+use super::{Cache, CacheItem, Entry, RcValue, Subscriber};
+use crate::Invalidatable;
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Ordering,
+    collections::VecDeque,
+    fmt::{Debug, Display, Formatter},
+    rc::Rc,
+};
+use yew::Callback;
+
+/// Error returned by a [`MockItem`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MockError {
+    /// Canned failure.
+    Failure,
+    /// No canned results are left.
+    Exhausted,
+}
+
+impl Display for MockError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Failure => write!(f, "mock failure"),
+            Self::Exhausted => write!(f, "no mock results left"),
+        }
+    }
+}
+
+impl std::error::Error for MockError {}
+
+/// Cache item which resolves from a queue of canned results.
+///
+/// Items are identified by their `id` only. Clones share the queue of results and the count of
+/// calls to [`CacheItem::send`].
+pub struct MockItem<V = u64> {
+    /// Identity of the item, used for comparing and ordering it.
+    pub id: u64,
+    results: Rc<RefCell<VecDeque<Result<V, MockError>>>>,
+    calls: Rc<Cell<usize>>,
+}
+
+impl<V> MockItem<V> {
+    /// Item with this id, which has no canned results yet.
+    pub fn new(id: u64) -> Self {
+        Self {
+            id,
+            results: Default::default(),
+            calls: Default::default(),
+        }
+    }
+
+    /// Queue a result for the next call to [`CacheItem::send`].
+    pub fn push(&self, result: Result<V, MockError>) {
+        self.results.borrow_mut().push_back(result);
+    }
+
+    /// Queue a successful result.
+    pub fn with_ok(self, value: V) -> Self {
+        self.push(Ok(value));
+        self
+    }
+
+    /// Queue a failure.
+    pub fn with_err(self) -> Self {
+        self.push(Err(MockError::Failure));
+        self
+    }
+
+    /// Number of times this item has been sent.
+    pub fn calls(&self) -> usize {
+        self.calls.get()
+    }
+
+    /// Number of canned results which are left.
+    pub fn remaining(&self) -> usize {
+        self.results.borrow().len()
+    }
+}
+
+impl<V> Clone for MockItem<V> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            results: self.results.clone(),
+            calls: self.calls.clone(),
+        }
+    }
+}
+
+impl<V> Debug for MockItem<V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("MockItem").field(&self.id).finish()
+    }
+}
+
+impl<V> PartialEq for MockItem<V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<V> Eq for MockItem<V> {}
+
+impl<V> PartialOrd for MockItem<V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<V> Ord for MockItem<V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.id.cmp(&other.id)
+    }
+}
+
+impl<M, V> Invalidatable<M> for MockItem<V> {}
+
+#[async_trait::async_trait(?Send)]
+impl<M: 'static, V: Clone + Debug + PartialEq + 'static> CacheItem<M> for MockItem<V> {
+    type Value = V;
+    type Error = MockError;
+
+    async fn send(&self) -> Result<Self::Value, Self::Error> {
+        self.calls.set(self.calls.get() + 1);
+        self.results
+            .borrow_mut()
+            .pop_front()
+            .unwrap_or(Err(MockError::Exhausted))
+    }
+}
+
+/// Subscriber which records every value broadcast to it.
+#[derive(Clone, Debug)]
+pub struct Recorder {
+    subscriber: Subscriber,
+    broadcasts: Rc<RefCell<Vec<RcValue>>>,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        let broadcasts: Rc<RefCell<Vec<RcValue>>> = Default::default();
+        let subscriber = {
+            let broadcasts = broadcasts.clone();
+            Callback::from(move |value| broadcasts.borrow_mut().push(value)).into()
+        };
+        Self {
+            subscriber,
+            broadcasts,
+        }
+    }
+}
+
+impl Recorder {
+    /// Recorder which has not received any broadcasts yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscriber to register with the cache.
+    pub fn subscriber(&self) -> &Subscriber {
+        &self.subscriber
+    }
+
+    /// All values broadcast so far, in order.
+    pub fn broadcasts(&self) -> Vec<RcValue> {
+        self.broadcasts.borrow().clone()
+    }
+
+    /// Data of all values broadcast so far, in order.
+    ///
+    /// Values without data, or with data of another type, are returned as `None`.
+    pub fn values<T: Clone + 'static>(&self) -> Vec<Option<T>> {
+        self.broadcasts
+            .borrow()
+            .iter()
+            .map(|value| {
+                value
+                    .data()
+                    .and_then(|data| data.downcast_ref::<T>())
+                    .cloned()
+            })
+            .collect()
+    }
+
+    /// Number of values broadcast so far.
+    pub fn count(&self) -> usize {
+        self.broadcasts.borrow().len()
+    }
+
+    /// Forget all values broadcast so far.
+    pub fn clear(&self) {
+        self.broadcasts.borrow_mut().clear();
+    }
+}
+
+/// Cache whose fetches are driven synchronously by the test.
+///
+/// Subscribing through this never spawns a fetch. Instead, the test calls [`TestCache::resolve`]
+/// to run the fetch of an item to completion, which stores the result as a background fetch would.
+pub struct TestCache<M: 'static = ()> {
+    pub cache: Cache<M>,
+}
+
+impl<M: 'static> Clone for TestCache<M> {
+    fn clone(&self) -> Self {
+        Self {
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<M: 'static> Default for TestCache<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M: 'static> From<Cache<M>> for TestCache<M> {
+    fn from(cache: Cache<M>) -> Self {
+        Self { cache }
+    }
+}
+
+impl<M: 'static> TestCache<M> {
+    /// Test cache around a new, empty cache.
+    pub fn new() -> Self {
+        Self {
+            cache: Cache::default(),
+        }
+    }
+
+    /// Subscribe a new [`Recorder`] to this item, without fetching it.
+    pub fn subscribe<R: CacheItem<M>>(&self, item: &R) -> Recorder {
+        let recorder = Recorder::new();
+        self.cache
+            .subscribe_with(item, recorder.subscriber(), None, false);
+        recorder
+    }
+
+    /// Unsubscribe this recorder from the item.
+    pub fn unsubscribe<R: CacheItem<M>>(&self, item: &R, recorder: &Recorder) {
+        self.cache.unsubscribe(item, recorder.subscriber());
+    }
+
+    /// Determine if the cache would fetch this item now.
+    pub fn needs_fetch<R: CacheItem<M>>(&self, item: &R) -> bool {
+        let cache = self.cache.cache.lock().expect("Failure to lock cache");
+        let now = cache.clock.now();
+        cache
+            .get(item)
+            .map(|entry| entry.needs_fetch(now))
+            .unwrap_or(true)
+    }
+
+    /// Fetch this item to completion and store the result.
+    ///
+    /// Creates the entry if it does not exist yet. Returns whether the fetch succeeded.
+    pub fn resolve<R: CacheItem<M>>(&self, item: &R) -> bool {
+        let mut cache = self.cache.cache.lock().expect("Failure to lock cache");
+        if cache.get(item).is_none() {
+            cache.insert(item.clone(), Entry::default());
+        }
+        if cache.mutate(item, |entry| !entry.progress) == Some(true) {
+            cache.fetch_begun();
+        }
+        let _ = cache.mutate(item, Entry::fetch_started);
+        drop(cache);
+
+        match futures::executor::block_on(item.send()) {
+            Ok(value) => {
+                self.cache.cache_fetched(item, Rc::new(value));
+                true
+            }
+            Err(error) => {
+                self.cache.failure(item, error);
+                false
+            }
+        }
+    }
+
+    /// Current value of this item.
+    pub fn value<R: CacheItem<M>>(&self, item: &R) -> RcValue<R::Value> {
+        self.cache.value_of(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yew::CacheOptions;
+
+    #[test]
+    fn mock_item_resolves_in_order() {
+        let cache = TestCache::<()>::new();
+        let item: MockItem = MockItem::new(1).with_err().with_ok(7);
+        let recorder = cache.subscribe(&item);
+
+        assert!(!cache.resolve(&item));
+        assert!(cache.resolve(&item));
+        assert!(!cache.resolve(&item));
+        assert_eq!(item.calls(), 3);
+        assert_eq!(recorder.values::<u64>(), vec![None, Some(7), Some(7)]);
+    }
+
+    #[test]
+    fn failure_keeps_value_and_backs_off() {
+        let cache = TestCache::<()>::new();
+        let item: MockItem = MockItem::new(1).with_ok(1).with_err();
+        let recorder = cache.subscribe(&item);
+
+        cache.resolve(&item);
+        cache.cache.invalidate_key(&item);
+        assert!(cache.needs_fetch(&item));
+        cache.resolve(&item);
+
+        let value = cache.value(&item);
+        assert!(!value.valid());
+        assert_eq!(value.data().map(|data| **data), Some(1));
+        assert_eq!(recorder.count(), 3);
+        let inner = cache.cache.cache.lock().unwrap();
+        assert_eq!(inner.get(&item).unwrap().failures, 1);
+    }
+
+    #[test]
+    fn discards_value_on_failure() {
+        let cache: TestCache = Cache::with_options(CacheOptions {
+            keep_stale_on_failure: false,
+            ..Default::default()
+        })
+        .into();
+        let item: MockItem = MockItem::new(1).with_ok(1).with_err();
+        cache.subscribe(&item);
+        cache.resolve(&item);
+        cache.resolve(&item);
+        assert_eq!(cache.value(&item).data(), None);
+    }
+
+    #[test]
+    fn equal_value_not_rebroadcast() {
+        let cache = TestCache::<()>::new();
+        let item: MockItem = MockItem::new(1).with_ok(5).with_ok(5);
+        let recorder = cache.subscribe(&item);
+        cache.resolve(&item);
+        cache.resolve(&item);
+        assert_eq!(recorder.count(), 1);
+
+        cache.unsubscribe(&item, &recorder);
+        recorder.clear();
+        cache.cache.invalidate_key(&item);
+        assert_eq!(recorder.count(), 0);
+    }
+}
@@ -0,0 +1,223 @@
+//! Subscriptions to several heterogeneous items at once.
+use super::{use_cache, Cache, Subscriber};
+use crate::{CacheItem, RcValue};
+use yew::prelude::*;
+
+/// Tuple of cache items which can be subscribed to at once.
+///
+/// This is implemented for tuples of two to five [`CacheItem`]s, which may be of different types.
+pub trait CacheItems<M: 'static>: Clone + PartialEq + 'static {
+    /// Tuple of the values of the items.
+    type Values;
+
+    /// Subscribe to all items, fetching them if needed.
+    fn subscribe(&self, cache: &Cache<M>, subscriber: &Subscriber);
+
+    /// Unsubscribe from all items.
+    fn unsubscribe(&self, cache: &Cache<M>, subscriber: &Subscriber);
+
+    /// Current values of all items.
+    fn values(&self, cache: &Cache<M>) -> Self::Values;
+
+    /// Determine if all values have data.
+    fn ready(values: &Self::Values) -> bool;
+}
+
+macro_rules! impl_cache_items {
+    ($($item:ident $index:tt),+) => {
+        impl<M: 'static, $($item: CacheItem<M>),+> CacheItems<M> for ($($item,)+) {
+            type Values = ($(RcValue<$item::Value>,)+);
+
+            fn subscribe(&self, cache: &Cache<M>, subscriber: &Subscriber) {
+                $(cache.subscribe_with(&self.$index, subscriber, None, true);)+
+            }
+
+            fn unsubscribe(&self, cache: &Cache<M>, subscriber: &Subscriber) {
+                $(cache.unsubscribe(&self.$index, subscriber);)+
+            }
+
+            fn values(&self, cache: &Cache<M>) -> Self::Values {
+                ($(cache.value_of(&self.$index),)+)
+            }
+
+            fn ready(values: &Self::Values) -> bool {
+                $(values.$index.data().is_some())&&+
+            }
+        }
+    };
+}
+
+impl_cache_items!(A 0, B 1);
+impl_cache_items!(A 0, B 1, C 2);
+impl_cache_items!(A 0, B 1, C 2, D 3);
+impl_cache_items!(A 0, B 1, C 2, D 3, E 4);
+
+/// Subscribe to a tuple of items of different types.
+///
+/// Returns a tuple of the values, and whether all of them have data. When several values arrive
+/// at the same time, the component is only rerendered once.
+///
+/// ```rust
+/// # use wasm_cache::{yew::*, CacheItem, Invalidatable};
+/// # use yew::prelude::*;
+/// # #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// # struct User(u64);
+/// # impl Invalidatable<()> for User {}
+/// # #[async_trait::async_trait(?Send)]
+/// # impl CacheItem for User {
+/// #     type Value = String;
+/// #     type Error = std::fmt::Error;
+/// #     async fn send(&self) -> Result<Self::Value, Self::Error> {
+/// #         Ok("user".into())
+/// #     }
+/// # }
+/// # #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// # struct Posts(u64);
+/// # impl Invalidatable<()> for Posts {}
+/// # #[async_trait::async_trait(?Send)]
+/// # impl CacheItem for Posts {
+/// #     type Value = Vec<String>;
+/// #     type Error = std::fmt::Error;
+/// #     async fn send(&self) -> Result<Self::Value, Self::Error> {
+/// #         Ok(vec![])
+/// #     }
+/// # }
+/// #[function_component]
+/// fn Profile() -> Html {
+///     let ((user, posts), ready) = use_cached_all::<(), _>((User(1), Posts(1)));
+///     if !ready {
+///         return html! { {"Loading"} };
+///     }
+///     html! {
+///         <p>{user.data().unwrap()}{" wrote "}{posts.data().unwrap().len()}{" posts"}</p>
+///     }
+/// }
+/// ```
+#[hook]
+pub fn use_cached_all<M: 'static, T: CacheItems<M>>(items: T) -> (T::Values, bool) {
+    let cache = use_cache::<M>();
+    let update = use_force_update();
+    let subscriber = use_memo(
+        |_| Subscriber::Callback(Callback::from(move |_| update.force_update())),
+        (),
+    );
+
+    // unsubscribe from previous items when they change or the component is unmounted
+    {
+        let cache = cache.clone();
+        let subscriber = subscriber.clone();
+        use_effect_with_deps(
+            move |items: &T| {
+                let items = items.clone();
+                move || items.unsubscribe(&cache, &subscriber)
+            },
+            items.clone(),
+        );
+    }
+
+    {
+        let cache = cache.clone();
+        let items = items.clone();
+        use_effect(move || items.subscribe(&cache, &subscriber));
+    }
+
+    let values = items.values(&cache);
+    let ready = T::ready(&values);
+    (values, ready)
+}
+
+/// Subscribe to a tuple of items of different types, returning a tuple of their values.
+///
+/// Behaves like [`use_cached_all`], for components which handle the loading state of each value
+/// themselves.
+#[hook]
+pub fn use_cached_tuple<M: 'static, T: CacheItems<M>>(items: T) -> T::Values {
+    use_cached_all::<M, T>(items).0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        yew::{
+            tests::{cache_with, counter, subscribers, Failure, Key},
+            Entry,
+        },
+        Invalidatable,
+    };
+    use std::{any::Any, rc::Rc};
+
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Name(&'static str);
+
+    impl Invalidatable<()> for Name {}
+
+    #[async_trait::async_trait(?Send)]
+    impl CacheItem for Name {
+        type Value = String;
+        type Error = Failure;
+
+        async fn send(&self) -> Result<Self::Value, Self::Error> {
+            Ok(self.0.into())
+        }
+    }
+
+    fn cache_names(cache: &Cache, names: &[&'static str]) {
+        let mut cache = cache.cache.lock().unwrap();
+        for name in names {
+            cache.insert(
+                Name(name),
+                Entry {
+                    value: RcValue::new(Rc::new(name.to_string()) as Rc<dyn Any>),
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    #[test]
+    fn tuple_values() {
+        let cache = cache_with(&[1, 2, 3]);
+        cache_names(&cache, &["a", "b"]);
+
+        let values = (Key(1), Name("a")).values(&cache);
+        assert_eq!(values.0.data().map(|data| **data), Some(1));
+        assert_eq!(values.1.data().map(|data| data.as_str()), Some("a"));
+        assert!(<(Key, Name)>::ready(&values));
+
+        let values = (Key(1), Name("a"), Key(2)).values(&cache);
+        assert!(<(Key, Name, Key)>::ready(&values));
+
+        let values = (Key(1), Name("a"), Key(2), Name("b")).values(&cache);
+        assert!(<(Key, Name, Key, Name)>::ready(&values));
+
+        let values = (Key(1), Name("a"), Key(2), Name("b"), Key(3)).values(&cache);
+        assert_eq!(values.4.data().map(|data| **data), Some(3));
+        assert!(<(Key, Name, Key, Name, Key)>::ready(&values));
+
+        // missing entries are not ready
+        let values = (Key(1), Name("a"), Key(2), Name("c"), Key(3)).values(&cache);
+        assert!(values.3.data().is_none());
+        assert!(!<(Key, Name, Key, Name, Key)>::ready(&values));
+    }
+
+    #[test]
+    fn tuple_subscribe() {
+        let cache = cache_with(&[1, 2]);
+        cache_names(&cache, &["a"]);
+        let (subscriber, count) = counter();
+
+        let items = (Key(1), Name("a"), Key(2));
+        items.subscribe(&cache, &subscriber);
+        assert_eq!(subscribers(&cache, 1), 1);
+        assert_eq!(subscribers(&cache, 2), 1);
+
+        // the subscriber is notified once for all of the items
+        cache.invalidate_all();
+        assert_eq!(count.get(), 1);
+
+        items.unsubscribe(&cache, &subscriber);
+        assert_eq!(subscribers(&cache, 1), 0);
+        assert_eq!(subscribers(&cache, 2), 0);
+    }
+}
@@ -0,0 +1,254 @@
+//! Waiting for cached values outside of components.
+
+use super::{Cache, CacheItem, FetchCallbacks, Subscriber};
+use std::{
+    cell::RefCell,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+use yew::prelude::*;
+
+/// Shared state of a [`WaitFor`] and the callback resolving it.
+struct WaitState<V> {
+    value: Option<Rc<V>>,
+    waker: Option<Waker>,
+}
+
+/// Future returned by [`Cache::wait_for`].
+struct WaitFor<M: 'static, T: CacheItem<M>> {
+    cache: Cache<M>,
+    key: T,
+    /// Subscriber waiting for the value, until it has been received.
+    subscriber: Option<Subscriber>,
+    state: Rc<RefCell<WaitState<T::Value>>>,
+}
+
+impl<M: 'static, T: CacheItem<M>> Future for WaitFor<M, T> {
+    type Output = Rc<T::Value>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut state = this.state.borrow_mut();
+        match state.value.take() {
+            Some(value) => {
+                this.subscriber = None;
+                Poll::Ready(value)
+            }
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<M: 'static, T: CacheItem<M>> Unpin for WaitFor<M, T> {}
+
+impl<M: 'static, T: CacheItem<M>> Drop for WaitFor<M, T> {
+    fn drop(&mut self) {
+        if let Some(subscriber) = &self.subscriber {
+            self.cache.unsubscribe(&self.key, subscriber);
+        }
+    }
+}
+
+impl<M: 'static> Cache<M> {
+    /// Call the callback once with the value of this data, as soon as it is valid.
+    ///
+    /// If the value is already valid, the callback is called immediately. Otherwise the data is
+    /// subscribed to and fetched if needed, retrying failed fetches with the usual backoff, and
+    /// the subscription is removed once a fetch succeeds. The callback is called while the cache
+    /// is not locked, so it may use the cache.
+    pub fn subscribe_once<T: CacheItem<M>, F: FnOnce(Rc<T::Value>) + 'static>(
+        &self,
+        key: T,
+        callback: F,
+    ) {
+        self.subscribe_until_valid(key, callback);
+    }
+
+    /// Wait until the value of this data is valid.
+    ///
+    /// Subscribes to the data like [`Cache::subscribe_once`], so it is fetched if needed and the
+    /// future resolves once a fetch succeeds. Stale data is not returned. If the future is dropped
+    /// before it resolves, the subscription is removed. A fetch which is already running is not
+    /// cancelled, since its result is shared with other subscribers.
+    pub fn wait_for<T: CacheItem<M>>(&self, key: T) -> impl Future<Output = Rc<T::Value>> {
+        let state = Rc::new(RefCell::new(WaitState {
+            value: None,
+            waker: None,
+        }));
+        let resolve = state.clone();
+        let subscriber = self.subscribe_until_valid(key.clone(), move |value| {
+            let mut state = resolve.borrow_mut();
+            state.value = Some(value);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+        WaitFor {
+            cache: self.clone(),
+            key,
+            subscriber,
+            state,
+        }
+    }
+
+    /// Call the callback once the value of this data is valid, see [`Cache::subscribe_once`].
+    ///
+    /// Returns the subscriber which is waiting for the value, or `None` if the callback has
+    /// already been called.
+    pub(super) fn subscribe_until_valid<T: CacheItem<M>, F: FnOnce(Rc<T::Value>) + 'static>(
+        &self,
+        key: T,
+        callback: F,
+    ) -> Option<Subscriber> {
+        if let Some(value) = self.valid_value_of(&key) {
+            callback(value);
+            return None;
+        }
+
+        let subscriber = Subscriber::Callback(Callback::from(|_| {}));
+        let callback = Rc::new(RefCell::new(Some(callback)));
+        let cache = Rc::downgrade(&self.cache);
+        let on_success = {
+            let cache = cache.clone();
+            let (key, subscriber) = (key.clone(), subscriber.clone());
+            let callback = callback.clone();
+            Callback::from(move |value: Rc<T::Value>| {
+                if let Some(cache) = cache.upgrade() {
+                    Cache { cache }.unsubscribe(&key, &subscriber);
+                }
+                if let Some(callback) = callback.borrow_mut().take() {
+                    callback(value);
+                }
+            })
+        };
+        let on_error = {
+            let (key, subscriber) = (key.clone(), subscriber.clone());
+            Callback::from(move |_error| {
+                // resubscribing would remove this listener, so only retry the fetch
+                if let Some(cache) = cache.upgrade() {
+                    Cache { cache }.fetch_if_subscribed(&key, &subscriber);
+                }
+            })
+        };
+        let mut listener = FetchCallbacks {
+            on_success: Some(on_success),
+            on_error: Some(on_error),
+        }
+        .into_listener();
+        // any valid value resolves the wait, not only the result of a fetch
+        listener.on_value = listener.on_success.take();
+
+        self.subscribe_with(&key, &subscriber, None, true);
+        self.set_fetch_listener(&key, &subscriber, listener);
+
+        // the fetch may have completed before the listener was set
+        if let Some(value) = self.valid_value_of(&key) {
+            self.unsubscribe(&key, &subscriber);
+            if let Some(callback) = callback.borrow_mut().take() {
+                callback(value);
+            }
+            return None;
+        }
+        Some(subscriber)
+    }
+
+    /// Current value of this data, if it is valid.
+    fn valid_value_of<T: CacheItem<M>>(&self, key: &T) -> Option<Rc<T::Value>> {
+        let value = self.value_of(key);
+        value.valid().then(|| value.data().cloned()).flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::yew::{
+        tests::{cache_with, subscribers, Failure, Key},
+        Cache,
+    };
+    use futures::{executor::block_on, poll};
+    use std::{cell::Cell, rc::Rc, task::Poll};
+
+    #[test]
+    fn subscribe_once_valid_value() {
+        let cache = cache_with(&[1]);
+        let called = Rc::new(Cell::new(0));
+        let count = called.clone();
+        cache.subscribe_once(Key(1), move |value| count.set(*value));
+        assert_eq!(called.get(), 1);
+        assert_eq!(subscribers(&cache, 1), 0);
+    }
+
+    #[test]
+    fn subscribe_once_fires_once() {
+        let cache = Cache::<()>::default().with_spawn_fn(|_future| {});
+        let values = Rc::new(Cell::new(0));
+        let calls = Rc::new(Cell::new(0));
+        let (value, count) = (values.clone(), calls.clone());
+        let inner = cache.clone();
+        cache.subscribe_once(Key(1), move |data| {
+            // the cache is unlocked while calling back
+            assert!(inner.peek(&Key(1)).is_some());
+            value.set(*data);
+            count.set(count.get() + 1);
+        });
+        assert_eq!(subscribers(&cache, 1), 1);
+
+        // failures keep the subscription and refetch it
+        cache.failure(&Key(1), Failure);
+        assert_eq!(calls.get(), 0);
+        assert_eq!(subscribers(&cache, 1), 1);
+        assert!(cache.peek_entry(&Key(1)).unwrap().progress);
+
+        cache.cache(&Key(1), Rc::new(5));
+        cache.cache(&Key(1), Rc::new(6));
+        assert_eq!((values.get(), calls.get()), (5, 1));
+        assert_eq!(subscribers(&cache, 1), 0);
+    }
+
+    #[test]
+    fn subscribe_once_synchronous_fetch() {
+        let cache = Cache::<()>::default().with_spawn_fn(|future| {
+            futures::executor::block_on(future);
+        });
+        let called = Rc::new(Cell::new(0));
+        let count = called.clone();
+        cache.subscribe_once(Key(3), move |value| count.set(*value));
+        assert_eq!(called.get(), 3);
+        assert_eq!(subscribers(&cache, 3), 0);
+    }
+
+    #[test]
+    fn wait_for_valid_value() {
+        let cache = Cache::<()>::default().with_spawn_fn(|_future| {});
+        cache.seed(&Key(1), 1);
+        block_on(async {
+            // stale data does not resolve the future
+            let mut future = Box::pin(cache.wait_for(Key(1)));
+            assert_eq!(poll!(future.as_mut()), Poll::Pending);
+            assert_eq!(subscribers(&cache, 1), 1);
+
+            cache.cache(&Key(1), Rc::new(2));
+            assert_eq!(future.await, Rc::new(2));
+            assert_eq!(subscribers(&cache, 1), 0);
+
+            assert_eq!(cache.wait_for(Key(1)).await, Rc::new(2));
+        });
+    }
+
+    #[test]
+    fn wait_for_dropped_unsubscribes() {
+        let cache = Cache::<()>::default().with_spawn_fn(|_future| {});
+        let future = cache.wait_for(Key(1));
+        assert_eq!(subscribers(&cache, 1), 1);
+        drop(future);
+        assert_eq!(subscribers(&cache, 1), 0);
+
+        // no callback into the dropped future
+        cache.cache(&Key(1), Rc::new(1));
+    }
+}